@@ -0,0 +1,6 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Usa o protoc distribuído junto ao crate para não exigir instalação na máquina.
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    tonic_build::compile_protos("proto/updater.proto")?;
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use std::{collections::BTreeMap, future::Future, pin::Pin, sync::Mutex};
+use dialoguer::{Password, theme::ColorfulTheme};
+
+use crate::azure_cli::acquire_azure_cli_token;
+use crate::certificate::acquire_certificate_token;
+use crate::claims::token_expiry;
+use crate::config::{GrantType, Secrets};
+use crate::device_code::acquire_device_code_token;
+use crate::managed_identity::acquire_managed_identity_token;
+use crate::token::TokenResponse;
+use crate::ui::is_interactive;
+
+/// Senhas digitadas nesta execução, por usuário, quando o `secrets.toml` não guarda `password`.
+static TYPED_PASSWORDS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+pub type TokenFuture<'a> = Pin<Box<dyn Future<Output = Result<TokenResponse, String>> + Send + 'a>>;
+
+/// Forma de obter tokens de acesso. Cada `grant_type` tem a sua; um novo fluxo (ou um provedor fictício) entra
+/// implementando o trait e sendo escolhido em `provider`, sem mudar quem pede o token.
+pub trait AuthProvider: Send + Sync {
+    /// Gera um token para a audiência informada (PowerBI, Fabric, XMLA...).
+    fn token<'a>(&'a self, resource: &'a str) -> TokenFuture<'a>;
+}
+
+/// Provedor do fluxo configurado no `secrets.toml`.
+pub fn provider(secrets: &Secrets) -> Box<dyn AuthProvider> {
+    let secrets = secrets.clone();
+    match secrets.grant_type {
+        GrantType::Password => Box::new(PasswordProvider { secrets }),
+        GrantType::ClientCredentials => Box::new(ClientCredentialsProvider { secrets }),
+        GrantType::DeviceCode => Box::new(DeviceCodeProvider { secrets }),
+        GrantType::Certificate => Box::new(CertificateProvider { secrets }),
+        GrantType::AzureCli => Box::new(AzureCliProvider { secrets }),
+        GrantType::ManagedIdentity => Box::new(ManagedIdentityProvider { secrets }),
+        GrantType::StaticToken => Box::new(StaticTokenProvider { access_token: secrets.access_token.unwrap_or_default() }),
+    }
+}
+
+/// Solicita o token ao endpoint OAuth do tenant com os parâmetros do fluxo.
+async fn request_token(secrets: &Secrets, resource: &str, params: &[(&str, &str)]) -> Result<TokenResponse, String> {
+
+    // Service principals (client_credentials) precisam do tenant; contas de usuário usam o endpoint comum.
+    let tenant = secrets.tenant.as_deref().unwrap_or("common");
+    let url = format!("{}/{}/oauth2/token", secrets.authority(), tenant);
+
+    let mut form = vec![("client_id", secrets.client_id.as_str()), ("resource", resource)];
+    form.extend_from_slice(params);
+
+    let res = reqwest::Client::new()
+        .post(url)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| format!("Falha ao solicitar token.\nErro: {}", e))?;
+
+    if !res.status().is_success() {
+        return Err(res.text().await.unwrap_or_default());
+    }
+
+    res.json().await.map_err(|e| format!("Falha ao converter JSON.\nErro: {}", e))
+}
+
+/// Usuário e senha (ROPC).
+pub struct PasswordProvider {
+    secrets: Secrets,
+}
+
+impl PasswordProvider {
+
+    /// Senha do `secrets.toml` ou, sem ela, digitada no terminal uma vez por execução. Como o token e o
+    /// `refresh_token` ficam guardados, a senha só é pedida quando o acesso precisa ser refeito do zero.
+    fn password(&self) -> Result<String, String> {
+
+        if let Some(password) = &self.secrets.password {
+            return Ok(password.clone());
+        }
+
+        let username = self.secrets.username.clone().unwrap_or_default();
+        let mut typed = TYPED_PASSWORDS.lock().unwrap();
+
+        if let Some(password) = typed.get(&username) {
+            return Ok(password.clone());
+        }
+
+        if !is_interactive() {
+            return Err(format!("Senha de {} não informada no secrets.toml e sem terminal para pedi-la.", username));
+        }
+
+        let password = Password::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Senha de {}", username))
+            .interact()
+            .map_err(|e| e.to_string())?;
+
+        typed.insert(username, password.clone());
+        Ok(password)
+    }
+}
+
+impl AuthProvider for PasswordProvider {
+    fn token<'a>(&'a self, resource: &'a str) -> TokenFuture<'a> {
+        Box::pin(async move {
+            let password = self.password()?;
+            let username = self.secrets.username.as_deref().unwrap_or_default();
+
+            let result = request_token(&self.secrets, resource, &[("grant_type", "password"), ("username", username), ("password", &password)]).await;
+
+            // Uma senha digitada errada é pedida de novo na próxima tentativa.
+            if result.is_err() {
+                TYPED_PASSWORDS.lock().unwrap().remove(username);
+            }
+
+            result
+        })
+    }
+}
+
+/// Service principal com segredo.
+pub struct ClientCredentialsProvider {
+    secrets: Secrets,
+}
+
+impl AuthProvider for ClientCredentialsProvider {
+    fn token<'a>(&'a self, resource: &'a str) -> TokenFuture<'a> {
+        Box::pin(async move {
+            let client_secret = self.secrets.client_secret.as_deref().unwrap_or_default();
+            request_token(&self.secrets, resource, &[("grant_type", "client_credentials"), ("client_secret", client_secret)]).await
+        })
+    }
+}
+
+pub struct DeviceCodeProvider {
+    secrets: Secrets,
+}
+
+impl AuthProvider for DeviceCodeProvider {
+    fn token<'a>(&'a self, resource: &'a str) -> TokenFuture<'a> {
+        Box::pin(acquire_device_code_token(&self.secrets, resource))
+    }
+}
+
+pub struct CertificateProvider {
+    secrets: Secrets,
+}
+
+impl AuthProvider for CertificateProvider {
+    fn token<'a>(&'a self, resource: &'a str) -> TokenFuture<'a> {
+        Box::pin(acquire_certificate_token(&self.secrets, resource))
+    }
+}
+
+pub struct AzureCliProvider {
+    secrets: Secrets,
+}
+
+impl AuthProvider for AzureCliProvider {
+    fn token<'a>(&'a self, resource: &'a str) -> TokenFuture<'a> {
+        Box::pin(acquire_azure_cli_token(&self.secrets, resource))
+    }
+}
+
+pub struct ManagedIdentityProvider {
+    secrets: Secrets,
+}
+
+impl AuthProvider for ManagedIdentityProvider {
+    fn token<'a>(&'a self, resource: &'a str) -> TokenFuture<'a> {
+        Box::pin(acquire_managed_identity_token(&self.secrets, resource))
+    }
+}
+
+/// Token obtido fora do programa (`access_token`), usado como está até expirar; serve para testes e pipelines
+/// que já têm um token. Vale apenas para a audiência com que foi emitido.
+pub struct StaticTokenProvider {
+    access_token: String,
+}
+
+impl AuthProvider for StaticTokenProvider {
+    fn token<'a>(&'a self, _resource: &'a str) -> TokenFuture<'a> {
+        Box::pin(async move {
+            let expires_on = token_expiry(&self.access_token)?;
+
+            if expires_on <= chrono::Utc::now().timestamp() {
+                return Err("O access_token informado expirou; gere outro e atualize o secrets.toml.".to_string());
+            }
+
+            Ok(TokenResponse {
+                token_type: "Bearer".to_string(),
+                expires_on: expires_on.to_string(),
+                access_token: self.access_token.clone(),
+                refresh_token: None,
+            })
+        })
+    }
+}
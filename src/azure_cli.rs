@@ -0,0 +1,71 @@
+use chrono::{Local, NaiveDateTime, TimeZone};
+use serde::Deserialize;
+use tokio::process::Command;
+
+use crate::config::Secrets;
+use crate::token::TokenResponse;
+
+/// Saída de `az account get-access-token`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AzureCliToken {
+    access_token: String,
+    #[serde(default)]
+    token_type: Option<String>,
+    /// Timestamp Unix, presente a partir da versão 2.54 da Azure CLI.
+    #[serde(default, rename = "expires_on")]
+    expires_on: Option<i64>,
+    /// Data local (`2024-01-31 18:00:00.000000`), nas versões anteriores.
+    #[serde(default, rename = "expiresOn")]
+    expires_on_local: Option<String>,
+}
+
+/// No Windows a Azure CLI é um `az.cmd`, que só é encontrado pelo interpretador de comandos.
+fn az_command() -> Command {
+    if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "az"]);
+        command
+    } else {
+        Command::new("az")
+    }
+}
+
+/// Reaproveita o login da Azure CLI (`az login`): o token é emitido pela própria CLI para a audiência informada,
+/// sem `client_id` nem senha no `secrets.toml`. `tenant`, quando informado, escolhe o tenant do token.
+pub async fn acquire_azure_cli_token(secrets: &Secrets, resource: &str) -> Result<TokenResponse, String> {
+
+    let mut command = az_command();
+    command.args(["account", "get-access-token", "--output", "json", "--resource", resource]);
+    if let Some(tenant) = &secrets.tenant {
+        command.args(["--tenant", tenant]);
+    }
+
+    let output = command
+        .output()
+        .await
+        .map_err(|e| format!("Azure CLI não encontrada; instale-a ou configure o secrets.toml.\nErro: {}", e))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Falha ao obter token da Azure CLI (execute `az login`).\n{}", error.trim()));
+    }
+
+    let token: AzureCliToken = serde_json::from_slice(&output.stdout).map_err(|e| format!("Resposta inválida da Azure CLI.\nErro: {}", e))?;
+
+    let expires_on = token
+        .expires_on
+        .or_else(|| {
+            let local = token.expires_on_local.as_deref()?;
+            let naive = NaiveDateTime::parse_from_str(local, "%Y-%m-%d %H:%M:%S%.f").ok()?;
+            Local.from_local_datetime(&naive).earliest().map(|date| date.timestamp())
+        })
+        .ok_or("Validade do token da Azure CLI não informada.")?;
+
+    Ok(TokenResponse {
+        token_type: token.token_type.unwrap_or_else(|| "Bearer".to_string()),
+        expires_on: expires_on.to_string(),
+        access_token: token.access_token,
+        refresh_token: None,
+    })
+}
@@ -0,0 +1,96 @@
+use std::{fs, path::{Path, PathBuf}};
+use chrono::Local;
+use dialoguer::{Select, theme::ColorfulTheme};
+
+use crate::config::FILENAME_CONFIG_JSON;
+use crate::ui::require_interactive;
+
+/// Pasta com as cópias do `dataset.json` feitas antes de cada gravação.
+pub const BACKUP_DIR: &str = "backups";
+/// Quantidade de cópias mantidas; as mais antigas são apagadas.
+const BACKUP_LIMIT: usize = 30;
+
+/// Copia o `dataset.json` atual para `backups/dataset-AAAAMMDD-HHMMSS-mmm.json` antes de uma gravação. Os
+/// milissegundos evitam que duas gravações no mesmo segundo (ex.: restaurar logo após salvar) sobrescrevam a cópia.
+pub fn backup_config_file() -> Result<(), String> {
+
+    if !Path::new(FILENAME_CONFIG_JSON).exists() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(BACKUP_DIR).map_err(|e| format!("Falha ao criar pasta {}.\nErro: {}", BACKUP_DIR, e))?;
+
+    let target = Path::new(BACKUP_DIR).join(format!("dataset-{}.json", Local::now().format("%Y%m%d-%H%M%S-%3f")));
+    fs::copy(FILENAME_CONFIG_JSON, &target).map_err(|e| format!("Falha ao copiar {} para {}.\nErro: {}", FILENAME_CONFIG_JSON, target.display(), e))?;
+
+    for old in list_backups().into_iter().skip(BACKUP_LIMIT) {
+        let _ = fs::remove_file(old);
+    }
+
+    Ok(())
+}
+
+/// Cópias existentes, da mais recente para a mais antiga.
+pub fn list_backups() -> Vec<PathBuf> {
+
+    let mut backups: Vec<PathBuf> = match fs::read_dir(BACKUP_DIR) {
+        Ok(entries) => entries
+            .map_while(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with("dataset-") && name.ends_with(".json")))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    // O nome carrega a data, então a ordem alfabética é a cronológica.
+    backups.sort();
+    backups.reverse();
+    backups
+}
+
+/// Restaura uma cópia (a informada ou a escolhida na lista); o arquivo atual também é copiado antes.
+pub fn rollback(file: Option<&str>) -> Result<(), String> {
+
+    let backups = list_backups();
+
+    let source = match file {
+        Some(file) => {
+            let path = Path::new(file);
+            let path = if path.exists() { path.to_path_buf() } else { Path::new(BACKUP_DIR).join(file) };
+            if !path.exists() {
+                return Err(format!("Cópia {} não encontrada.", file));
+            }
+            path
+        }
+        None => {
+            if backups.is_empty() {
+                return Err(format!("Nenhuma cópia encontrada em {}.", BACKUP_DIR));
+            }
+
+            require_interactive("A escolha da cópia a restaurar");
+
+            let labels: Vec<String> = backups.iter().map(|path| path.display().to_string()).collect();
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Cópia a restaurar")
+                .default(0)
+                .items(&labels)
+                .interact_opt()
+                .map_err(|e| e.to_string())?;
+
+            match selection {
+                Some(index) => backups[index].clone(),
+                None => return Ok(()),
+            }
+        }
+    };
+
+    // Valida antes de substituir o arquivo em uso.
+    let content = fs::read_to_string(&source).map_err(|e| format!("Falha ao ler {}.\nErro: {}", source.display(), e))?;
+    serde_json::from_str::<serde_json::Value>(&content).map_err(|e| format!("Cópia {} inválida.\nErro: {}", source.display(), e))?;
+
+    backup_config_file()?;
+    fs::write(FILENAME_CONFIG_JSON, content).map_err(|e| format!("Falha ao gravar {}.\nErro: {}", FILENAME_CONFIG_JSON, e))?;
+    println!("{} restaurado a partir de {}.", FILENAME_CONFIG_JSON, source.display());
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::{collections::HashMap, fs, sync::{atomic::{AtomicBool, Ordering}, Mutex}};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const FILENAME_METADATA_CACHE_JSON: &str = ".metadata_cache.json";
+
+/// Validade das respostas de metadados (workspaces, datasets, relatórios) guardadas em disco.
+const METADATA_TTL_MINUTES: i64 = 60;
+
+/// Ignora as respostas guardadas nesta execução (`--refresh-cache`); as novas continuam sendo gravadas.
+static BYPASS: AtomicBool = AtomicBool::new(false);
+
+/// Serializa o acesso ao arquivo: perfis consultados em paralelo gravariam uns por cima dos outros.
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    fetched_at: DateTime<Utc>,
+    value: Value,
+}
+
+pub fn set_bypass(bypass: bool) {
+    BYPASS.store(bypass, Ordering::Relaxed);
+}
+
+fn read_cache() -> HashMap<String, CacheEntry> {
+    fs::read_to_string(FILENAME_METADATA_CACHE_JSON)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Chave da resposta no arquivo: a URL dentro do escopo (identidade do token) que a obteve.
+fn key(scope: &str, url: &str) -> String {
+    format!("{} {}", scope, url)
+}
+
+/// Resposta guardada para a URL no escopo, se ainda estiver dentro da validade.
+pub fn get(scope: &str, url: &str) -> Option<Value> {
+
+    if BYPASS.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let _lock = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    read_cache()
+        .remove(&key(scope, url))
+        .filter(|entry| Utc::now() - entry.fetched_at < Duration::minutes(METADATA_TTL_MINUTES))
+        .map(|entry| entry.value)
+}
+
+/// Guarda a resposta da URL no escopo; falhas de gravação apenas deixam de aproveitar o cache.
+pub fn put(scope: &str, url: &str, value: &Value) {
+
+    let _lock = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut cache = read_cache();
+    let now = Utc::now();
+
+    cache.retain(|_, entry| now - entry.fetched_at < Duration::minutes(METADATA_TTL_MINUTES));
+    cache.insert(key(scope, url), CacheEntry { fetched_at: now, value: value.clone() });
+
+    if let Err(e) = fs::write(FILENAME_METADATA_CACHE_JSON, serde_json::to_string(&cache).unwrap()) {
+        eprintln!("Falha ao gravar cache de metadados.\nErro: {}", e);
+    }
+}
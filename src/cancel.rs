@@ -0,0 +1,109 @@
+use colored::Colorize;
+use dialoguer::{Select, theme::ColorfulTheme};
+
+use crate::config::DatasetConfig;
+use crate::locale;
+use crate::monitor::STATUS_IN_PROGRESS;
+use crate::powerbi::{cancel_refresh, list_refreshes};
+use crate::token::TokenResponse;
+use crate::ui::require_interactive;
+
+/// Atualizações recentes consultadas em cada dataset à procura das que estão em andamento.
+const RECENT_REFRESHES: u32 = 5;
+
+/// Cancela as atualizações em andamento dos datasets, por exemplo para desfazer a publicação de uma origem com
+/// problema no meio da carga. Devolve se todos os cancelamentos foram aceitos.
+pub async fn cancel_in_progress(targets: &[(u32, DatasetConfig)], token: &TokenResponse) -> bool {
+
+    let mut cancelled = 0;
+    let mut ok = true;
+
+    for (company, dataset) in targets {
+
+        let refreshes = match list_refreshes(&dataset.id, RECENT_REFRESHES, token).await {
+            Ok(refreshes) => refreshes,
+            Err(e) => {
+                eprintln!("Falha ao consultar atualizações do dataset {} (empresa {}).\n{}", dataset.id, company, e);
+                ok = false;
+                continue;
+            }
+        };
+
+        for refresh in refreshes.iter().filter(|refresh| refresh.status == STATUS_IN_PROGRESS) {
+
+            let Some(request_id) = &refresh.request_id else {
+                eprintln!("Dataset {} (empresa {}): atualização em andamento sem identificador; cancele-a pelo portal.", dataset.id, company);
+                ok = false;
+                continue;
+            };
+
+            match cancel_refresh(&dataset.id, request_id, token).await {
+                Ok(_) => {
+                    cancelled += 1;
+                    println!("Dataset {} (empresa {}): {}", dataset.id, company, "cancelada".yellow());
+                }
+                Err(e) => {
+                    ok = false;
+                    eprintln!("Dataset {} (empresa {}): falha ao cancelar.\n{}", dataset.id, company, e);
+                }
+            }
+        }
+    }
+
+    println!("{} atualização(ões) cancelada(s) em {} dataset(s) consultado(s).", cancelled, targets.len());
+    ok
+}
+
+/// Cancela uma atualização em andamento do dataset, por exemplo a que está travando a fila da capacidade. Sem
+/// `request_id`, lista as atualizações em andamento e pergunta qual cancelar.
+pub async fn cancel_one(dataset_id: &str, request_id: Option<String>, token: &TokenResponse) -> Result<(), String> {
+
+    let running: Vec<_> = list_refreshes(dataset_id, RECENT_REFRESHES, token)
+        .await?
+        .into_iter()
+        .filter(|refresh| refresh.status == STATUS_IN_PROGRESS)
+        .collect();
+
+    if running.is_empty() {
+        println!("Nenhuma atualização em andamento no dataset {}.", dataset_id);
+        return Ok(());
+    }
+
+    let request_id = match request_id {
+        Some(request_id) if running.iter().any(|refresh| refresh.request_id.as_deref() == Some(request_id.as_str())) => request_id,
+        Some(request_id) => return Err(format!("A atualização {} não está em andamento no dataset {}.", request_id, dataset_id)),
+        None => {
+            require_interactive("A escolha da atualização a cancelar");
+
+            let labels: Vec<String> = running
+                .iter()
+                .map(|refresh| format!(
+                    "{} (início {}{})",
+                    refresh.request_id.as_deref().unwrap_or("sem identificador"),
+                    refresh.start_time.map(locale::datetime).unwrap_or_else(|| "-".to_string()),
+                    refresh.extended_status.as_deref().map(|status| format!(", {}", status)).unwrap_or_default(),
+                ))
+                .collect();
+
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Atualização a cancelar")
+                .default(0)
+                .items(&labels)
+                .interact_opt()
+                .map_err(|e| e.to_string())?;
+
+            let Some(index) = selection else {
+                return Ok(());
+            };
+
+            running[index]
+                .request_id
+                .clone()
+                .ok_or("Atualização em andamento sem identificador; cancele-a pelo portal.")?
+        }
+    };
+
+    cancel_refresh(dataset_id, &request_id, token).await?;
+    println!("Atualização {} do dataset {}: {}", request_id, dataset_id, "cancelada".yellow());
+    Ok(())
+}
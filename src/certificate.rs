@@ -0,0 +1,101 @@
+use std::fs;
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine};
+use chrono::{Duration, Utc};
+use rsa::{
+    pkcs1::DecodeRsaPrivateKey,
+    pkcs1v15::SigningKey,
+    pkcs8::DecodePrivateKey,
+    signature::{SignatureEncoding, Signer},
+    RsaPrivateKey,
+};
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::config::Secrets;
+use crate::token::TokenResponse;
+
+/// Validade da asserção enviada ao Azure AD.
+const ASSERTION_MINUTES: i64 = 10;
+
+/// Primeiro bloco PEM com o rótulo informado, já decodificado.
+fn pem_block(content: &str, label: &str) -> Option<Vec<u8>> {
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+
+    let start = content.find(&begin)? + begin.len();
+    let stop = start + content[start..].find(&end)?;
+    let body: String = content[start..stop].chars().filter(|c| !c.is_whitespace()).collect();
+
+    STANDARD.decode(body).ok()
+}
+
+/// Chave privada (PKCS#8 ou PKCS#1, sem senha) e impressão digital SHA-1 do certificado do arquivo PEM.
+fn read_certificate(path: &str) -> Result<(RsaPrivateKey, Vec<u8>), String> {
+
+    let content = fs::read_to_string(path).map_err(|e| format!("Falha ao ler certificado {}.\nErro: {}", path, e))?;
+
+    let certificate = pem_block(&content, "CERTIFICATE")
+        .ok_or_else(|| format!("Certificado {} sem bloco CERTIFICATE.", path))?;
+
+    let key = if let Some(der) = pem_block(&content, "PRIVATE KEY") {
+        RsaPrivateKey::from_pkcs8_der(&der).map_err(|e| format!("Chave privada inválida em {}.\nErro: {}", path, e))?
+    } else if let Some(der) = pem_block(&content, "RSA PRIVATE KEY") {
+        RsaPrivateKey::from_pkcs1_der(&der).map_err(|e| format!("Chave privada inválida em {}.\nErro: {}", path, e))?
+    } else {
+        return Err(format!("Certificado {} sem chave privada RSA sem senha (PRIVATE KEY ou RSA PRIVATE KEY).", path));
+    };
+
+    Ok((key, Sha1::digest(&certificate).to_vec()))
+}
+
+/// JWT assinado com a chave do certificado (RS256), usado no lugar do `client_secret`.
+fn client_assertion(secrets: &Secrets, audience: &str, path: &str) -> Result<String, String> {
+
+    let (key, thumbprint) = read_certificate(path)?;
+    let now = Utc::now();
+
+    let header = json!({ "alg": "RS256", "typ": "JWT", "x5t": URL_SAFE_NO_PAD.encode(thumbprint) });
+    let claims = json!({
+        "aud": audience,
+        "iss": secrets.client_id,
+        "sub": secrets.client_id,
+        "jti": format!("{:x}", now.timestamp_nanos_opt().unwrap_or_default()),
+        "nbf": now.timestamp(),
+        "exp": (now + Duration::minutes(ASSERTION_MINUTES)).timestamp(),
+    });
+
+    let payload = format!("{}.{}", URL_SAFE_NO_PAD.encode(header.to_string()), URL_SAFE_NO_PAD.encode(claims.to_string()));
+    let signature = SigningKey::<Sha256>::new(key).sign(payload.as_bytes());
+
+    Ok(format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(signature.to_bytes())))
+}
+
+/// Gera um token de service principal autenticado por certificado (`client_credentials` com `client_assertion`).
+pub async fn acquire_certificate_token(secrets: &Secrets, resource: &str) -> Result<TokenResponse, String> {
+
+    let tenant = secrets.tenant.as_deref().unwrap_or("common");
+    let path = secrets.certificate.as_deref().unwrap_or_default();
+    let url = format!("{}/{}/oauth2/token", secrets.authority(), tenant);
+
+    let assertion = client_assertion(secrets, &url, path)?;
+
+    let res = reqwest::Client::new()
+        .post(&url)
+        .form(&[
+            ("client_id", secrets.client_id.as_str()),
+            ("grant_type", "client_credentials"),
+            ("resource", resource),
+            ("client_assertion_type", "urn:ietf:params:oauth:client-assertion-type:jwt-bearer"),
+            ("client_assertion", assertion.as_str()),
+        ])
+        .send()
+        .await
+        .map_err(|e| format!("Falha ao solicitar token.\nErro: {}", e))?;
+
+    if res.status().is_success() {
+        res.json().await.map_err(|e| format!("Falha ao converter JSON.\nErro: {}", e))
+    } else {
+        Err(res.text().await.unwrap_or_default())
+    }
+}
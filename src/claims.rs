@@ -0,0 +1,173 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::Secrets;
+use crate::locale;
+use crate::profiles::profile_name;
+use crate::token::{default_resource, TokenResponse};
+
+/// Identificador do serviço do PowerBI, usado em `aud` no lugar da URL em alguns tokens.
+const POWER_BI_APP_ID: &str = "00000009-0000-0000-c000-000000000000";
+/// Permissões que autorizam a solicitação de atualização. `user_impersonation` é a da Azure CLI.
+const REFRESH_PERMISSIONS: [&str; 3] = ["Dataset.ReadWrite.All", "Tenant.ReadWrite.All", "user_impersonation"];
+
+/// Audiência: texto ou lista, conforme o emissor.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Declarações do token de acesso relevantes para as chamadas à API.
+#[derive(Debug, Deserialize)]
+struct Claims {
+    #[serde(default)]
+    aud: Option<Audience>,
+    /// Permissões delegadas (fluxos de usuário), separadas por espaço.
+    #[serde(default)]
+    scp: Option<String>,
+    /// Permissões de aplicativo (service principals).
+    #[serde(default)]
+    roles: Vec<String>,
+    #[serde(default)]
+    exp: Option<i64>,
+    /// Conta do usuário (`upn`, ou `unique_name` em contas convidadas e tokens v1).
+    #[serde(default)]
+    upn: Option<String>,
+    #[serde(default)]
+    unique_name: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    /// Aplicativo que obteve o token; identifica o service principal quando não há usuário.
+    #[serde(default)]
+    appid: Option<String>,
+    #[serde(default)]
+    app_displayname: Option<String>,
+    #[serde(default)]
+    tid: Option<String>,
+}
+
+fn audiences(aud: Option<Audience>) -> Vec<String> {
+    match aud {
+        Some(Audience::One(aud)) => vec![aud],
+        Some(Audience::Many(auds)) => auds,
+        None => Vec::new(),
+    }
+}
+
+fn decode_claims(token: &str) -> Result<Claims, String> {
+
+    let payload = token.split('.').nth(1).ok_or("Token em formato inesperado (não é um JWT).")?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|e| format!("Falha ao decodificar o token.\nErro: {}", e))?;
+
+    serde_json::from_slice(&payload).map_err(|e| format!("Falha ao ler as declarações do token.\nErro: {}", e))
+}
+
+/// Validade (`exp`, timestamp Unix) de um token obtido fora do programa.
+pub fn token_expiry(access_token: &str) -> Result<i64, String> {
+    decode_claims(access_token)?.exp.ok_or_else(|| "O access_token não informa a validade (exp).".to_string())
+}
+
+/// Problemas do token que fariam todas as atualizações falharem com 403: audiência de outro recurso, falta da
+/// permissão `Dataset.ReadWrite.All` ou validade já vencida. Service principals sem `roles` são autorizados pelos
+/// workspaces, então só a audiência e a validade são conferidas.
+fn token_problems(secrets: &Secrets, token: &TokenResponse) -> Vec<String> {
+
+    let claims = match decode_claims(&token.access_token) {
+        Ok(claims) => claims,
+        Err(e) => return vec![e],
+    };
+
+    let mut problems = Vec::new();
+
+    let resource = default_resource(secrets).trim_end_matches('/');
+    let audiences = audiences(claims.aud);
+    if !audiences.iter().any(|aud| aud.trim_end_matches('/') == resource || aud == POWER_BI_APP_ID) {
+        problems.push(format!("O token foi emitido para {} e não para {}; confira `resource` e `cloud` no secrets.toml.", audiences.join(", "), resource));
+    }
+
+    let scopes: Vec<&str> = claims.scp.as_deref().unwrap_or_default().split_whitespace().collect();
+    let permissions: Vec<&str> = scopes.iter().copied().chain(claims.roles.iter().map(String::as_str)).collect();
+    if !permissions.is_empty() && !permissions.iter().any(|permission| REFRESH_PERMISSIONS.contains(permission)) {
+        problems.push(format!(
+            "O token não tem a permissão Dataset.ReadWrite.All (tem: {}); conceda-a ao aplicativo no Azure AD.",
+            permissions.join(", ")
+        ));
+    }
+
+    if let Some(expires) = claims.exp.and_then(|exp| DateTime::from_timestamp(exp, 0)).filter(|expires| *expires < Utc::now()) {
+        problems.push(format!("O token expirou em {}; confira o relógio da máquina.", locale::datetime(expires)));
+    }
+
+    problems
+}
+
+/// Identidade do token (tenant, aplicativo e conta) resumida em um hash, que separa o cache de metadados entre perfis.
+/// Tokens que não são JWT usam o próprio token, sem aproveitar o cache depois de renovados.
+pub fn cache_scope(token: &TokenResponse) -> String {
+
+    let identity = match decode_claims(&token.access_token) {
+        Ok(claims) => format!(
+            "{}/{}/{}",
+            claims.tid.unwrap_or_default(),
+            claims.appid.unwrap_or_default(),
+            claims.upn.or(claims.unique_name).unwrap_or_default()
+        ),
+        Err(_) => token.access_token.clone(),
+    };
+
+    format!("{:x}", Sha256::digest(identity.as_bytes()))
+}
+
+/// Exibe quem fará as atualizações com o token atual: conta ou aplicativo, tenant, permissões e validade.
+pub fn print_identity(secrets: &Secrets, token: &TokenResponse) -> Result<(), String> {
+
+    let claims = decode_claims(&token.access_token)?;
+
+    println!("Perfil: {} ({})", profile_name(secrets), secrets.grant_type.as_str());
+
+    match claims.upn.or(claims.unique_name) {
+        Some(account) => match claims.name {
+            Some(name) => println!("Conta: {} ({})", account, name),
+            None => println!("Conta: {}", account),
+        },
+        None => println!("Conta: nenhuma (token de aplicativo)"),
+    }
+
+    if let Some(appid) = claims.appid {
+        match claims.app_displayname {
+            Some(name) => println!("Aplicativo: {} ({})", appid, name),
+            None => println!("Aplicativo: {}", appid),
+        }
+    }
+
+    println!("Tenant: {}", claims.tid.as_deref().unwrap_or("-"));
+
+    let audiences = audiences(claims.aud);
+    println!("Audiência: {}", if audiences.is_empty() { "-".to_string() } else { audiences.join(", ") });
+
+    let mut permissions: Vec<String> = claims.scp.as_deref().unwrap_or_default().split_whitespace().map(str::to_string).collect();
+    permissions.extend(claims.roles);
+    println!("Permissões: {}", if permissions.is_empty() { "-".to_string() } else { permissions.join(", ") });
+
+    match claims.exp.and_then(|exp| DateTime::from_timestamp(exp, 0)) {
+        Some(expires) if expires > Utc::now() => println!("Válido até: {}", locale::datetime(expires)),
+        Some(expires) => println!("{}", format!("Expirado em: {}", locale::datetime(expires)).red()),
+        None => println!("Validade: desconhecida"),
+    }
+
+    Ok(())
+}
+
+/// Exibe os problemas do token logo após obtê-lo, antes de a primeira atualização falhar com um 403 sem detalhes.
+pub fn warn_token_problems(secrets: &Secrets, token: &TokenResponse) {
+    for problem in token_problems(secrets, token) {
+        eprintln!("{}", format!("Atenção: {}", problem).yellow());
+    }
+}
@@ -0,0 +1,1300 @@
+use std::{fs::{self, File}, io::{self, ErrorKind, IsTerminal, Read}, path::Path, process::exit, collections::{BTreeMap, HashMap}, env, sync::atomic::{AtomicBool, Ordering}, time::Duration};
+use chrono::{NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+use config::{Config, Environment, File as ConfigFile, FileFormat, Value as ConfigValue};
+use colored::Colorize;
+use dialoguer::{Confirm, theme::ColorfulTheme};
+
+use crate::backup::backup_config_file;
+use crate::history_store::{self, HistoryBackend};
+use crate::links;
+use crate::locale::Locale;
+use crate::monitor::WaitOptions;
+use crate::notify;
+use crate::server;
+use crate::sops;
+use crate::state;
+use crate::ui::pause;
+use crate::vault::{resolve_vault, VAULT_KEYS};
+
+pub const FILENAME_CONFIG_JSON: &str = "dataset.json";
+pub const FILENAME_SECRETS_TOML: &str = "secrets.toml";
+/// Valor de `workspace` para o "Meu workspace" da conta, que service principals não acessam.
+pub const MY_WORKSPACE: &str = "my";
+
+/// Datasets informados por variável de ambiente, substituindo o `dataset.json`.
+/// Aceita `12:guid1,guid2;13:guid3` ou o mesmo JSON do arquivo.
+const ENV_DATASETS: &str = "PBI_DATASETS";
+/// Prefixo das variáveis de ambiente de segredos (ex.: `PBI_CLIENT_ID`).
+const ENV_SECRETS_PREFIX: &str = "PBI";
+/// Pasta com os arquivos de configuração, token e histórico, no lugar da pasta de execução.
+const ENV_HOME: &str = "PBI_HOME";
+/// Variáveis `PBI_*` que não são segredos: têm o mesmo prefixo, mas são lidas por outras partes do programa e
+/// ficam fora da leitura do `secrets.toml`. Toda nova variável `PBI_*` que não seja segredo entra aqui.
+const NON_SECRET_ENV: &[&str] = &[
+    ENV_DATASETS, ENV_HOME, links::ENV_LINKS_KEY, server::ENV_DASHBOARD_KEY, history_store::ENV_HISTORY_KEY,
+    state::ENV_STATE_PASSPHRASE, notify::ENV_ANNOTATIONS_TOKEN,
+];
+
+/// Modo somente leitura (`--read-only` ou `"read_only": true`): atualizações e alterações ficam bloqueadas.
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+pub fn set_read_only() {
+    READ_ONLY.store(true, Ordering::Relaxed);
+}
+
+pub fn is_read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+/// Erro a devolver quando a ação altera algo e o modo somente leitura está ativo.
+pub fn ensure_writable(action: &str) -> Result<(), String> {
+    if is_read_only() {
+        return Err(format!("{} não é permitida no modo somente leitura.", action));
+    }
+    Ok(())
+}
+
+/// Conteúdo do `dataset.json`: a lista de empresas ou um objeto com a lista e opções gerais.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AppConfig {
+    pub companies: Vec<GuidEntry>,
+    /// Máximo de atualizações simultâneas por capacidade, ex.: `{ "P1-prod": 2 }`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub capacities: HashMap<String, usize>,
+    /// Idioma das datas, durações e números exibidos (`pt-BR` ou `en-US`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<Locale>,
+    /// Fuso de exibição e dos horários sem fuso próprio, ex.: `"America/Sao_Paulo"`. Padrão: fuso do sistema.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<Tz>,
+    /// Horários (`HH:MM`) de atualização diária de todas as empresas no `serve`, quando `--at` e `--interval` não são informados.
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "schedule_format")]
+    pub schedule: Vec<NaiveTime>,
+    /// Webhooks (Teams, Slack...) avisados das falhas das empresas sem destinatários próprios.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify: Vec<String>,
+    /// Jobs nomeados, executados com `refresh --job <nome>`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub jobs: BTreeMap<String, JobConfig>,
+    /// Antecedência, em minutos, com que o token é renovado antes de expirar. Padrão: 5.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_skew_minutes: Option<i64>,
+    /// Onde o histórico é guardado: `file` (padrão), `sqlite` ou o `serve` central (`http`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<HistoryBackend>,
+    /// Modo somente leitura, para auditoria e suporte: apenas listas, status e histórico.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub read_only: bool,
+    /// Novas tentativas do envio em falhas transitórias. Padrão: 3 tentativas, a partir de 5 segundos.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+    /// Intervalo mínimo, em minutos, entre atualizações bem-sucedidas de um mesmo dataset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_interval_minutes: Option<u64>,
+    /// Segundos que o "Pressione ENTER" aguarda antes de continuar sozinho. Sem ele, aguarda indefinidamente.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_timeout_seconds: Option<u64>,
+    /// Máximo de envios simultâneos de datasets sem capacidade com limite. Padrão: 1 (um por vez).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_parallel: Option<usize>,
+    /// Anotações enviadas a um painel de monitoramento (Grafana ou API própria) ao fim de cada atualização.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Annotations>,
+}
+
+/// Conjunto nomeado de empresas, acompanhamento e notificação (ex.: `noturno`, `fechamento-mensal`),
+/// para que agendadores referenciem um nome estável em vez de uma lista de IDs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct JobConfig {
+    /// Empresas do job; vazio atualiza todas.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub companies: Vec<u32>,
+    /// Acompanha as atualizações até o fim, como `--wait`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait: Option<ManifestWait>,
+    /// URL que recebe o resultado final dos datasets do job que não tenham callback próprio.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback: Option<String>,
+    /// Etapas executadas em sequência; cada uma só começa quando a anterior termina com sucesso.
+    /// Com etapas, `companies` é ignorado.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<JobStage>,
+}
+
+/// Etapa de um job: dataflows, empresas e datasets atualizados juntos, com limite de simultaneidade.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct JobStage {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dataflows: Vec<DataflowRef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub companies: Vec<u32>,
+    /// GUIDs de datasets configurados, além dos das empresas da etapa.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub datasets: Vec<String>,
+    /// Máximo de atualizações simultâneas na etapa; sem limite quando omitido.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+}
+
+/// Dataflow e o workspace em que está publicado.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DataflowRef {
+    pub id: String,
+    pub workspace: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GuidEntry {
+    pub id: u32,
+    #[serde(default, deserialize_with = "deserialize_datasets", serialize_with = "serialize_datasets")]
+    pub guid: Vec<DatasetConfig>,
+    /// Padrões (glob) de assunto de eventos do Event Grid que disparam a atualização da empresa.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triggers: Vec<String>,
+    /// Capacidade em que os datasets da empresa estão hospedados.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<String>,
+    /// URL que recebe o resultado final de cada dataset da empresa.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback: Option<String>,
+    /// Empresa favorita, atualizada pela ação "Atualizar favoritas" e por `refresh --favorites`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub favorite: bool,
+    /// Fuso dos horários (`deadline`) dos datasets da empresa, ex.: `"America/Manaus"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<Tz>,
+    /// Perfil de credenciais (tabela do `secrets.toml`) usado pela empresa; sem ele, vale o perfil padrão.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Workspace dos datasets da empresa: `"my"` (Meu workspace) ou o GUID do workspace. Sem ele, os endereços
+    /// `myorg` localizam o dataset pela conta, o que não funciona com service principals.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    /// Webhooks avisados das falhas dos datasets da empresa, no lugar dos gerais.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify: Vec<String>,
+    /// Empresa desativada: seus datasets são ignorados (e registrados como tal) até ser reativada.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+}
+
+/// Dataset configurado. No `dataset.json` pode ser apenas o GUID ou um objeto com opções.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DatasetConfig {
+    pub id: String,
+    /// Nome do dataset no PowerBI, apenas informativo; usado pelo `config diff` para apontar renomeações.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Apelido compartilhado por datasets de várias empresas (ex.: `"vendas"`), atualizados juntos por `refresh --alias`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// Etiquetas livres (ex.: `"noturno"`) para agir sobre vários datasets de uma vez, como em `cancel --tag`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Horário (local) até o qual a atualização precisa estar concluída, ex.: `"07:00"`.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "deadline_format")]
+    pub deadline: Option<NaiveTime>,
+    /// Fuso do `deadline`, quando diferente do fuso da empresa.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<Tz>,
+    /// Capacidade do dataset, quando diferente da capacidade da empresa.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<String>,
+    /// Workspace do dataset, quando diferente do workspace da empresa.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    /// URL que recebe um POST com o resultado final da atualização.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback: Option<String>,
+    /// Webhooks avisados das falhas do dataset, no lugar dos da empresa.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify: Vec<String>,
+    /// Consultas DAX executadas após a conclusão para aquecer o cache dos relatórios.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warmup: Vec<String>,
+    /// Conjuntos de parâmetros; o dataset é atualizado uma vez para cada um, em sequência.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matrix: Vec<BTreeMap<String, String>>,
+    /// Consulta de validação executada após a conclusão, comparada com o valor do dia anterior.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation: Option<Validation>,
+    /// Marca d'água da origem; o dataset só é enviado quando ela mudou desde a última atualização bem-sucedida.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Watermark>,
+    /// Minutos após uma atualização bem-sucedida em que o dataset não é enviado de novo (ex.: ao repetir a execução
+    /// depois de uma falha parcial), poupando a cota diária. Sem ele, vale o `min_interval_minutes` geral.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_interval_minutes: Option<u64>,
+    /// Formato da solicitação de atualização: `simple` (padrão), `enhanced` ou `auto`.
+    #[serde(default, skip_serializing_if = "RefreshMode::is_simple")]
+    pub refresh_mode: RefreshMode,
+    /// Opções da atualização aprimorada, enviadas quando ela é usada.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_options: Option<RefreshOptions>,
+    /// Tabelas e partições atualizadas, no lugar do modelo inteiro (atualização aprimorada).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub objects: Vec<RefreshObject>,
+    /// Dataset desativado (ex.: origem fora do ar); é ignorado até ser reativado.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    /// Motivo da atualização manual (`refresh --note`, menu); não faz parte do arquivo.
+    #[serde(skip)]
+    pub note: Option<String>,
+}
+
+/// Formato da solicitação de atualização. A atualização aprimorada (com corpo) só é aceita em workspaces de
+/// capacidade dedicada (Premium, PPU, Fabric); `auto` consulta o workspace do dataset e escolhe o formato.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RefreshMode {
+    #[default]
+    Simple,
+    Enhanced,
+    Auto,
+}
+
+impl RefreshMode {
+    fn is_simple(&self) -> bool {
+        *self == RefreshMode::Simple
+    }
+}
+
+/// Tipo de processamento da atualização aprimorada.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum RefreshType {
+    #[default]
+    Full,
+    ClearValues,
+    Calculate,
+    DataOnly,
+    Automatic,
+    Defragment,
+}
+
+/// Confirmação das alterações: tudo de uma vez (`transactional`) ou em lotes (`partialBatch`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CommitMode {
+    Transactional,
+    PartialBatch,
+}
+
+/// Tabela (e, opcionalmente, uma partição dela) processada pela atualização aprimorada.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RefreshObject {
+    pub table: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+}
+
+/// Corpo da atualização aprimorada; as opções omitidas ficam com o padrão do serviço.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RefreshOptions {
+    #[serde(rename = "type", default)]
+    pub refresh_type: RefreshType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_mode: Option<CommitMode>,
+    /// Máximo de objetos processados em paralelo.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_parallelism: Option<u32>,
+    /// Novas tentativas do serviço antes de considerar a atualização falha.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_count: Option<u32>,
+    /// Aplica a política de atualização incremental das tabelas que a têm.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apply_refresh_policy: Option<bool>,
+}
+
+impl RefreshOptions {
+
+    /// Corpo da solicitação, com os nomes da API.
+    pub fn body(&self) -> Value {
+
+        let mut body = json!({ "type": self.refresh_type });
+
+        if let Some(commit_mode) = self.commit_mode {
+            body["commitMode"] = json!(commit_mode);
+        }
+        if let Some(max_parallelism) = self.max_parallelism {
+            body["maxParallelism"] = json!(max_parallelism);
+        }
+        if let Some(retry_count) = self.retry_count {
+            body["retryCount"] = json!(retry_count);
+        }
+        if let Some(apply_refresh_policy) = self.apply_refresh_policy {
+            body["applyRefreshPolicy"] = json!(apply_refresh_policy);
+        }
+
+        body
+    }
+}
+
+/// Novas tentativas do envio em falhas transitórias (sem resposta ou erro 5xx). Erros permanentes, como 404, não são
+/// repetidos. A espera dobra a cada tentativa, com uma variação aleatória para que execuções paralelas não repitam juntas.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Total de tentativas, incluindo a primeira.
+    #[serde(default = "default_retry_attempts")]
+    pub attempts: u32,
+    /// Espera, em segundos, antes da segunda tentativa.
+    #[serde(default = "default_retry_base_delay")]
+    pub base_delay_seconds: u64,
+    /// Espera máxima, em segundos, entre tentativas.
+    #[serde(default = "default_retry_max_delay")]
+    pub max_delay_seconds: u64,
+}
+
+fn default_retry_attempts() -> u32 {
+    3
+}
+
+fn default_retry_base_delay() -> u64 {
+    5
+}
+
+fn default_retry_max_delay() -> u64 {
+    120
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: default_retry_attempts(),
+            base_delay_seconds: default_retry_base_delay(),
+            max_delay_seconds: default_retry_max_delay(),
+        }
+    }
+}
+
+impl RetryPolicy {
+
+    /// Espera após a tentativa `attempt` (a partir de 1): entre metade e o total de `base * 2^(attempt - 1)`, limitado
+    /// a `max_delay_seconds`.
+    pub fn delay(&self, attempt: u32) -> Duration {
+
+        let millis = (self.base_delay_seconds * 1000)
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay_seconds * 1000);
+
+        // A variação vem do relógio, suficiente para espalhar as tentativas de execuções paralelas.
+        let jitter = u64::from(Utc::now().timestamp_subsec_nanos()) % (millis / 2 + 1);
+
+        Duration::from_millis(millis / 2 + jitter)
+    }
+}
+
+/// Destino das anotações com o início, o fim e o resultado de cada atualização, para marcar as janelas de carga nos
+/// painéis das origens. O token, quando exigido, vem de `PBI_ANNOTATIONS_TOKEN`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotations {
+    /// Endereço que recebe as anotações, ex.: `https://grafana.exemplo.com/api/annotations`.
+    pub url: String,
+    /// Formato do corpo: `grafana` (padrão) ou `generic`.
+    #[serde(default, skip_serializing_if = "AnnotationFormat::is_grafana")]
+    pub format: AnnotationFormat,
+    /// Tags acrescentadas às de empresa, dataset e resultado.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Painel do Grafana em que a anotação aparece; sem ele, a anotação é da organização.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dashboard_uid: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnotationFormat {
+    #[default]
+    Grafana,
+    Generic,
+}
+
+impl AnnotationFormat {
+    fn is_grafana(&self) -> bool {
+        *self == AnnotationFormat::Grafana
+    }
+}
+
+/// Consulta DAX que devolve um número (ex.: quantidade de linhas) e a queda máxima tolerada.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Validation {
+    pub query: String,
+    /// Queda percentual máxima em relação ao valor do dia anterior.
+    #[serde(default = "default_max_drop_pct")]
+    pub max_drop_pct: f64,
+}
+
+fn default_max_drop_pct() -> f64 {
+    10.0
+}
+
+/// Consulta que devolve a marca d'água da origem (ex.: a maior data de alteração), por um comando (hook, `sqlcmd`)
+/// ou por uma URL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Watermark {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Ponteiro JSON do campo com a marca (ex.: `/last_modified`), quando a resposta é um JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pointer: Option<String>,
+}
+
+impl DatasetConfig {
+    pub fn new(id: &str) -> Self {
+        DatasetConfig { id: id.to_string(), ..Default::default() }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DatasetRef {
+    Guid(String),
+    Detailed(Box<DatasetConfig>),
+}
+
+fn deserialize_datasets<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<DatasetConfig>, D::Error> {
+    let datasets = Vec::<DatasetRef>::deserialize(deserializer)?;
+    Ok(datasets
+        .into_iter()
+        .map(|dataset| match dataset {
+            DatasetRef::Guid(id) => DatasetConfig::new(&id),
+            DatasetRef::Detailed(config) => *config,
+        })
+        .collect())
+}
+
+/// Datasets sem opções continuam gravados apenas como GUID.
+fn serialize_datasets<S: Serializer>(datasets: &[DatasetConfig], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(datasets.iter().map(|dataset| {
+        if *dataset == DatasetConfig::new(&dataset.id) {
+            serde_json::Value::String(dataset.id.clone())
+        } else {
+            serde_json::to_value(dataset).unwrap()
+        }
+    }))
+}
+
+mod schedule_format {
+    use chrono::NaiveTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%H:%M";
+
+    pub fn serialize<S: Serializer>(times: &[NaiveTime], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(times.iter().map(|time| time.format(FORMAT).to_string()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<NaiveTime>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|value| NaiveTime::parse_from_str(value, FORMAT).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
+mod deadline_format {
+    use chrono::NaiveTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%H:%M";
+
+    pub fn serialize<S: Serializer>(deadline: &Option<NaiveTime>, serializer: S) -> Result<S::Ok, S::Error> {
+        match deadline {
+            Some(deadline) => serializer.serialize_str(&deadline.format(FORMAT).to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveTime>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(value) => NaiveTime::parse_from_str(&value, FORMAT).map(Some).map_err(serde::de::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Modelo do `dataset.json` criado com `--init`, a ser editado com as empresas e os GUID reais.
+const CONFIG_TEMPLATE: &str = r#"{
+    "companies": [
+        { "id": 1, "guid": ["00000000-0000-0000-0000-000000000000"] }
+    ]
+}
+"#;
+
+/// Cria o `dataset.json` modelo, sem sobrescrever um arquivo existente.
+pub fn write_config_template() -> Result<(), String> {
+
+    if Path::new(FILENAME_CONFIG_JSON).exists() {
+        return Err(format!("Arquivo {} já existe.", FILENAME_CONFIG_JSON));
+    }
+
+    fs::write(FILENAME_CONFIG_JSON, CONFIG_TEMPLATE).map_err(|e| format!("Falha ao gravar {}.\nErro: {}", FILENAME_CONFIG_JSON, e))?;
+    println!("Modelo criado em {}. Informe os IDs das empresas e os GUID dos datasets e execute novamente.", FILENAME_CONFIG_JSON);
+
+    Ok(())
+}
+
+/// Sem empresas não há o que atualizar, mas isso não impede a execução.
+fn warn_if_empty(config: AppConfig) -> AppConfig {
+
+    if config.companies.is_empty() {
+        eprintln!(
+            "{}",
+            format!("Nenhuma empresa configurada em {}. Adicione empresas ao arquivo ou execute `init` para importá-las da API.", FILENAME_CONFIG_JSON).yellow()
+        );
+    }
+
+    config
+}
+
+/// Lê as configurações sem perguntas nem encerrar o programa, para recarregá-las com o processo em execução.
+pub fn load_config_file() -> Result<AppConfig, String> {
+
+    if let Ok(value) = env::var(ENV_DATASETS) {
+        return parse_datasets_env(&value);
+    }
+
+    let content = fs::read_to_string(FILENAME_CONFIG_JSON).map_err(|e| format!("Erro ao ler {}.\n{}", FILENAME_CONFIG_JSON, e))?;
+    parse_config(&content).map_err(|e| format!("Erro ao desserializar {}.\n{}", FILENAME_CONFIG_JSON, e))
+}
+
+pub fn read_config_file() -> AppConfig {
+
+    if let Ok(value) = env::var(ENV_DATASETS) {
+        match parse_datasets_env(&value) {
+            Ok(config) => return warn_if_empty(config),
+            Err(e) => {
+                eprintln!("Erro ao ler variável de ambiente {}.\n{}", ENV_DATASETS, e);
+                pause();
+                exit(1);
+            }
+        }
+    }
+
+    let mut file = match File::open(FILENAME_CONFIG_JSON) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            eprintln!("Arquivo {} não encontrado.", FILENAME_CONFIG_JSON);
+
+            let create = io::stdin().is_terminal() && Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt("Criar um arquivo modelo?")
+                .default(true)
+                .interact()
+                .unwrap_or(false);
+
+            if create {
+                if let Err(e) = write_config_template() {
+                    eprintln!("{}", e);
+                }
+                pause();
+                exit(0);
+            }
+
+            eprintln!("Execute com --init para criar um modelo ou com `init` para o assistente de configuração.");
+            pause();
+            exit(1);
+        }
+        Err(e) => {
+            eprintln!("Erro ao ler arquivo de configurações\n{}", e);
+            pause();
+            exit(1);
+        },
+    };
+
+    let mut content = String::new();
+    file.read_to_string(&mut content).expect("Erro ao ler arquivo de configurações.");
+
+    match parse_config(&content) {
+        Ok(config) => warn_if_empty(config),
+        Err(_) => {
+            eprintln!("Erro ao desserializar arquivo de dataset.");
+            pause();
+            exit(1);
+        }
+    }
+}
+
+/// Especificação completa de uma execução em YAML (`refresh --manifest`): as mesmas opções do `dataset.json`
+/// (empresas, datasets, callbacks, capacidades) mais o acompanhamento das atualizações.
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    #[serde(flatten)]
+    pub config: AppConfig,
+    #[serde(default)]
+    pub wait: Option<ManifestWait>,
+}
+
+/// Acompanhamento definido no manifesto ou em um job; equivale a `--wait` com as opções correspondentes.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ManifestWait {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll_interval_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_minutes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_queue_wait_minutes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watchdog_factor: Option<f64>,
+}
+
+impl ManifestWait {
+    pub fn options(&self) -> WaitOptions {
+        let defaults = WaitOptions::default();
+        WaitOptions {
+            poll_interval: self.poll_interval_secs.map(Duration::from_secs).unwrap_or(defaults.poll_interval),
+            timeout: self.timeout_minutes.map(|minutes| Duration::from_secs(minutes * 60)).unwrap_or(defaults.timeout),
+            max_queue_wait: self.max_queue_wait_minutes.map(|minutes| Duration::from_secs(minutes * 60)).unwrap_or(defaults.max_queue_wait),
+            watchdog_factor: self.watchdog_factor.unwrap_or(defaults.watchdog_factor),
+        }
+    }
+}
+
+pub fn read_manifest(path: &str) -> Result<Manifest, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Erro ao ler manifesto {}.\n{}", path, e))?;
+    serde_yaml::from_str(&content).map_err(|e| format!("Manifesto {} inválido.\n{}", path, e))
+}
+
+/// Grava as configurações no `dataset.json`; sem opções gerais, mantém o formato original (lista de empresas).
+pub fn write_config_file(config: &AppConfig) -> Result<(), String> {
+
+    if env::var(ENV_DATASETS).is_ok() {
+        return Err(format!("Configurações lidas da variável {}; o arquivo {} não será alterado.", ENV_DATASETS, FILENAME_CONFIG_JSON));
+    }
+
+    let simple = config.capacities.is_empty()
+        && config.schedule.is_empty()
+        && config.notify.is_empty()
+        && config.locale.is_none()
+        && config.timezone.is_none()
+        && config.jobs.is_empty()
+        && config.token_skew_minutes.is_none()
+        && config.history.is_none()
+        && !config.read_only
+        && config.retry.is_none()
+        && config.min_interval_minutes.is_none()
+        && config.pause_timeout_seconds.is_none()
+        && config.max_parallel.is_none()
+        && config.annotations.is_none();
+
+    let content = if simple {
+        serde_json::to_string_pretty(&config.companies)
+    } else {
+        serde_json::to_string_pretty(config)
+    };
+
+    backup_config_file()?;
+    fs::write(FILENAME_CONFIG_JSON, content.unwrap()).map_err(|e| format!("Falha ao gravar {}.\nErro: {}", FILENAME_CONFIG_JSON, e))
+}
+
+/// Aceita tanto a lista de empresas (formato original) quanto o objeto completo.
+fn parse_config(content: &str) -> Result<AppConfig, String> {
+
+    if content.trim_start().starts_with('[') {
+        let companies = serde_json::from_str::<Vec<GuidEntry>>(content).map_err(|e| e.to_string())?;
+        return Ok(AppConfig { companies, ..Default::default() });
+    }
+
+    serde_json::from_str::<AppConfig>(content).map_err(|e| e.to_string())
+}
+
+/// Converte o conteúdo de `PBI_DATASETS` nas configurações.
+fn parse_datasets_env(value: &str) -> Result<AppConfig, String> {
+
+    let value = value.trim();
+
+    if value.starts_with('[') || value.starts_with('{') {
+        return parse_config(value);
+    }
+
+    let mut entries = Vec::new();
+
+    for company in value.split(';').map(str::trim).filter(|company| !company.is_empty()) {
+
+        let (id, guids) = company
+            .split_once(':')
+            .ok_or_else(|| format!("Empresa sem separador ':' em \"{}\".", company))?;
+
+        let id = id.trim().parse::<u32>().map_err(|_| format!("ID de empresa inválido: \"{}\".", id))?;
+
+        let guid = guids
+            .split(',')
+            .map(str::trim)
+            .filter(|guid| !guid.is_empty())
+            .map(DatasetConfig::new)
+            .collect();
+
+        entries.push(GuidEntry { id, guid, ..Default::default() });
+    }
+
+    Ok(AppConfig { companies: entries, ..Default::default() })
+}
+
+/// Agrupa os datasets de atualização por empresa; os de empresas desativadas ficam desativados.
+pub fn guid_entries_map(entries: &[GuidEntry]) -> HashMap<u32, Vec<DatasetConfig>> {
+
+    let mut hash_guid_entries: HashMap<u32, Vec<DatasetConfig>> = HashMap::new();
+
+    for config in entries {
+        let mut datasets = config.guid.clone();
+        for dataset in datasets.iter_mut() {
+            dataset.disabled |= config.disabled;
+        }
+        hash_guid_entries.insert(config.id, datasets);
+    }
+
+    hash_guid_entries
+}
+
+/// Workspace de cada dataset configurado, o do próprio dataset ou o da empresa.
+pub fn dataset_workspaces(config: &AppConfig) -> HashMap<String, String> {
+
+    let mut workspaces = HashMap::new();
+
+    for company in &config.companies {
+        for dataset in &company.guid {
+            if let Some(workspace) = dataset.workspace.as_ref().or(company.workspace.as_ref()) {
+                workspaces.insert(dataset.id.clone(), workspace.clone());
+            }
+        }
+    }
+
+    workspaces
+}
+
+fn is_guid(value: &str) -> bool {
+    value.len() == 36
+        && value.char_indices().all(|(index, c)| if matches!(index, 8 | 13 | 18 | 23) { c == '-' } else { c.is_ascii_hexdigit() })
+}
+
+/// Confere os workspaces das empresas do perfil: cada um deve ser `"my"` ou um GUID, e o "Meu workspace" só é
+/// acessível a contas de usuário.
+pub fn check_workspaces(config: &AppConfig, secrets: &Secrets) -> Result<(), String> {
+
+    let mut problems = Vec::new();
+
+    for company in config.companies.iter().filter(|company| company.profile == secrets.profile) {
+        for dataset in &company.guid {
+            let Some(workspace) = dataset.workspace.as_ref().or(company.workspace.as_ref()) else {
+                continue;
+            };
+
+            if workspace == MY_WORKSPACE {
+                if secrets.grant_type.is_service_principal() {
+                    problems.push(format!(
+                        "Dataset {} (empresa {}) está no Meu workspace, inacessível ao service principal ({}); mova-o para um workspace compartilhado.",
+                        dataset.id, company.id, secrets.grant_type.as_str()
+                    ));
+                }
+            } else if !is_guid(workspace) {
+                problems.push(format!("Workspace inválido no dataset {} (empresa {}): \"{}\" (use \"{}\" ou o GUID do workspace).", dataset.id, company.id, workspace, MY_WORKSPACE));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Workspaces inválidos no {}:\n\t- {}", FILENAME_CONFIG_JSON, problems.join("\n\t- ")))
+    }
+}
+
+/// Fluxo de autenticação (`grant_type`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GrantType {
+    Password,
+    ClientCredentials,
+    /// Login no navegador com um código exibido no terminal, sem senha gravada.
+    DeviceCode,
+    /// Service principal autenticado por certificado, no lugar do `client_secret`.
+    Certificate,
+    /// Token emitido pela Azure CLI já autenticada (`az login`), sem credenciais próprias.
+    AzureCli,
+    /// Identidade gerenciada da VM, Automação ou servidor Azure Arc onde o programa executa.
+    ManagedIdentity,
+    /// Token obtido fora do programa (`access_token`), usado até expirar.
+    StaticToken,
+}
+
+impl GrantType {
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GrantType::Password => "password",
+            GrantType::ClientCredentials => "client_credentials",
+            GrantType::DeviceCode => "device_code",
+            GrantType::Certificate => "certificate",
+            GrantType::AzureCli => "azure_cli",
+            GrantType::ManagedIdentity => "managed_identity",
+            GrantType::StaticToken => "static_token",
+        }
+    }
+
+    /// Chaves obrigatórias, além de `client_id`, em cada fluxo.
+    fn required_keys(&self) -> &'static [&'static str] {
+        match self {
+            GrantType::Password => &["username"],
+            GrantType::ClientCredentials => &["tenant", "client_secret"],
+            GrantType::DeviceCode => &[],
+            GrantType::Certificate => &["tenant", "certificate"],
+            GrantType::AzureCli | GrantType::ManagedIdentity => &[],
+            GrantType::StaticToken => &["access_token"],
+        }
+    }
+
+    /// Indica se o fluxo autentica um service principal, que não tem "Meu workspace".
+    pub fn is_service_principal(&self) -> bool {
+        matches!(self, GrantType::ClientCredentials | GrantType::Certificate | GrantType::ManagedIdentity)
+    }
+
+    /// Indica se o fluxo exige `client_id`: a Azure CLI usa o próprio aplicativo, na identidade gerenciada
+    /// o `client_id` é opcional e escolhe a identidade atribuída pelo usuário, e o token estático já vem pronto.
+    fn requires_client_id(&self) -> bool {
+        !matches!(self, GrantType::AzureCli | GrantType::ManagedIdentity | GrantType::StaticToken)
+    }
+}
+
+/// Nuvem do Azure (`cloud`): define o login, a audiência do token e o endereço da API do PowerBI.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Cloud {
+    #[default]
+    Public,
+    /// Governo dos EUA (GCC).
+    UsGov,
+    /// Governo dos EUA (GCC High).
+    UsGovHigh,
+    /// Departamento de Defesa dos EUA (DoD).
+    UsGovDod,
+    /// Azure China (21Vianet).
+    China,
+}
+
+impl Cloud {
+
+    fn parse(value: &str) -> Option<Cloud> {
+        match value {
+            "public" => Some(Cloud::Public),
+            "usgov" => Some(Cloud::UsGov),
+            "usgovhigh" => Some(Cloud::UsGovHigh),
+            "usgovdod" => Some(Cloud::UsGovDod),
+            "china" => Some(Cloud::China),
+            _ => None,
+        }
+    }
+
+    fn authority(&self) -> &'static str {
+        match self {
+            Cloud::Public | Cloud::UsGov => "https://login.microsoftonline.com",
+            Cloud::UsGovHigh | Cloud::UsGovDod => "https://login.microsoftonline.us",
+            Cloud::China => "https://login.chinacloudapi.cn",
+        }
+    }
+
+    /// Audiência dos tokens da API do PowerBI.
+    pub fn resource(&self) -> &'static str {
+        match self {
+            Cloud::Public => "https://analysis.windows.net/powerbi/api",
+            Cloud::UsGov => "https://analysis.usgovcloudapi.net/powerbi/api",
+            Cloud::UsGovHigh => "https://high.analysis.usgovcloudapi.net/powerbi/api",
+            Cloud::UsGovDod => "https://mil.analysis.usgovcloudapi.net/powerbi/api",
+            Cloud::China => "https://analysis.chinacloudapi.cn/powerbi/api",
+        }
+    }
+
+    fn api_base_url(&self) -> &'static str {
+        match self {
+            Cloud::Public => "https://api.powerbi.com/v1.0/myorg",
+            Cloud::UsGov => "https://api.powerbigov.us/v1.0/myorg",
+            Cloud::UsGovHigh => "https://api.high.powerbigov.us/v1.0/myorg",
+            Cloud::UsGovDod => "https://api.mil.powerbigov.us/v1.0/myorg",
+            Cloud::China => "https://api.powerbi.cn/v1.0/myorg",
+        }
+    }
+}
+
+/// Onde o token é guardado entre execuções (`token_storage`).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TokenStorage {
+    /// Cofre de credenciais do sistema (Gerenciador de Credenciais, Keychain, Secret Service).
+    #[default]
+    Keyring,
+    /// Arquivo `.token` em texto puro na pasta de execução.
+    File,
+}
+
+/// Segredos de autenticação, lidos do `secrets.toml` e das variáveis `PBI_*`.
+#[derive(Debug, Clone)]
+pub struct Secrets {
+    pub client_id: String,
+    pub grant_type: GrantType,
+    pub resource: Option<String>,
+    pub tenant: Option<String>,
+    pub client_secret: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Arquivo PEM com o certificado e a chave privada (`grant_type = "certificate"`).
+    pub certificate: Option<String>,
+    /// Token de acesso pronto (`grant_type = "static_token"`).
+    pub access_token: Option<String>,
+    pub token_storage: TokenStorage,
+    pub cloud: Cloud,
+    /// Endereço de login, no lugar do definido pela nuvem (ex.: `https://login.microsoftonline.us`).
+    pub authority_url: Option<String>,
+    /// Endereço da API do PowerBI, no lugar do definido pela nuvem.
+    pub api_base_url: Option<String>,
+    /// Perfil (tabela do `secrets.toml`) de onde vieram; `None` é o perfil padrão.
+    pub profile: Option<String>,
+}
+
+/// Chaves reconhecidas no arquivo de segredos.
+const SECRETS_KEYS: [&str; 13] = [
+    "client_id", "grant_type", "resource", "tenant", "client_secret", "username", "password", "certificate", "access_token", "token_storage", "cloud",
+    "authority_url", "api_base_url",
+];
+/// Sufixo das chaves cujo valor é lido de um arquivo (ex.: `PBI_PASSWORD_FILE=/run/secrets/pbi_password`).
+const SECRETS_FILE_SUFFIX: &str = "_file";
+
+impl Secrets {
+
+    /// Endereço de login (sem o tenant): o informado em `authority_url` ou o da nuvem.
+    pub fn authority(&self) -> &str {
+        self.authority_url.as_deref().map(|url| url.trim_end_matches('/')).unwrap_or(self.cloud.authority())
+    }
+
+    /// Endereço base da API do PowerBI: o informado em `api_base_url` ou o da nuvem.
+    pub fn api_base_url(&self) -> &str {
+        self.api_base_url.as_deref().map(|url| url.trim_end_matches('/')).unwrap_or(self.cloud.api_base_url())
+    }
+
+    /// Valida as chaves lidas, reportando todas as ausentes, desconhecidas ou com valor inválido.
+    fn from_map(mut values: HashMap<String, String>) -> Result<Secrets, Vec<String>> {
+
+        let mut problems = Vec::new();
+
+        let mut unknown: Vec<&String> = values
+            .keys()
+            .filter(|key| !SECRETS_KEYS.contains(&key.as_str()) && !is_non_secret_key(key))
+            .collect();
+        unknown.sort();
+
+        for key in unknown {
+            match SECRETS_KEYS.iter().min_by_key(|known| edit_distance(key, known)).filter(|known| edit_distance(key, known) <= 2) {
+                Some(known) => problems.push(format!("Chave desconhecida \"{}\" (seria \"{}\"?).", key, known)),
+                None => problems.push(format!("Chave desconhecida \"{}\".", key)),
+            }
+        }
+
+        let grant_type = match values.get("grant_type").map(String::as_str) {
+            Some("password") => Some(GrantType::Password),
+            Some("client_credentials") => Some(GrantType::ClientCredentials),
+            Some("device_code") => Some(GrantType::DeviceCode),
+            Some("certificate") => Some(GrantType::Certificate),
+            Some("azure_cli") => Some(GrantType::AzureCli),
+            Some("managed_identity") => Some(GrantType::ManagedIdentity),
+            Some("static_token") => Some(GrantType::StaticToken),
+            Some(other) => {
+                problems.push(format!(
+                    "Valor inválido para grant_type: \"{}\" (use \"password\", \"client_credentials\", \"device_code\", \"certificate\", \"azure_cli\", \"managed_identity\" ou \"static_token\").",
+                    other
+                ));
+                None
+            }
+            None => {
+                problems.push("Chave obrigatória ausente: grant_type.".to_string());
+                None
+            }
+        };
+
+        let token_storage = match values.get("token_storage").map(String::as_str) {
+            None | Some("keyring") => TokenStorage::Keyring,
+            Some("file") => TokenStorage::File,
+            Some(other) => {
+                problems.push(format!("Valor inválido para token_storage: \"{}\" (use \"keyring\" ou \"file\").", other));
+                TokenStorage::Keyring
+            }
+        };
+
+        let cloud = match values.get("cloud").map(String::as_str) {
+            None => Cloud::Public,
+            Some(value) => Cloud::parse(value).unwrap_or_else(|| {
+                problems.push(format!("Valor inválido para cloud: \"{}\" (use \"public\", \"usgov\", \"usgovhigh\", \"usgovdod\" ou \"china\").", value));
+                Cloud::Public
+            }),
+        };
+
+        let mut required = if grant_type.is_some_and(|grant_type| !grant_type.requires_client_id()) { vec![] } else { vec!["client_id"] };
+        if let Some(grant_type) = grant_type {
+            required.extend(grant_type.required_keys());
+        }
+
+        for key in required {
+            if values.get(key).is_none_or(|value| value.trim().is_empty()) {
+                match grant_type {
+                    Some(grant_type) if key != "client_id" => problems.push(format!("Chave obrigatória ausente para {}: {}.", grant_type.as_str(), key)),
+                    _ => problems.push(format!("Chave obrigatória ausente: {}.", key)),
+                }
+            }
+        }
+
+        match grant_type {
+            Some(grant_type) if problems.is_empty() => Ok(Secrets {
+                client_id: values.remove("client_id").unwrap_or_default(),
+                grant_type,
+                resource: values.remove("resource"),
+                tenant: values.remove("tenant"),
+                client_secret: values.remove("client_secret"),
+                username: values.remove("username"),
+                password: values.remove("password"),
+                certificate: values.remove("certificate"),
+                access_token: values.remove("access_token"),
+                token_storage,
+                cloud,
+                authority_url: values.remove("authority_url"),
+                api_base_url: values.remove("api_base_url"),
+                profile: None,
+            }),
+            _ => Err(problems),
+        }
+    }
+}
+
+/// Distância de edição entre duas chaves, para sugerir a correta quando houver erro de digitação.
+fn edit_distance(a: &str, b: &str) -> usize {
+
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current.push((previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Define a pasta de trabalho, onde todos os arquivos são lidos e gravados: `PBI_HOME` quando informada ou, se a pasta
+/// atual não tiver os arquivos de configuração, a pasta do executável. Serviços do Windows iniciam em
+/// `C:\Windows\System32` e agendadores nem sempre definem a pasta de execução.
+pub fn enter_home_dir() {
+
+    if let Some(home) = env::var_os(ENV_HOME).filter(|home| !home.is_empty()) {
+        if let Err(e) = env::set_current_dir(&home) {
+            eprintln!("Falha ao acessar a pasta {} ({}).\nErro: {}", ENV_HOME, Path::new(&home).display(), e);
+            exit(1);
+        }
+        return;
+    }
+
+    let has_config = |dir: &Path| dir.join(FILENAME_SECRETS_TOML).exists() || dir.join(FILENAME_CONFIG_JSON).exists();
+
+    if has_config(Path::new(".")) {
+        return;
+    }
+
+    let exe_dir = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+    if let Some(dir) = exe_dir.filter(|dir| has_config(dir)) {
+        let _ = env::set_current_dir(dir);
+    }
+}
+
+/// Indica se a chave lida com as variáveis `PBI_*` vem de uma variável que não é segredo (ex.: `datasets`).
+fn is_non_secret_key(key: &str) -> bool {
+    NON_SECRET_ENV
+        .iter()
+        .filter_map(|name| name.strip_prefix(ENV_SECRETS_PREFIX)?.strip_prefix('_'))
+        .any(|name| name.eq_ignore_ascii_case(key))
+}
+
+/// Perfis de credenciais: as chaves do topo do `secrets.toml` (e as variáveis `PBI_*`) formam o perfil padrão,
+/// cada tabela (`[clienteA]`) forma um perfil nomeado.
+fn read_secrets_sources() -> (HashMap<String, String>, BTreeMap<String, HashMap<String, String>>) {
+    let current_dir = env::current_dir().expect("Erro ao obter diretório de execução");
+    let settings_file = current_dir.join(FILENAME_SECRETS_TOML);
+
+    // O arquivo é opcional quando os segredos vêm das variáveis de ambiente `PBI_*`. Um arquivo cifrado pelo SOPS é
+    // decifrado em memória e lido como TOML.
+    let settings_builder = match fs::read_to_string(&settings_file) {
+        Ok(content) if sops::is_encrypted(&content) => match sops::decrypt(&settings_file) {
+            Ok(decrypted) => Config::builder().add_source(ConfigFile::from_str(&decrypted, FileFormat::Toml)),
+            Err(e) => {
+                eprintln!("Falha ao ler arquivo de segredos.\n{}", e);
+                pause();
+                exit(1);
+            }
+        },
+        _ => Config::builder().add_source(ConfigFile::with_name(settings_file.to_str().unwrap()).required(false)),
+    };
+
+    let settings_builder = settings_builder
+    .add_source(Environment::with_prefix(ENV_SECRETS_PREFIX))
+    .build();
+
+    let settings = match settings_builder.and_then(|settings| settings.try_deserialize::<HashMap<String, ConfigValue>>()) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Falha ao ler arquivo de segredos.\n{}", e);
+            pause();
+            exit(1);
+        }
+    };
+
+    let mut default = HashMap::new();
+    let mut profiles = BTreeMap::new();
+
+    for (key, value) in settings {
+
+        if is_non_secret_key(&key) {
+            continue;
+        }
+
+        match value.clone().into_table() {
+            Ok(table) => {
+                let values = table.into_iter().map(|(key, value)| (key, value.into_string().unwrap_or_default())).collect();
+                profiles.insert(key, values);
+            }
+            Err(_) => {
+                default.insert(key, value.into_string().unwrap_or_default());
+            }
+        }
+    }
+
+    resolve_secrets(&mut default, None);
+    for (profile, values) in profiles.iter_mut() {
+        resolve_secrets(values, Some(profile));
+    }
+
+    (default, profiles)
+}
+
+/// Lê os valores guardados fora do arquivo: primeiro as chaves `<chave>_file`, depois o segredo do Vault.
+fn resolve_secrets(values: &mut HashMap<String, String>, profile: Option<&str>) {
+
+    resolve_secret_files(values);
+
+    if let Err(e) = resolve_vault(values, &SECRETS_KEYS) {
+        match profile {
+            Some(profile) => eprintln!("Falha ao ler segredos do Vault (perfil {}).\n{}", profile, e),
+            None => eprintln!("Falha ao ler segredos do Vault.\n{}", e),
+        }
+        pause();
+        exit(1);
+    }
+}
+
+/// Troca as chaves `<chave>_file` pelo conteúdo do arquivo indicado, como nos secrets do Docker e do Kubernetes.
+/// O valor lido do arquivo prevalece sobre a chave informada diretamente.
+fn resolve_secret_files(values: &mut HashMap<String, String>) {
+
+    let files: Vec<String> = values
+        .keys()
+        .filter(|key| key.strip_suffix(SECRETS_FILE_SUFFIX).is_some_and(|key| SECRETS_KEYS.contains(&key) || VAULT_KEYS.contains(&key)))
+        .cloned()
+        .collect();
+
+    for file_key in files {
+        let path = values.remove(&file_key).unwrap_or_default();
+        let key = file_key.trim_end_matches(SECRETS_FILE_SUFFIX).to_string();
+
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                values.insert(key, content.trim().to_string());
+            }
+            Err(e) => {
+                eprintln!("Falha ao ler o arquivo de {} ({}).\nErro: {}", key, path, e);
+                pause();
+                exit(1);
+            }
+        }
+    }
+}
+
+fn validate_secrets(values: HashMap<String, String>, profile: Option<&str>) -> Secrets {
+    match Secrets::from_map(values) {
+        Ok(secrets) => Secrets { profile: profile.map(str::to_string), ..secrets },
+        Err(problems) => {
+            match profile {
+                Some(profile) => eprintln!("Arquivo de segredos inválido (perfil {}):", profile),
+                None => eprintln!("Arquivo de segredos inválido:"),
+            }
+            for problem in problems {
+                eprintln!("\t- {}", problem);
+            }
+            pause();
+            exit(1);
+        }
+    }
+}
+
+/// Segredos do perfil informado (`--profile`) ou do perfil padrão. Sem arquivo nem variáveis, usa o login da
+/// Azure CLI.
+pub fn read_secrets_file(profile: Option<&str>) -> Secrets {
+
+    let (mut default, mut profiles) = read_secrets_sources();
+
+    if let Some(profile) = profile {
+        let Some(values) = profiles.remove(profile) else {
+            let available: Vec<&str> = profiles.keys().map(String::as_str).collect();
+            eprintln!("Perfil \"{}\" não encontrado no {}. Perfis disponíveis: {}", profile, FILENAME_SECRETS_TOML, if available.is_empty() { "nenhum".to_string() } else { available.join(", ") });
+            pause();
+            exit(1);
+        };
+        return validate_secrets(values, Some(profile));
+    }
+
+    // Só há perfis nomeados: escolher um deles em silêncio, ou cair na Azure CLI, usaria a conta errada.
+    if default.is_empty() && !profiles.is_empty() {
+        let available: Vec<&str> = profiles.keys().map(String::as_str).collect();
+        eprintln!("O {} só tem perfis nomeados; informe um com --profile (ou use refresh --all-profiles). Perfis disponíveis: {}", FILENAME_SECRETS_TOML, available.join(", "));
+        pause();
+        exit(1);
+    }
+
+    if default.is_empty() {
+        println!("Arquivo {} não encontrado e nenhuma variável {}_* definida; usando o login da Azure CLI.", FILENAME_SECRETS_TOML, ENV_SECRETS_PREFIX);
+        default.insert("grant_type".to_string(), GrantType::AzureCli.as_str().to_string());
+    }
+
+    validate_secrets(default, None)
+}
+
+/// Nomes dos perfis nomeados do `secrets.toml`, em ordem alfabética.
+pub fn profile_names() -> Vec<String> {
+    read_secrets_sources().1.into_keys().collect()
+}
+
+/// Indica se há perfil padrão (chaves do topo do `secrets.toml` ou variáveis `PBI_*`).
+pub fn has_default_profile() -> bool {
+    !read_secrets_sources().0.is_empty()
+}
+
+/// Segredos de todos os perfis: o padrão (quando houver) e os nomeados.
+pub fn read_all_profiles() -> Vec<Secrets> {
+
+    let (default, profiles) = read_secrets_sources();
+
+    if default.is_empty() && profiles.is_empty() {
+        eprintln!("Falha ao ler arquivo de segredos.\nArquivo {} não encontrado e nenhuma variável {}_* definida.", FILENAME_SECRETS_TOML, ENV_SECRETS_PREFIX);
+        pause();
+        exit(1);
+    }
+
+    let mut all = Vec::new();
+
+    if !default.is_empty() {
+        all.push(validate_secrets(default, None));
+    }
+
+    for (name, values) in profiles {
+        all.push(validate_secrets(values, Some(&name)));
+    }
+
+    all
+}
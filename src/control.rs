@@ -0,0 +1,194 @@
+use std::sync::{atomic::Ordering, Arc};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::config::ensure_writable;
+use crate::locale;
+use crate::server::ServerState;
+
+/// Endereço padrão do controle: um socket no diretório de trabalho ou, no Windows, um named pipe.
+#[cfg(unix)]
+pub const DEFAULT_ADDRESS: &str = "power_bi_updater.sock";
+#[cfg(windows)]
+pub const DEFAULT_ADDRESS: &str = r"\\.\pipe\power_bi_updater";
+
+/// Executa um comando de controle (`reload`, `pause`, `resume`, `run-job <nome>`, `status`).
+async fn execute(state: &ServerState, line: &str) -> Result<String, String> {
+
+    let (command, argument) = line.split_once(' ').map(|(command, argument)| (command, argument.trim())).unwrap_or((line, ""));
+
+    match (command, argument) {
+        ("reload", "") => {
+            let loaded = state.reload().await?;
+            let datasets: usize = loaded.session.entries.values().map(Vec::len).sum();
+            Ok(format!("Configurações recarregadas: {} empresa(s), {} dataset(s).", loaded.session.entries.len(), datasets))
+        }
+        ("pause", "") => {
+            state.paused.store(true, Ordering::Relaxed);
+            Ok("Agendamentos e eventos em pausa.".to_string())
+        }
+        ("resume", "") => {
+            state.paused.store(false, Ordering::Relaxed);
+            Ok("Agendamentos e eventos retomados.".to_string())
+        }
+        ("run-job", "") => Err("Informe o nome do job.".to_string()),
+        ("run-job", name) => {
+            ensure_writable("A atualização")?;
+            state.spawn_job(name)?;
+            Ok(format!("Job {} iniciado.", name))
+        }
+        ("status", "") => {
+            let loaded = state.loaded();
+            let datasets: usize = loaded.session.entries.values().map(Vec::len).sum();
+            let next_run = match state.next_run().await {
+                Some(next_run) => locale::datetime(next_run),
+                None => "sem agendamento".to_string(),
+            };
+
+            Ok(format!(
+                "Situação: {}\nPróxima execução: {}\nEmpresas: {}\nDatasets: {}\nJobs: {}\nConfigurações carregadas em {}",
+                if state.is_paused() { "em pausa" } else { "ativo" },
+                next_run,
+                loaded.session.entries.len(),
+                datasets,
+                loaded.config.jobs.len(),
+                locale::datetime(loaded.loaded_at),
+            ))
+        }
+        _ => Err(format!("Comando desconhecido: {}", line)),
+    }
+}
+
+/// Lê um comando por conexão e responde com `ok` ou `erro` na primeira linha, seguido da mensagem.
+async fn handle<S: AsyncRead + AsyncWrite + Unpin>(state: &ServerState, stream: S) {
+
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut line = String::new();
+
+    if let Err(e) = BufReader::new(reader).read_line(&mut line).await {
+        eprintln!("Falha ao ler comando de controle.\nErro: {}", e);
+        return;
+    }
+
+    let line = line.trim();
+    println!("Comando de controle recebido: {}", line);
+
+    let reply = match execute(state, line).await {
+        Ok(message) => format!("ok\n{}\n", message),
+        Err(message) => format!("erro\n{}\n", message),
+    };
+
+    if let Err(e) = writer.write_all(reply.as_bytes()).await {
+        eprintln!("Falha ao responder comando de controle.\nErro: {}", e);
+    }
+    let _ = writer.shutdown().await;
+}
+
+/// Atende os comandos de controle do modo servidor. O socket só aceita conexões do próprio usuário.
+#[cfg(unix)]
+pub async fn listen(state: Arc<ServerState>, address: String) {
+
+    use std::{fs, os::unix::fs::PermissionsExt};
+    use tokio::net::UnixListener;
+
+    // Um socket deixado por uma execução anterior impediria a criação do novo.
+    let _ = fs::remove_file(&address);
+
+    let listener = match UnixListener::bind(&address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Falha ao criar o socket de controle {}.\nErro: {}", address, e);
+            return;
+        }
+    };
+
+    if let Err(e) = fs::set_permissions(&address, fs::Permissions::from_mode(0o600)) {
+        eprintln!("Falha ao restringir as permissões do socket de controle.\nErro: {}", e);
+    }
+
+    println!("Controle disponível em {}", address);
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let state = state.clone();
+                tokio::spawn(async move { handle(&state, stream).await });
+            }
+            Err(e) => eprintln!("Falha ao aceitar conexão de controle.\nErro: {}", e),
+        }
+    }
+}
+
+/// Atende os comandos de controle do modo servidor por um named pipe.
+#[cfg(windows)]
+pub async fn listen(state: Arc<ServerState>, address: String) {
+
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = match ServerOptions::new().first_pipe_instance(true).create(&address) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Falha ao criar o named pipe de controle {}.\nErro: {}", address, e);
+            return;
+        }
+    };
+
+    println!("Controle disponível em {}", address);
+
+    loop {
+        if let Err(e) = server.connect().await {
+            eprintln!("Falha ao aceitar conexão de controle.\nErro: {}", e);
+            continue;
+        }
+
+        // A próxima instância é criada antes de atender a conexão, para não recusar clientes nesse intervalo.
+        let connected = server;
+        server = match ServerOptions::new().create(&address) {
+            Ok(server) => server,
+            Err(e) => {
+                eprintln!("Falha ao recriar o named pipe de controle.\nErro: {}", e);
+                return;
+            }
+        };
+
+        let state = state.clone();
+        tokio::spawn(async move { handle(&state, connected).await });
+    }
+}
+
+#[cfg(unix)]
+async fn connect(address: &str) -> std::io::Result<tokio::net::UnixStream> {
+    tokio::net::UnixStream::connect(address).await
+}
+
+#[cfg(windows)]
+async fn connect(address: &str) -> std::io::Result<tokio::net::windows::named_pipe::NamedPipeClient> {
+    tokio::net::windows::named_pipe::ClientOptions::new().open(address)
+}
+
+/// Envia um comando ao processo do `serve` e devolve a resposta.
+pub async fn send(address: &str, command: &str) -> Result<String, String> {
+
+    let stream = connect(address)
+        .await
+        .map_err(|e| format!("Nenhum servidor respondendo em {}; inicie-o com `serve --control`.\nErro: {}", address, e))?;
+
+    let (reader, mut writer) = tokio::io::split(stream);
+
+    writer
+        .write_all(format!("{}\n", command).as_bytes())
+        .await
+        .map_err(|e| format!("Falha ao enviar o comando.\nErro: {}", e))?;
+
+    let mut reader = BufReader::new(reader);
+    let mut status = String::new();
+    let mut message = String::new();
+
+    reader.read_line(&mut status).await.map_err(|e| e.to_string())?;
+    reader.read_to_string(&mut message).await.map_err(|e| e.to_string())?;
+
+    match status.trim() {
+        "ok" => Ok(message.trim_end().to_string()),
+        "erro" => Err(message.trim_end().to_string()),
+        _ => Err("Resposta inválida do servidor.".to_string()),
+    }
+}
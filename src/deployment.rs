@@ -0,0 +1,149 @@
+use std::{collections::BTreeMap, fs};
+use colored::Colorize;
+use serde::Deserialize;
+
+use crate::powerbi::{list_datasets, list_datasources, list_parameters, list_pipeline_stages, list_refreshes, Dataset};
+use crate::token::TokenResponse;
+
+/// Verificação de uma implantação (`verify-deployment`), em YAML: o workspace de destino, informado direto ou pelo
+/// estágio de um pipeline, e o que se espera de cada dataset depois da promoção.
+#[derive(Debug, Deserialize)]
+pub struct DeploymentSpec {
+    #[serde(default)]
+    pub pipeline: Option<String>,
+    /// Estágio do pipeline: 0 desenvolvimento, 1 teste, 2 produção.
+    #[serde(default)]
+    pub stage: Option<u32>,
+    #[serde(default)]
+    pub workspace: Option<String>,
+    pub datasets: Vec<ExpectedDataset>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpectedDataset {
+    /// Nome do dataset no workspace de destino (os GUIDs mudam a cada estágio).
+    pub name: String,
+    #[serde(default)]
+    pub parameters: BTreeMap<String, String>,
+    /// Gateway ao qual as fontes de dados devem estar vinculadas.
+    #[serde(default)]
+    pub gateway: Option<String>,
+}
+
+pub fn read_spec(path: &str) -> Result<DeploymentSpec, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Erro ao ler verificação {}.\n{}", path, e))?;
+    serde_yaml::from_str(&content).map_err(|e| format!("Verificação {} inválida.\n{}", path, e))
+}
+
+/// Workspace verificado, com o nome quando conhecido: o informado ou o do estágio do pipeline.
+async fn target_workspace(spec: &DeploymentSpec, token: &TokenResponse) -> Result<(String, Option<String>), String> {
+
+    if let Some(workspace) = &spec.workspace {
+        return Ok((workspace.clone(), None));
+    }
+
+    let pipeline = spec.pipeline.as_deref().ok_or("Informe `workspace` ou `pipeline` e `stage` na verificação.")?;
+    let order = spec.stage.ok_or("Informe o `stage` do pipeline na verificação.")?;
+
+    list_pipeline_stages(pipeline, token)
+        .await?
+        .into_iter()
+        .find(|stage| stage.order == order)
+        .and_then(|stage| stage.workspace_id.map(|id| (id, stage.workspace_name)))
+        .ok_or_else(|| format!("O estágio {} do pipeline {} não tem workspace associado.", order, pipeline))
+}
+
+/// Item da lista de verificação.
+fn check(label: &str, result: Result<(), String>) -> bool {
+    match result {
+        Ok(()) => {
+            println!("\t{} {}", "[OK]".green(), label);
+            true
+        }
+        Err(problem) => {
+            println!("\t{} {}: {}", "[FALHA]".red(), label, problem);
+            false
+        }
+    }
+}
+
+async fn check_parameters(group: &str, dataset: &Dataset, expected: &BTreeMap<String, String>, token: &TokenResponse) -> Result<(), String> {
+
+    let parameters = list_parameters(group, &dataset.id, token).await?;
+    let mut problems = Vec::new();
+
+    for (name, value) in expected {
+        match parameters.iter().find(|parameter| &parameter.name == name) {
+            None => problems.push(format!("{} não existe", name)),
+            Some(parameter) if parameter.current_value.as_deref() != Some(value.as_str()) => problems.push(format!(
+                "{} = {} (esperado {})",
+                name,
+                parameter.current_value.as_deref().unwrap_or("-"),
+                value
+            )),
+            Some(_) => {}
+        }
+    }
+
+    if problems.is_empty() { Ok(()) } else { Err(problems.join("; ")) }
+}
+
+async fn check_gateway(group: &str, dataset: &Dataset, gateway: &str, token: &TokenResponse) -> Result<(), String> {
+
+    let unbound: Vec<String> = list_datasources(group, &dataset.id, token)
+        .await?
+        .into_iter()
+        .filter(|datasource| !datasource.gateway_id.as_deref().is_some_and(|id| id.eq_ignore_ascii_case(gateway)))
+        .map(|datasource| format!("{} ({})", datasource.datasource_type.unwrap_or_default(), datasource.gateway_id.as_deref().unwrap_or("sem gateway")))
+        .collect();
+
+    if unbound.is_empty() { Ok(()) } else { Err(format!("fontes fora do gateway: {}", unbound.join(", "))) }
+}
+
+/// A atualização mais recente deve ter terminado com sucesso; uma ainda em andamento conta como falha.
+async fn check_refresh(dataset: &Dataset, token: &TokenResponse) -> Result<(), String> {
+    match list_refreshes(&dataset.id, 1, token).await?.first() {
+        None => Err("nenhuma atualização encontrada".to_string()),
+        Some(refresh) if refresh.status == "Completed" => Ok(()),
+        Some(refresh) if refresh.status == "Unknown" => Err("atualização ainda em andamento".to_string()),
+        Some(refresh) => Err(format!("última atualização: {}", refresh.status)),
+    }
+}
+
+/// Confere, depois de uma promoção de pipeline, se os datasets do destino têm os parâmetros e o gateway esperados
+/// e uma atualização concluída. Devolve `false` se algum item falhou.
+pub async fn verify(spec: &DeploymentSpec, token: &TokenResponse) -> Result<bool, String> {
+
+    let (group, name) = target_workspace(spec, token).await?;
+    let datasets = list_datasets(&group, token).await?;
+    let mut ok = true;
+
+    match name {
+        Some(name) => println!("Workspace {} ({})", name, group),
+        None => println!("Workspace {}", group),
+    }
+
+    for expected in &spec.datasets {
+        println!("Dataset {}", expected.name);
+
+        let Some(dataset) = datasets.iter().find(|dataset| dataset.name == expected.name) else {
+            check("Publicado no workspace", Err("não encontrado".to_string()));
+            ok = false;
+            continue;
+        };
+
+        ok &= check("Publicado no workspace", Ok(()));
+
+        if !expected.parameters.is_empty() {
+            ok &= check("Parâmetros", check_parameters(&group, dataset, &expected.parameters, token).await);
+        }
+
+        if let Some(gateway) = &expected.gateway {
+            ok &= check("Gateway", check_gateway(&group, dataset, gateway, token).await);
+        }
+
+        ok &= check("Atualização inicial", check_refresh(dataset, token).await);
+    }
+
+    Ok(ok)
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+use colored::Colorize;
+use serde::Deserialize;
+
+use crate::config::Secrets;
+use crate::token::TokenResponse;
+
+/// Intervalo de consulta usado quando o servidor não informa um.
+const DEFAULT_INTERVAL_SECONDS: u64 = 5;
+
+/// Código gerado para o usuário autorizar o acesso em outro dispositivo.
+#[derive(Debug, Deserialize)]
+struct DeviceCodeResponse {
+    device_code: String,
+    user_code: String,
+    verification_url: String,
+    #[serde(deserialize_with = "number_or_string")]
+    expires_in: u64,
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    interval: Option<u64>,
+}
+
+/// Erro devolvido pelo endpoint de token enquanto o código não é autorizado.
+#[derive(Debug, Deserialize)]
+struct TokenError {
+    error: String,
+    #[serde(default)]
+    error_description: String,
+}
+
+// O endpoint v1 devolve alguns números como texto.
+fn number_or_string<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(number) => number.as_u64().ok_or_else(|| serde::de::Error::custom("número inválido")),
+        serde_json::Value::String(text) => text.parse().map_err(serde::de::Error::custom),
+        other => Err(serde::de::Error::custom(format!("valor inválido: {}", other))),
+    }
+}
+
+fn optional_number_or_string<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    number_or_string(deserializer).map(Some)
+}
+
+/// Autentica pelo fluxo de código de dispositivo: exibe o código e o endereço de login e consulta o endpoint de token
+/// até o usuário concluir a autorização no navegador (em qualquer dispositivo).
+pub async fn acquire_device_code_token(secrets: &Secrets, resource: &str) -> Result<TokenResponse, String> {
+
+    let tenant = secrets.tenant.as_deref().unwrap_or("common");
+    let client = reqwest::Client::new();
+
+    let res = client
+        .post(format!("{}/{}/oauth2/devicecode", secrets.authority(), tenant))
+        .form(&[("client_id", secrets.client_id.as_str()), ("resource", resource)])
+        .send()
+        .await
+        .map_err(|e| format!("Falha ao solicitar código de dispositivo.\nErro: {}", e))?;
+
+    if !res.status().is_success() {
+        return Err(res.text().await.unwrap_or_default());
+    }
+
+    let code: DeviceCodeResponse = res.json().await.map_err(|e| format!("Resposta inválida do código de dispositivo.\nErro: {}", e))?;
+
+    println!("{}", format!("Acesse {} e informe o código {} para autenticar.", code.verification_url, code.user_code).yellow());
+
+    let mut interval = Duration::from_secs(code.interval.unwrap_or(DEFAULT_INTERVAL_SECONDS));
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(code.expires_in);
+
+    while tokio::time::Instant::now() < deadline {
+
+        tokio::time::sleep(interval).await;
+
+        let res = client
+            .post(format!("{}/{}/oauth2/token", secrets.authority(), tenant))
+            .form(&[
+                ("client_id", secrets.client_id.as_str()),
+                ("grant_type", "device_code"),
+                ("code", code.device_code.as_str()),
+                ("resource", resource),
+            ])
+            .send()
+            .await
+            .map_err(|e| format!("Falha ao consultar autorização do código de dispositivo.\nErro: {}", e))?;
+
+        if res.status().is_success() {
+            return res.json().await.map_err(|e| format!("Falha ao converter JSON.\nErro: {}", e));
+        }
+
+        let text = res.text().await.unwrap_or_default();
+        let error: TokenError = serde_json::from_str(&text).map_err(|_| text.clone())?;
+
+        match error.error.as_str() {
+            "authorization_pending" => {}
+            "slow_down" => interval += Duration::from_secs(DEFAULT_INTERVAL_SECONDS),
+            "authorization_declined" => return Err("Autorização recusada pelo usuário.".to_string()),
+            "expired_token" | "code_expired" => break,
+            _ => return Err(format!("{}: {}", error.error, error.error_description)),
+        }
+    }
+
+    Err("O código de dispositivo expirou antes da autorização.".to_string())
+}
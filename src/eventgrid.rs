@@ -0,0 +1,78 @@
+use std::{collections::{HashMap, BTreeSet}, sync::Arc};
+use axum::{extract::{Query, State}, response::{IntoResponse, Json}, http::StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::config::ensure_writable;
+use crate::server::ServerState;
+
+const SUBSCRIPTION_VALIDATION_EVENT: &str = "Microsoft.EventGrid.SubscriptionValidationEvent";
+
+/// Evento no esquema do Event Grid (apenas os campos utilizados).
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventGridEvent {
+    event_type: String,
+    #[serde(default)]
+    subject: String,
+    #[serde(default)]
+    data: Value,
+}
+
+/// Webhook do Event Grid: valida a assinatura e atualiza as empresas cujo padrão casa com o assunto do evento.
+pub async fn handle_events(State(state): State<Arc<ServerState>>, Query(query): Query<HashMap<String, String>>, Json(events): Json<Vec<EventGridEvent>>) -> impl IntoResponse {
+
+    if let Some(key) = &state.events_key {
+        if query.get("key") != Some(key) {
+            return (StatusCode::UNAUTHORIZED, "Chave inválida.").into_response();
+        }
+    }
+
+    // Handshake de criação da assinatura no Event Grid.
+    if let Some(validation) = events.iter().find(|event| event.event_type == SUBSCRIPTION_VALIDATION_EVENT) {
+        let code = validation.data.get("validationCode").cloned().unwrap_or(Value::Null);
+        return Json(json!({ "validationResponse": code })).into_response();
+    }
+
+    // Um lote com vários arquivos da mesma empresa gera apenas uma atualização.
+    let mut companies: BTreeSet<u32> = BTreeSet::new();
+
+    let loaded = state.loaded();
+
+    for event in &events {
+        for (pattern, company) in &loaded.triggers {
+            if pattern.matches(&event.subject) {
+                companies.insert(*company);
+            }
+        }
+    }
+
+    if companies.is_empty() {
+        return StatusCode::OK.into_response();
+    }
+
+    if let Err(e) = ensure_writable("A atualização") {
+        return (StatusCode::FORBIDDEN, e).into_response();
+    }
+
+    // Com o servidor em pausa, o Event Grid reenvia o evento mais tarde.
+    if state.is_paused() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Servidor em pausa.").into_response();
+    }
+
+    // O Event Grid espera resposta rápida; a atualização segue em segundo plano.
+    let mut targets = Vec::new();
+
+    for company in companies {
+        println!("Evento recebido, atualizando empresa {}.", company);
+
+        match loaded.session.company_targets(company) {
+            Ok(company_targets) => targets.extend(company_targets),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    state.spawn_run(targets);
+
+    StatusCode::ACCEPTED.into_response()
+}
@@ -0,0 +1,270 @@
+use std::{collections::BTreeMap, fs, path::Path};
+use colored::Colorize;
+use serde::Deserialize;
+
+use crate::locale::{self, Locale};
+
+/// Base de erros do usuário, somada (e com prioridade) à base embutida.
+pub const FILENAME_ERRORS_YAML: &str = "errors.yaml";
+
+/// Explicação de um código de erro do PowerBI e as correções sugeridas, em português e, em `translations`, nos
+/// demais idiomas (`en-US`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct ErrorInfo {
+    pub description: String,
+    #[serde(default)]
+    pub fixes: Vec<String>,
+    #[serde(default)]
+    pub translations: BTreeMap<String, ErrorText>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ErrorText {
+    pub description: String,
+    #[serde(default)]
+    pub fixes: Vec<String>,
+}
+
+impl ErrorInfo {
+
+    /// Descrição e correções no idioma informado; sem tradução, em português.
+    pub fn localized(&self, locale: Locale) -> (&str, &[String]) {
+        match self.translations.get(locale.tag()) {
+            Some(text) => (&text.description, &text.fixes),
+            None => (&self.description, &self.fixes),
+        }
+    }
+}
+
+/// Códigos mais comuns nas falhas de atualização.
+const BUILTIN: &[(&str, &str, &[&str])] = &[
+    (
+        "DM_GWPipeline_Gateway_MashupDataAccessError",
+        "O gateway não conseguiu ler a fonte de dados durante a execução das consultas (Power Query).",
+        &[
+            "Confira se a fonte (banco, pasta, API) está disponível a partir da máquina do gateway.",
+            "Revise as credenciais da fonte nas configurações do dataset ou do gateway.",
+            "Veja o detalhe do erro no histórico de atualizações do dataset para identificar a consulta com falha.",
+        ],
+    ),
+    (
+        "DM_GWPipeline_Client_GatewayUnreachable",
+        "O serviço não conseguiu se comunicar com o gateway de dados local.",
+        &[
+            "Verifique se o serviço do gateway está em execução e com acesso à internet.",
+            "Confira o status do gateway no portal do PowerBI (Gerenciar conexões e gateways).",
+        ],
+    ),
+    (
+        "DM_GWPipeline_Gateway_DataSourceAccessError",
+        "O gateway alcançou a fonte de dados, mas o acesso foi negado ou falhou.",
+        &[
+            "Revise usuário, senha e permissões da conta configurada na fonte de dados.",
+            "Confira regras de firewall entre o gateway e a fonte.",
+        ],
+    ),
+    (
+        "DM_GWPipeline_Gateway_TimeoutError",
+        "A consulta à fonte de dados excedeu o tempo limite do gateway.",
+        &[
+            "Otimize as consultas ou reduza o volume (atualização incremental, filtros).",
+            "Distribua as atualizações em horários de menor carga na fonte.",
+        ],
+    ),
+    (
+        "DMTS_DatasourceHasNoCredentialError",
+        "A fonte de dados do dataset está sem credenciais configuradas.",
+        &["Informe as credenciais em Configurações do dataset > Credenciais da fonte de dados."],
+    ),
+    (
+        "DMTS_OAuthTokenRefreshFailedError",
+        "O token OAuth salvo para a fonte de dados expirou ou foi revogado.",
+        &[
+            "Edite as credenciais da fonte nas configurações do dataset e autentique novamente.",
+            "Considere usar uma conta de serviço ou service principal na fonte.",
+        ],
+    ),
+    (
+        "ModelRefresh_ShortMessage_ProcessingError",
+        "Falha no processamento do modelo após a leitura dos dados (tipos, relacionamentos ou memória).",
+        &[
+            "Procure colunas com tipos inconsistentes ou chaves duplicadas em relacionamentos um-para-muitos.",
+            "Verifique o consumo de memória da capacidade durante a atualização.",
+        ],
+    ),
+    (
+        "PowerBINotAuthorizedException",
+        "A conta usada pelo programa não tem permissão sobre o workspace ou o dataset.",
+        &[
+            "Adicione a conta (ou o service principal) como membro ou colaborador do workspace.",
+            "Para service principal, confira se o uso da API está liberado nas configurações de locatário.",
+        ],
+    ),
+    (
+        "ItemNotFound",
+        "O dataset não existe ou não está visível para a conta usada.",
+        &["Confira o GUID no dataset.json (`config diff` e `config prune` ajudam) e as permissões do workspace."],
+    ),
+];
+
+/// Tradução para o inglês dos códigos da base embutida.
+const BUILTIN_EN_US: &[(&str, &str, &[&str])] = &[
+    (
+        "DM_GWPipeline_Gateway_MashupDataAccessError",
+        "The gateway could not read the data source while running the queries (Power Query).",
+        &[
+            "Check that the source (database, folder, API) is reachable from the gateway machine.",
+            "Review the source credentials in the dataset or gateway settings.",
+            "See the error detail in the dataset refresh history to find the failing query.",
+        ],
+    ),
+    (
+        "DM_GWPipeline_Client_GatewayUnreachable",
+        "The service could not communicate with the on-premises data gateway.",
+        &[
+            "Check that the gateway service is running and has internet access.",
+            "Check the gateway status in the Power BI portal (Manage connections and gateways).",
+        ],
+    ),
+    (
+        "DM_GWPipeline_Gateway_DataSourceAccessError",
+        "The gateway reached the data source, but access was denied or failed.",
+        &[
+            "Review the user, password and permissions of the account configured for the source.",
+            "Check firewall rules between the gateway and the source.",
+        ],
+    ),
+    (
+        "DM_GWPipeline_Gateway_TimeoutError",
+        "The data source query exceeded the gateway timeout.",
+        &[
+            "Optimize the queries or reduce the volume (incremental refresh, filters).",
+            "Schedule refreshes at times of lower load on the source.",
+        ],
+    ),
+    (
+        "DMTS_DatasourceHasNoCredentialError",
+        "The dataset data source has no credentials configured.",
+        &["Enter the credentials in Dataset settings > Data source credentials."],
+    ),
+    (
+        "DMTS_OAuthTokenRefreshFailedError",
+        "The OAuth token saved for the data source has expired or was revoked.",
+        &[
+            "Edit the source credentials in the dataset settings and sign in again.",
+            "Consider using a service account or service principal for the source.",
+        ],
+    ),
+    (
+        "ModelRefresh_ShortMessage_ProcessingError",
+        "The model failed to process after reading the data (types, relationships or memory).",
+        &[
+            "Look for columns with inconsistent types or duplicate keys in one-to-many relationships.",
+            "Check the capacity memory usage during the refresh.",
+        ],
+    ),
+    (
+        "PowerBINotAuthorizedException",
+        "The account used by the program has no permission on the workspace or dataset.",
+        &[
+            "Add the account (or service principal) as a member or contributor of the workspace.",
+            "For a service principal, check that API access is enabled in the tenant settings.",
+        ],
+    ),
+    (
+        "ItemNotFound",
+        "The dataset does not exist or is not visible to the account used.",
+        &["Check the GUID in dataset.json (`config diff` and `config prune` help) and the workspace permissions."],
+    ),
+];
+
+fn error_text(description: &str, fixes: &[&str]) -> ErrorText {
+    ErrorText { description: description.to_string(), fixes: fixes.iter().map(|fix| fix.to_string()).collect() }
+}
+
+/// Base completa: a embutida mais o `errors.yaml`, cujas entradas substituem as embutidas de mesmo código.
+pub fn knowledge_base() -> Result<BTreeMap<String, ErrorInfo>, String> {
+
+    let mut base: BTreeMap<String, ErrorInfo> = BUILTIN
+        .iter()
+        .map(|(code, description, fixes)| {
+            let text = error_text(description, fixes);
+            let translations = BUILTIN_EN_US
+                .iter()
+                .filter(|(translated, _, _)| translated == code)
+                .map(|(_, description, fixes)| (Locale::EnUs.tag().to_string(), error_text(description, fixes)))
+                .collect();
+            (code.to_string(), ErrorInfo { description: text.description, fixes: text.fixes, translations })
+        })
+        .collect();
+
+    if Path::new(FILENAME_ERRORS_YAML).exists() {
+        let content = fs::read_to_string(FILENAME_ERRORS_YAML).map_err(|e| format!("Erro ao ler {}.\n{}", FILENAME_ERRORS_YAML, e))?;
+        let custom: BTreeMap<String, ErrorInfo> = serde_yaml::from_str(&content).map_err(|e| format!("Arquivo {} inválido.\n{}", FILENAME_ERRORS_YAML, e))?;
+        base.extend(custom);
+    }
+
+    Ok(base)
+}
+
+/// Explicação do código no idioma configurado, para as notificações de falha. Códigos desconhecidos (ou uma base
+/// inválida) ficam sem explicação.
+pub fn localized_explanation(code: &str) -> Option<String> {
+
+    let base = knowledge_base().ok()?;
+    let (_, info) = base.iter().find(|(known, _)| known.eq_ignore_ascii_case(code))?;
+
+    let locale = locale::locale();
+    let (description, fixes) = info.localized(locale);
+    let label = match locale {
+        Locale::PtBr => "O que fazer",
+        Locale::EnUs => "What to do",
+    };
+
+    let mut text = description.to_string();
+    if !fixes.is_empty() {
+        text.push_str(&format!("\n{}:", label));
+        for fix in fixes {
+            text.push_str(&format!("\n- {}", fix));
+        }
+    }
+
+    Some(text)
+}
+
+/// Exibe a explicação do código; sem correspondência exata, lista os códigos parecidos.
+pub fn explain(code: &str) -> Result<bool, String> {
+
+    let base = knowledge_base()?;
+
+    if let Some((code, info)) = base.iter().find(|(known, _)| known.eq_ignore_ascii_case(code)) {
+        let (description, fixes) = info.localized(locale::locale());
+
+        println!("{}", code.yellow());
+        println!("{}", description);
+
+        if !fixes.is_empty() {
+            println!("\nSugestões:");
+            for fix in fixes {
+                println!("\t- {}", fix);
+            }
+        }
+
+        return Ok(true);
+    }
+
+    println!("Código {} não encontrado na base de erros.", code);
+
+    let term = code.to_lowercase();
+    let similar: Vec<&String> = base.keys().filter(|known| known.to_lowercase().contains(&term) || term.contains(&known.to_lowercase())).collect();
+
+    if !similar.is_empty() {
+        println!("Códigos parecidos:");
+        for known in similar {
+            println!("\t- {}", known);
+        }
+    }
+
+    println!("Explicações próprias podem ser adicionadas em {}.", FILENAME_ERRORS_YAML);
+    Ok(false)
+}
@@ -0,0 +1,106 @@
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+use tonic::{Request, Response, Status};
+
+use crate::config::ensure_writable;
+use crate::history::RefreshRecord;
+use crate::server::ServerState;
+
+pub mod pb {
+    tonic::include_proto!("powerbi_updater");
+}
+
+use pb::updater_server::{Updater, UpdaterServer};
+
+impl From<RefreshRecord> for pb::RefreshEvent {
+    fn from(record: RefreshRecord) -> Self {
+        pb::RefreshEvent {
+            timestamp: record.timestamp.timestamp(),
+            company: record.company,
+            dataset: record.dataset,
+            accepted: record.accepted,
+            status: record.status as u32,
+        }
+    }
+}
+
+struct UpdaterService {
+    state: Arc<ServerState>,
+}
+
+#[tonic::async_trait]
+impl Updater for UpdaterService {
+
+    async fn list_datasets(&self, _request: Request<pb::ListDatasetsRequest>) -> Result<Response<pb::ListDatasetsResponse>, Status> {
+
+        let mut companies: Vec<pb::Company> = self.state.session().entries
+            .iter()
+            .map(|(id, datasets)| pb::Company { id: *id, datasets: datasets.iter().map(|dataset| dataset.id.clone()).collect() })
+            .collect();
+        companies.sort_by_key(|company| company.id);
+
+        Ok(Response::new(pb::ListDatasetsResponse { companies }))
+    }
+
+    async fn refresh_dataset(&self, request: Request<pb::RefreshDatasetRequest>) -> Result<Response<pb::RefreshEvent>, Status> {
+
+        ensure_writable("A atualização").map_err(Status::permission_denied)?;
+
+        let request = request.into_inner();
+        let session = self.state.session();
+
+        // Somente datasets presentes no arquivo de configurações podem ser atualizados.
+        if !session.is_configured(request.company, &request.dataset) {
+            return Err(Status::not_found("Dataset não encontrado."));
+        }
+
+        match session.refresh_one(request.company, &request.dataset).await {
+            Ok(record) => Ok(Response::new(record.into())),
+            Err(e) => Err(Status::unavailable(e)),
+        }
+    }
+
+    async fn refresh_company(&self, request: Request<pb::RefreshCompanyRequest>) -> Result<Response<pb::RefreshCompanyResponse>, Status> {
+
+        ensure_writable("A atualização").map_err(Status::permission_denied)?;
+
+        let company = request.into_inner().company;
+        let session = self.state.session();
+
+        if !session.entries.contains_key(&company) {
+            return Err(Status::not_found(format!("Empresa {} não encontrada.", company)));
+        }
+
+        match session.refresh_company(company).await {
+            Ok(records) => Ok(Response::new(pb::RefreshCompanyResponse {
+                events: records.into_iter().map(Into::into).collect(),
+            })),
+            Err(e) => Err(Status::unavailable(e)),
+        }
+    }
+
+    type StreamEventsStream = Pin<Box<dyn Stream<Item = Result<pb::RefreshEvent, Status>> + Send>>;
+
+    #[allow(clippy::result_large_err)]
+    async fn stream_events(&self, _request: Request<pb::StreamEventsRequest>) -> Result<Response<Self::StreamEventsStream>, Status> {
+
+        // Eventos perdidos por assinantes lentos são descartados.
+        let stream = BroadcastStream::new(self.state.session().events.subscribe())
+            .filter_map(|event| event.ok())
+            .map(|record| Ok(record.into()));
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Inicia o servidor gRPC compartilhando a sessão do modo servidor, inclusive após o `reload`.
+pub async fn serve(bind: SocketAddr, state: Arc<ServerState>) {
+
+    println!("API gRPC disponível em {}", bind);
+
+    let service = UpdaterServer::new(UpdaterService { state });
+
+    if let Err(e) = tonic::transport::Server::builder().add_service(service).serve(bind).await {
+        eprintln!("Falha ao iniciar servidor gRPC.\nErro: {}", e);
+    }
+}
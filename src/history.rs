@@ -0,0 +1,445 @@
+use std::{collections::{HashMap, HashSet}, sync::OnceLock};
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use colored::{ColoredString, Colorize};
+
+use crate::config::{DatasetConfig, GuidEntry};
+use crate::history_store::{open_store, FileStore, HistoryBackend, HistoryStore, FILENAME_HISTORY_JSONL};
+use crate::locale;
+use crate::powerbi::list_refreshes;
+use crate::token::TokenResponse;
+
+static STORE: OnceLock<Box<dyn HistoryStore>> = OnceLock::new();
+/// Atualizações por dia permitidas a cada dataset em capacidade compartilhada (Pro), incluídas as agendadas no serviço.
+pub const PRO_DAILY_REFRESHES: usize = 8;
+/// Quantidade de atualizações pedidas por dataset na importação; a API devolve só as que ainda guarda.
+const BACKFILL_TOP: u32 = 1000;
+
+/// Registro de uma requisição de atualização enviada.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshRecord {
+    pub timestamp: DateTime<Utc>,
+    pub company: u32,
+    pub dataset: String,
+    pub accepted: bool,
+    pub status: u16,
+    /// Preenchidos quando o resultado final da atualização é acompanhado (`--wait`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Início do processamento informado pelo PowerBI, depois da espera na fila.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    /// Tempo total, em milissegundos, das consultas de aquecimento executadas após a conclusão.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warmup_ms: Option<u64>,
+    /// Valor devolvido pela consulta de validação após a conclusão.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation_value: Option<f64>,
+    /// Motivo informado pelo operador ao solicitar a atualização manualmente.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Preenchido quando o dataset não foi enviado (desativado, janela de bloqueio...).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<SkipReason>,
+    /// RequestId da solicitação na API do PowerBI, pedido pelo suporte da Microsoft.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// A resposta do envio se perdeu e a atualização foi reconhecida no histórico do PowerBI pelo horário, não pelo
+    /// identificador enviado; pode ser uma atualização disparada por outra pessoa ou sistema.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub presumed: bool,
+    /// Marca d'água da origem no envio, comparada na próxima atualização.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<String>,
+    /// Código do erro informado pelo PowerBI quando a atualização falha.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+}
+
+/// Motivo de um dataset não ter sido enviado; o registro fica no histórico sem requisição.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// A origem não tem dados novos desde a última atualização.
+    Stale,
+    /// Dataset ou empresa desativados na configuração.
+    Disabled,
+    /// Horário dentro de uma janela de bloqueio.
+    Blackout,
+    /// Pré-condição da atualização não atendida.
+    Gate,
+    /// O dataset já tinha uma atualização em andamento, que a API recusaria.
+    InProgress,
+    /// Atualizado com sucesso há menos de `min_interval_minutes`.
+    Recent,
+}
+
+/// Situação de um registro, usada nas mensagens, resumos e no código de saída.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    Submitted,
+    SkippedStale,
+    SkippedDisabled,
+    SkippedBlackout,
+    SkippedInProgress,
+    SkippedRecent,
+    GateFailed,
+    Denied,
+    Failed,
+    Completed,
+}
+
+impl Outcome {
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Outcome::Submitted => "Enviada",
+            Outcome::SkippedStale => "Ignorada (sem dados novos)",
+            Outcome::SkippedDisabled => "Ignorada (desativada)",
+            Outcome::SkippedBlackout => "Ignorada (janela de bloqueio)",
+            Outcome::SkippedInProgress => "Ignorada (atualização em andamento)",
+            Outcome::SkippedRecent => "Ignorada (atualizada recentemente)",
+            Outcome::GateFailed => "Bloqueada (pré-condição)",
+            Outcome::Denied => "Negada",
+            Outcome::Failed => "Falhou",
+            Outcome::Completed => "Concluída",
+        }
+    }
+
+    pub fn is_skipped(&self) -> bool {
+        matches!(self, Outcome::SkippedStale | Outcome::SkippedDisabled | Outcome::SkippedBlackout | Outcome::SkippedInProgress | Outcome::SkippedRecent)
+    }
+
+    /// Indica se o resultado faz a execução falhar. Ignorados não falham; enviados só contam como sucesso
+    /// quando a execução não acompanha o resultado final.
+    pub fn is_failure(&self, waited: bool) -> bool {
+        match self {
+            Outcome::Completed => false,
+            Outcome::Submitted => waited,
+            Outcome::SkippedStale | Outcome::SkippedDisabled | Outcome::SkippedBlackout | Outcome::SkippedInProgress | Outcome::SkippedRecent => false,
+            Outcome::GateFailed | Outcome::Denied | Outcome::Failed => true,
+        }
+    }
+}
+
+impl RefreshRecord {
+
+    /// Situação do registro, derivada dos campos gravados (registros antigos incluídos).
+    pub fn outcome(&self) -> Outcome {
+        match (self.skipped, self.result.as_deref()) {
+            (Some(SkipReason::Stale), _) => Outcome::SkippedStale,
+            (Some(SkipReason::Disabled), _) => Outcome::SkippedDisabled,
+            (Some(SkipReason::Blackout), _) => Outcome::SkippedBlackout,
+            (Some(SkipReason::Gate), _) => Outcome::GateFailed,
+            (Some(SkipReason::InProgress), _) => Outcome::SkippedInProgress,
+            (Some(SkipReason::Recent), _) => Outcome::SkippedRecent,
+            (None, _) if !self.accepted => Outcome::Denied,
+            (None, Some("Completed")) => Outcome::Completed,
+            (None, Some(_)) => Outcome::Failed,
+            (None, None) => Outcome::Submitted,
+        }
+    }
+
+    /// Situação colorida para o terminal, com o status HTTP da negação ou o resultado da falha.
+    pub fn describe(&self) -> ColoredString {
+        let outcome = self.outcome();
+        match (outcome, self.result.as_deref()) {
+            (Outcome::Denied, _) => format!("{} ({})", outcome.label(), self.status).red(),
+            (Outcome::Failed, Some(result)) => format!("{} ({})", outcome.label(), result).red(),
+            (Outcome::Completed | Outcome::Submitted, _) => outcome.label().green(),
+            _ if outcome.is_skipped() => outcome.label().yellow(),
+            _ => outcome.label().red(),
+        }
+    }
+}
+
+/// Define onde o histórico é guardado (`history` no `dataset.json`); sem chamar, vale o `history.jsonl`.
+pub fn set_backend(backend: &HistoryBackend) -> Result<(), String> {
+    let _ = STORE.set(open_store(backend)?);
+    Ok(())
+}
+
+fn store() -> &'static dyn HistoryStore {
+    STORE.get_or_init(|| Box::new(FileStore::new(FILENAME_HISTORY_JSONL))).as_ref()
+}
+
+/// Acrescenta um registro ao final do histórico.
+pub fn append_record(record: &RefreshRecord) {
+    if let Err(e) = store().append(record) {
+        eprintln!("{}", e);
+    }
+}
+
+/// Na primeira execução (sem histórico), importa as atualizações que a API do PowerBI ainda guarda dos
+/// datasets configurados, para que as estimativas de duração e os relatórios tenham dados desde o início.
+/// Atualizações em andamento ficam de fora. Se nenhum dataset puder ser consultado, a importação é refeita na próxima vez.
+pub async fn backfill(entries: &HashMap<u32, Vec<DatasetConfig>>, token: &TokenResponse) {
+
+    if entries.is_empty() || store().exists() {
+        return;
+    }
+
+    println!("Importando o histórico de atualizações do PowerBI...");
+
+    let mut records = Vec::new();
+    let mut imported = false;
+
+    for (company, datasets) in entries {
+        for dataset in datasets {
+            let refreshes = match list_refreshes(&dataset.id, BACKFILL_TOP, token).await {
+                Ok(refreshes) => refreshes,
+                Err(e) => {
+                    eprintln!("Falha ao importar histórico do dataset {}.\n{}", dataset.id, e);
+                    continue;
+                }
+            };
+            imported = true;
+
+            for refresh in refreshes.into_iter().filter(|refresh| refresh.status != "Unknown") {
+                let Some(started) = refresh.start_time else { continue };
+                records.push(RefreshRecord {
+                    timestamp: started,
+                    company: *company,
+                    dataset: dataset.id.clone(),
+                    accepted: true,
+                    status: 202,
+                    completed_at: refresh.end_time,
+                    started_at: Some(started),
+                    duration_secs: refresh.end_time.map(|ended| (ended - started).num_seconds()),
+                    error_code: refresh.error_code(),
+                    result: Some(refresh.status),
+                    warmup_ms: None,
+                    validation_value: None,
+                    note: None,
+                    skipped: None,
+                    request_id: refresh.request_id,
+                    presumed: false,
+                    watermark: None,
+                });
+            }
+        }
+    }
+
+    if !imported {
+        return;
+    }
+
+    // O histórico é criado mesmo sem registros, marcando a importação como feita.
+    if let Err(e) = store().create() {
+        eprintln!("{}", e);
+        return;
+    }
+
+    records.sort_by_key(|record| record.timestamp);
+    for record in &records {
+        append_record(record);
+    }
+
+    println!("{} atualização(ões) importada(s) para o histórico.", records.len());
+}
+
+/// Lê todos os registros do histórico, ignorando registros inválidos.
+pub fn read_records() -> Vec<RefreshRecord> {
+    store().read_all().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        Vec::new()
+    })
+}
+
+/// Registros de um dataset, sem ler o histórico inteiro quando o armazenamento filtra por dataset.
+pub fn read_dataset_records(dataset: &str) -> Vec<RefreshRecord> {
+    store().read_dataset(dataset).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        Vec::new()
+    })
+}
+
+/// Último registro de cada dataset.
+pub fn last_outcomes() -> HashMap<String, RefreshRecord> {
+
+    let mut outcomes: HashMap<String, RefreshRecord> = HashMap::new();
+
+    for record in read_records() {
+        outcomes.insert(record.dataset.clone(), record);
+    }
+
+    outcomes
+}
+
+/// Mediana da duração (segundos) das atualizações concluídas do dataset enviadas antes de `before`.
+pub fn median_duration(dataset: &str, before: DateTime<Utc>) -> Option<i64> {
+
+    let mut durations: Vec<i64> = read_dataset_records(dataset)
+        .into_iter()
+        .filter(|record| record.result.as_deref() == Some("Completed"))
+        .filter(|record| record.timestamp < before)
+        .filter_map(|record| record.duration_secs)
+        .collect();
+
+    if durations.is_empty() {
+        return None;
+    }
+
+    durations.sort();
+    Some(durations[durations.len() / 2])
+}
+
+/// Resultado da execução anterior a `before` do dataset: concluída (acompanhada) ou aceita (sem acompanhamento).
+/// Execuções ignoradas não contam.
+pub fn previous_outcome(dataset: &str, before: DateTime<Utc>) -> Option<bool> {
+
+    // O resultado final é gravado depois do envio, com o mesmo horário; o último registro prevalece.
+    read_dataset_records(dataset)
+        .into_iter()
+        .rev()
+        .find(|record| record.timestamp < before && !record.outcome().is_skipped())
+        .map(|record| !record.outcome().is_failure(false))
+}
+
+/// Último valor de validação do dataset registrado em um dia (local) anterior ao de `date`.
+pub fn previous_day_validation(dataset: &str, date: DateTime<Utc>) -> Option<f64> {
+
+    let day = locale::local_date(date);
+
+    read_dataset_records(dataset)
+        .into_iter()
+        .rev()
+        .filter(|record| locale::local_date(record.timestamp) < day)
+        .find_map(|record| record.validation_value)
+}
+
+/// Última atualização bem-sucedida do dataset. O resultado acompanhado (`--wait`) é gravado depois do envio com o
+/// mesmo horário; uma atualização que falhou no fim não conta como bem-sucedida.
+fn last_success(dataset: &str) -> Option<RefreshRecord> {
+
+    let mut failed = HashSet::new();
+
+    for record in read_dataset_records(dataset).into_iter().rev().filter(|record| record.skipped.is_none()) {
+        match record.outcome() {
+            Outcome::Completed | Outcome::Submitted if !failed.contains(&record.timestamp) => return Some(record),
+            _ => {
+                failed.insert(record.timestamp);
+            }
+        }
+    }
+
+    None
+}
+
+/// Marca d'água da última atualização bem-sucedida do dataset.
+pub fn last_watermark(dataset: &str) -> Option<String> {
+    last_success(dataset)?.watermark
+}
+
+/// Horário da última atualização bem-sucedida do dataset: a conclusão, quando acompanhada, ou o envio.
+pub fn last_success_at(dataset: &str) -> Option<DateTime<Utc>> {
+    last_success(dataset).map(|record| record.completed_at.unwrap_or(record.timestamp))
+}
+
+/// Atualizações do dataset aceitas no dia (UTC) de `now`, que é quando a cota da capacidade compartilhada é renovada.
+/// O resultado final acompanhado repete o horário do envio, então cada horário conta uma vez.
+pub fn refreshes_on_day(records: &[RefreshRecord], dataset: &str, now: DateTime<Utc>) -> usize {
+    records
+        .iter()
+        .filter(|record| record.dataset == dataset && record.accepted && record.skipped.is_none())
+        .filter(|record| record.timestamp.date_naive() == now.date_naive())
+        .map(|record| record.timestamp)
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// Exibe as últimas `top` atualizações de cada dataset registradas no PowerBI, incluindo as agendadas no serviço e
+/// as feitas por outras ferramentas: início, término, duração, status e erro.
+pub async fn print_service_history(datasets: &[DatasetConfig], top: u32, token: &TokenResponse) {
+
+    for dataset in datasets {
+
+        println!("Dataset {}", dataset.name.as_deref().unwrap_or(&dataset.id));
+
+        let refreshes = match list_refreshes(&dataset.id, top, token).await {
+            Ok(refreshes) => refreshes,
+            Err(e) => {
+                eprintln!("\tFalha ao consultar o histórico.\n{}", e);
+                continue;
+            }
+        };
+
+        if refreshes.is_empty() {
+            println!("\tNenhuma atualização registrada.");
+        }
+
+        for refresh in &refreshes {
+            let started = refresh.start_time.map(locale::datetime).unwrap_or_else(|| "-".to_string());
+            let ended = refresh.end_time.map(locale::datetime).unwrap_or_else(|| "-".to_string());
+            let duration = match (refresh.start_time, refresh.end_time) {
+                (Some(start), Some(end)) => locale::duration((end - start).num_seconds()),
+                _ => "-".to_string(),
+            };
+            let status = match refresh.status.as_str() {
+                "Completed" => refresh.status.green(),
+                "Unknown" => "Em andamento".yellow(),
+                _ => refresh.status.red(),
+            };
+
+            println!("\t{} -> {} ({}): {}", started, ended, duration, status);
+
+            if let Some(code) = refresh.error_code() {
+                println!("\t\t{}: {}", code, refresh.error_description().unwrap_or_default());
+            }
+        }
+    }
+}
+
+/// Último resultado de cada dataset configurado, com a cota restante hoje dos que estão em capacidade compartilhada.
+pub fn print_status(companies: &[GuidEntry]) {
+
+    let outcomes = last_outcomes();
+    let records = read_records();
+    let now = Utc::now();
+
+    for company in companies {
+        for dataset in &company.guid {
+            // Datasets sem capacidade dedicada estão na compartilhada, limitada por dia.
+            let quota = if dataset.capacity.is_none() && company.capacity.is_none() {
+                let remaining = PRO_DAILY_REFRESHES.saturating_sub(refreshes_on_day(&records, &dataset.id, now));
+                format!(" (cota: {} de {} hoje)", remaining, PRO_DAILY_REFRESHES)
+            } else {
+                String::new()
+            };
+
+            match outcomes.get(&dataset.id) {
+                Some(record) => println!("Empresa {} - {}: {} em {}{}", company.id, dataset.id, record.describe(), locale::datetime(record.timestamp), quota),
+                None => println!("Empresa {} - {}: nunca atualizado{}", company.id, dataset.id, quota),
+            }
+        }
+    }
+}
+
+/// Cota restante hoje dos datasets em capacidade compartilhada; os com `capacity` (dedicada) não têm limite.
+pub fn print_quota(targets: &[(u32, DatasetConfig)]) {
+
+    let shared: Vec<&(u32, DatasetConfig)> = targets.iter().filter(|(_, dataset)| dataset.capacity.is_none()).collect();
+    if shared.is_empty() {
+        return;
+    }
+
+    let records = read_records();
+    let now = Utc::now();
+
+    println!("Cota diária da capacidade compartilhada (Pro):");
+
+    for (company, dataset) in shared {
+        let used = refreshes_on_day(&records, &dataset.id, now);
+        let remaining = PRO_DAILY_REFRESHES.saturating_sub(used);
+        let line = format!("\t- Empresa {} - {}: {} de {} restantes hoje", company, dataset.id, remaining, PRO_DAILY_REFRESHES);
+        match remaining {
+            0 => println!("{}", format!("{} (a atualização será recusada)", line).red()),
+            1 => println!("{}", line.yellow()),
+            _ => println!("{}", line),
+        }
+    }
+}
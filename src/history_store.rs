@@ -0,0 +1,284 @@
+use std::{fs::{File, OpenOptions}, io::{Write, BufRead, BufReader}, path::Path, sync::{atomic::{AtomicBool, Ordering}, Mutex}, time::Duration};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::history::RefreshRecord;
+
+pub const FILENAME_HISTORY_JSONL: &str = "history.jsonl";
+const FILENAME_HISTORY_DB: &str = "history.db";
+/// Chave compartilhada entre os agentes e o `serve` que guarda o histórico central.
+pub const ENV_HISTORY_KEY: &str = "PBI_HISTORY_KEY";
+
+pub fn history_key() -> Option<String> {
+    std::env::var(ENV_HISTORY_KEY).ok().filter(|key| !key.is_empty())
+}
+
+/// Onde o histórico de atualizações é guardado (`history` no `dataset.json`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum HistoryBackend {
+    /// Arquivo JSON Lines local (padrão).
+    File {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+    /// Banco SQLite, local ou em um compartilhamento de rede.
+    Sqlite {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+    /// Endpoint `/history` de um `serve` central, compartilhado por vários agentes.
+    Http { url: String },
+}
+
+/// Armazenamento do histórico. As operações são síncronas, como a gravação em arquivo que substituem.
+pub trait HistoryStore: Send + Sync {
+    /// Acrescenta um registro ao final do histórico.
+    fn append(&self, record: &RefreshRecord) -> Result<(), String>;
+    /// Todos os registros, na ordem em que foram gravados.
+    fn read_all(&self) -> Result<Vec<RefreshRecord>, String>;
+    /// Registros de um dataset, na ordem em que foram gravados, para as consultas feitas a cada atualização.
+    fn read_dataset(&self, dataset: &str) -> Result<Vec<RefreshRecord>, String> {
+        Ok(self.read_all()?.into_iter().filter(|record| record.dataset == dataset).collect())
+    }
+    /// Indica se o histórico já existe; sem ele, a primeira execução importa o histórico do PowerBI.
+    fn exists(&self) -> bool;
+    /// Cria o histórico vazio.
+    fn create(&self) -> Result<(), String>;
+}
+
+pub fn open_store(backend: &HistoryBackend) -> Result<Box<dyn HistoryStore>, String> {
+    Ok(match backend {
+        HistoryBackend::File { path } => Box::new(FileStore::new(path.as_deref().unwrap_or(FILENAME_HISTORY_JSONL))),
+        HistoryBackend::Sqlite { path } => Box::new(SqliteStore::open(path.as_deref().unwrap_or(FILENAME_HISTORY_DB))?),
+        HistoryBackend::Http { url } => Box::new(HttpStore::new(url)),
+    })
+}
+
+/// Arquivo local do histórico; `None` quando ele fica em um `serve` central.
+pub fn local_path(backend: Option<&HistoryBackend>) -> Option<&str> {
+    match backend {
+        None => Some(FILENAME_HISTORY_JSONL),
+        Some(HistoryBackend::File { path }) => Some(path.as_deref().unwrap_or(FILENAME_HISTORY_JSONL)),
+        Some(HistoryBackend::Sqlite { path }) => Some(path.as_deref().unwrap_or(FILENAME_HISTORY_DB)),
+        Some(HistoryBackend::Http { .. }) => None,
+    }
+}
+
+pub struct FileStore {
+    path: String,
+}
+
+impl FileStore {
+    pub fn new(path: &str) -> Self {
+        FileStore { path: path.to_string() }
+    }
+}
+
+impl HistoryStore for FileStore {
+
+    fn append(&self, record: &RefreshRecord) -> Result<(), String> {
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Falha ao abrir arquivo de histórico.\nErro: {}", e))?;
+
+        let line = serde_json::to_string(record).unwrap();
+        writeln!(file, "{}", line).map_err(|e| format!("Falha ao gravar histórico.\nErro: {}", e))
+    }
+
+    /// Linhas inválidas são ignoradas.
+    fn read_all(&self) -> Result<Vec<RefreshRecord>, String> {
+
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        Ok(BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect())
+    }
+
+    fn exists(&self) -> bool {
+        Path::new(&self.path).exists()
+    }
+
+    fn create(&self) -> Result<(), String> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map(|_| ())
+            .map_err(|e| format!("Falha ao criar arquivo de histórico.\nErro: {}", e))
+    }
+}
+
+/// Cada registro é guardado como JSON, com o horário e o dataset em colunas próprias para consultas externas.
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+    /// O banco já tinha a tabela ao ser aberto, ou ela foi criada pela importação inicial.
+    existed: AtomicBool,
+}
+
+impl SqliteStore {
+
+    pub fn open(path: &str) -> Result<Self, String> {
+
+        let connection = Connection::open(path).map_err(|e| format!("Falha ao abrir banco de histórico {}.\nErro: {}", path, e))?;
+
+        // Vários agentes podem gravar no mesmo banco; a gravação aguarda a vez em vez de falhar.
+        connection.busy_timeout(Duration::from_secs(30)).map_err(|e| e.to_string())?;
+
+        let existed = connection
+            .query_row("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'history'", [], |row| row.get::<_, String>(0))
+            .optional()
+            .map_err(|e| format!("Falha ao ler banco de histórico {}.\nErro: {}", path, e))?
+            .is_some();
+
+        // O esquema é criado uma vez, na abertura; `exists` continua indicando se o histórico já existia.
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    timestamp TEXT NOT NULL,
+                    dataset TEXT NOT NULL,
+                    record TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS history_dataset ON history (dataset);",
+            )
+            .map_err(|e| format!("Falha ao criar banco de histórico.\nErro: {}", e))?;
+
+        Ok(SqliteStore { connection: Mutex::new(connection), existed: AtomicBool::new(existed) })
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<RefreshRecord>, String> {
+
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(sql).map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params, |row| row.get::<_, String>(0))
+            .map_err(|e| format!("Falha ao ler histórico.\nErro: {}", e))?;
+
+        Ok(rows.filter_map(Result::ok).filter_map(|record| serde_json::from_str(&record).ok()).collect())
+    }
+}
+
+impl HistoryStore for SqliteStore {
+
+    fn append(&self, record: &RefreshRecord) -> Result<(), String> {
+
+        let connection = self.connection.lock().unwrap();
+        connection
+            .execute(
+                "INSERT INTO history (timestamp, dataset, record) VALUES (?1, ?2, ?3)",
+                params![record.timestamp.to_rfc3339(), record.dataset, serde_json::to_string(record).unwrap()],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Falha ao gravar histórico.\nErro: {}", e))
+    }
+
+    fn read_all(&self) -> Result<Vec<RefreshRecord>, String> {
+        self.query("SELECT record FROM history ORDER BY id", [])
+    }
+
+    fn read_dataset(&self, dataset: &str) -> Result<Vec<RefreshRecord>, String> {
+        self.query("SELECT record FROM history WHERE dataset = ?1 ORDER BY id", params![dataset])
+    }
+
+    fn exists(&self) -> bool {
+        self.existed.load(Ordering::Relaxed)
+    }
+
+    fn create(&self) -> Result<(), String> {
+        self.existed.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Histórico central de um `serve` (`GET` e `POST` em `/history`), autenticado com `PBI_HISTORY_KEY`.
+pub struct HttpStore {
+    url: String,
+    key: Option<String>,
+}
+
+impl HttpStore {
+
+    pub fn new(url: &str) -> Self {
+        HttpStore { url: url.trim_end_matches('/').to_string(), key: history_key() }
+    }
+
+    /// O cliente bloqueante não pode rodar diretamente nas tarefas do tokio; `block_in_place` libera a thread.
+    fn request<T>(&self, send: impl FnOnce(reqwest::blocking::Client) -> Result<T, String>) -> Result<T, String> {
+        tokio::task::block_in_place(|| {
+            let client = reqwest::blocking::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .map_err(|e| e.to_string())?;
+            send(client)
+        })
+    }
+
+    fn authorize(&self, request: reqwest::blocking::RequestBuilder) -> reqwest::blocking::RequestBuilder {
+        match &self.key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    fn fetch(&self, query: &[(&str, &str)]) -> Result<Vec<RefreshRecord>, String> {
+        self.request(|client| {
+            let res = self
+                .authorize(client.get(&self.url).query(query))
+                .send()
+                .map_err(|e| format!("Falha ao consultar o histórico central.\nErro: {}", e))?;
+
+            if !res.status().is_success() {
+                return Err(format!("Histórico central indisponível ({}).", res.status()));
+            }
+
+            res.json().map_err(|e| format!("Resposta inválida do histórico central.\nErro: {}", e))
+        })
+    }
+}
+
+impl HistoryStore for HttpStore {
+
+    fn append(&self, record: &RefreshRecord) -> Result<(), String> {
+        self.request(|client| {
+            let res = self
+                .authorize(client.post(&self.url))
+                .json(record)
+                .send()
+                .map_err(|e| format!("Falha ao enviar registro ao histórico central.\nErro: {}", e))?;
+
+            if !res.status().is_success() {
+                return Err(format!("Histórico central recusou o registro ({}).", res.status()));
+            }
+
+            Ok(())
+        })
+    }
+
+    fn read_all(&self) -> Result<Vec<RefreshRecord>, String> {
+        self.fetch(&[])
+    }
+
+    /// O filtro é feito pelo servidor; os registros são conferidos de novo para o caso de um `serve` sem o filtro.
+    fn read_dataset(&self, dataset: &str) -> Result<Vec<RefreshRecord>, String> {
+        Ok(self.fetch(&[("dataset", dataset)])?.into_iter().filter(|record| record.dataset == dataset).collect())
+    }
+
+    /// O histórico central existe quando já tem registros, de qualquer agente.
+    fn exists(&self) -> bool {
+        self.read_all().map(|records| !records.is_empty()).unwrap_or(true)
+    }
+
+    fn create(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
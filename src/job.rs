@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use colored::Colorize;
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::config::JobConfig;
+use crate::history::RefreshRecord;
+use crate::lineage::refresh_dataflow_and_wait;
+use crate::monitor::WaitOptions;
+use crate::session::{round_robin, Session};
+
+/// Executa as etapas do job em sequência, acompanhando cada atualização até o fim.
+/// Uma etapa com falha interrompe as seguintes. Devolve os registros e se todas as etapas foram concluídas.
+pub async fn run_stages(session: &Arc<Session>, job: &JobConfig, note: Option<&str>, wait: WaitOptions) -> Result<(Vec<RefreshRecord>, bool), String> {
+
+    let mut records = Vec::new();
+
+    for (index, stage) in job.stages.iter().enumerate() {
+
+        println!("{}", format!("Etapa {}: {}", index + 1, stage.name).cyan());
+
+        let targets = round_robin(session.stage_targets(job, stage));
+        let slots = stage.concurrency.unwrap_or(stage.dataflows.len() + targets.len()).max(1);
+        let semaphore = Arc::new(Semaphore::new(slots));
+
+        let mut dataflows: JoinSet<Result<bool, String>> = JoinSet::new();
+        for dataflow in stage.dataflows.clone() {
+            let (session, semaphore) = (session.clone(), semaphore.clone());
+            dataflows.spawn(async move {
+                let _permit = semaphore.acquire_owned().await.unwrap();
+                refresh_dataflow_and_wait(&session, &dataflow.workspace, &dataflow.id, wait).await
+            });
+        }
+
+        let mut datasets: JoinSet<Result<Vec<RefreshRecord>, String>> = JoinSet::new();
+        for mut target in targets {
+            target.1.note = note.map(str::to_string);
+            let (session, semaphore) = (session.clone(), semaphore.clone());
+            datasets.spawn(async move {
+                let _permit = semaphore.acquire_owned().await.unwrap();
+                session.run(vec![target], Some(wait)).await
+            });
+        }
+
+        let mut success = true;
+
+        while let Some(result) = dataflows.join_next().await {
+            match result {
+                Ok(Ok(completed)) => success &= completed,
+                Ok(Err(e)) => {
+                    eprintln!("{}", e);
+                    success = false;
+                }
+                Err(e) => {
+                    eprintln!("Falha na tarefa de atualização.\n{}", e);
+                    success = false;
+                }
+            }
+        }
+
+        while let Some(result) = datasets.join_next().await {
+            match result {
+                Ok(Ok(stage_records)) => {
+                    success &= !stage_records.is_empty() && stage_records.iter().all(|record| !record.outcome().is_failure(true));
+                    records.extend(stage_records);
+                }
+                Ok(Err(e)) => {
+                    eprintln!("{}", e);
+                    success = false;
+                }
+                Err(e) => {
+                    eprintln!("Falha na tarefa de atualização.\n{}", e);
+                    success = false;
+                }
+            }
+        }
+
+        if !success {
+            eprintln!("{}", format!("Etapa \"{}\" não foi concluída; as etapas seguintes não serão executadas.", stage.name).red());
+            return Ok((records, false));
+        }
+    }
+
+    Ok((records, true))
+}
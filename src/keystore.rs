@@ -0,0 +1,39 @@
+use keyring::Entry;
+
+/// Serviço sob o qual o token é guardado no cofre do sistema.
+const KEYRING_SERVICE: &str = "power_bi_updater";
+/// Conta com a quantidade de partes do token do perfil padrão; perfis nomeados usam `token@<perfil>`.
+pub const KEYRING_ACCOUNT: &str = "token";
+/// O Gerenciador de Credenciais do Windows limita o tamanho de cada senha, então o token é dividido em partes.
+const PART_SIZE: usize = 1000;
+
+fn entry(account: &str) -> Result<Entry, String> {
+    Entry::new(KEYRING_SERVICE, account).map_err(|e| format!("Falha ao acessar o cofre de credenciais.\nErro: {}", e))
+}
+
+/// Conteúdo guardado no cofre (Gerenciador de Credenciais, Keychain ou Secret Service), se houver.
+pub fn load(account: &str) -> Option<String> {
+
+    let parts: usize = entry(account).ok()?.get_password().ok()?.parse().ok()?;
+
+    (1..=parts)
+        .map(|part| entry(&format!("{}-{}", account, part)).ok()?.get_password().ok())
+        .collect()
+}
+
+/// Guarda o conteúdo no cofre, substituindo o anterior.
+pub fn save(account: &str, content: &str) -> Result<(), String> {
+
+    let chars: Vec<char> = content.chars().collect();
+    let parts: Vec<String> = chars.chunks(PART_SIZE).map(|chunk| chunk.iter().collect()).collect();
+
+    for (index, part) in parts.iter().enumerate() {
+        entry(&format!("{}-{}", account, index + 1))?
+            .set_password(part)
+            .map_err(|e| format!("Falha ao gravar token no cofre de credenciais.\nErro: {}", e))?;
+    }
+
+    entry(account)?
+        .set_password(&parts.len().to_string())
+        .map_err(|e| format!("Falha ao gravar token no cofre de credenciais.\nErro: {}", e))
+}
@@ -0,0 +1,210 @@
+use std::{cmp::Reverse, collections::{BTreeMap, HashMap}, sync::Arc};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+
+use crate::config::DatasetConfig;
+use crate::monitor::WaitOptions;
+use crate::powerbi::{list_app_reports, list_apps, list_dataflow_transactions, list_datasets, list_groups, list_reports, list_upstream_dataflows, refresh_dataflow};
+use crate::session::Session;
+
+/// Status da transação do dataflow enquanto ainda está em execução.
+const DATAFLOW_IN_PROGRESS: &str = "InProgress";
+
+/// Dataset que depende do dataflow.
+pub struct DependentDataset {
+    pub id: String,
+    pub name: String,
+    pub workspace: String,
+    /// Empresa em que o dataset está configurado, se estiver.
+    pub company: Option<u32>,
+}
+
+/// Procura, em todos os workspaces acessíveis, os datasets que dependem do dataflow.
+pub async fn dependent_datasets(session: &Session, dataflow_id: &str) -> Result<Vec<DependentDataset>, String> {
+
+    let token = session.current_token().await?;
+    let mut dependents = Vec::new();
+
+    for group in list_groups(&token).await? {
+
+        let links: Vec<_> = list_upstream_dataflows(&group.id, &token)
+            .await?
+            .into_iter()
+            .filter(|link| link.dataflow_object_id.eq_ignore_ascii_case(dataflow_id))
+            .collect();
+
+        if links.is_empty() {
+            continue;
+        }
+
+        let datasets = list_datasets(&group.id, &token).await?;
+
+        for link in links {
+            let name = datasets
+                .iter()
+                .find(|dataset| dataset.id.eq_ignore_ascii_case(&link.dataset_object_id))
+                .map(|dataset| dataset.name.clone())
+                .unwrap_or_default();
+
+            dependents.push(DependentDataset {
+                company: session.find_company(&link.dataset_object_id),
+                id: link.dataset_object_id,
+                name,
+                workspace: group.name.clone(),
+            });
+        }
+    }
+
+    Ok(dependents)
+}
+
+/// Aguarda a transação mais recente do dataflow terminar; devolve o status final.
+/// Com `since`, execuções iniciadas antes dele são ignoradas, aguardando a que acabou de ser solicitada aparecer.
+async fn wait_for_dataflow(session: &Session, workspace: &str, dataflow_id: &str, since: Option<DateTime<Utc>>, options: WaitOptions) -> Result<String, String> {
+
+    let started = tokio::time::Instant::now();
+    let mut due = started;
+
+    loop {
+        session.poller.wait_turn(due).await;
+        due = tokio::time::Instant::now() + options.poll_interval;
+
+        let token = session.current_token().await?;
+        let mut transactions = list_dataflow_transactions(workspace, dataflow_id, &token).await?;
+        transactions.retain(|transaction| since.is_none_or(|since| transaction.start_time.is_some_and(|start| start >= since)));
+        transactions.sort_by_key(|transaction| Reverse(transaction.start_time));
+
+        match transactions.into_iter().next() {
+            Some(transaction) if transaction.status != DATAFLOW_IN_PROGRESS => return Ok(transaction.status),
+            Some(_) => println!("Dataflow {} em execução, aguardando...", dataflow_id),
+            None if since.is_some() => println!("Dataflow {} aguardando início da execução...", dataflow_id),
+            None => return Err(format!("Nenhuma execução encontrada para o dataflow {}.", dataflow_id)),
+        }
+
+        if started.elapsed() > options.timeout {
+            return Err(format!("Tempo de espera esgotado para o dataflow {}.", dataflow_id));
+        }
+    }
+}
+
+/// Lista os datasets dependentes do dataflow e, opcionalmente, os atualiza após o término do dataflow.
+pub async fn run_dataflow_command(session: Arc<Session>, dataflow_id: &str, workspace: &str, refresh: bool, wait: Option<WaitOptions>) -> Result<bool, String> {
+
+    let dependents = dependent_datasets(&session, dataflow_id).await?;
+
+    if dependents.is_empty() {
+        println!("Nenhum dataset depende do dataflow {}.", dataflow_id);
+        return Ok(true);
+    }
+
+    println!("Datasets dependentes do dataflow {}:", dataflow_id);
+
+    for dependent in &dependents {
+        let company = match dependent.company {
+            Some(company) => format!("empresa {}", company).green(),
+            None => "não configurado".yellow(),
+        };
+        println!("\t- {} / {} ({}) [{}]", dependent.workspace, dependent.name, dependent.id, company);
+    }
+
+    if !refresh {
+        return Ok(true);
+    }
+
+    let status = wait_for_dataflow(&session, workspace, dataflow_id, None, wait.unwrap_or_default()).await?;
+
+    if status != "Success" {
+        eprintln!("Dataflow {} terminou com status {}; datasets dependentes não serão atualizados.", dataflow_id, status);
+        return Ok(false);
+    }
+
+    // Datasets fora do arquivo de configurações são atualizados sem empresa (0).
+    let targets = dependents
+        .iter()
+        .map(|dependent| (dependent.company.unwrap_or(0), session.dataset_config(&dependent.id).cloned().unwrap_or_else(|| DatasetConfig::new(&dependent.id))))
+        .collect();
+
+    let records = session.run(targets, wait).await?;
+
+    Ok(records.iter().all(|record| record.accepted))
+}
+
+/// Solicita a atualização do dataflow e aguarda o término; indica se terminou com sucesso.
+pub async fn refresh_dataflow_and_wait(session: &Session, workspace: &str, dataflow_id: &str, options: WaitOptions) -> Result<bool, String> {
+
+    // Margem para diferenças de relógio entre a máquina e o serviço.
+    let since = Utc::now() - chrono::Duration::minutes(1);
+
+    let token = session.current_token().await?;
+    refresh_dataflow(workspace, dataflow_id, &token).await.map_err(|e| format!("Falha ao solicitar atualização do dataflow {}.\n{}", dataflow_id, e))?;
+    println!("Dataflow {}: {}", dataflow_id, "Aceita".green());
+
+    let status = wait_for_dataflow(session, workspace, dataflow_id, Some(since), options).await?;
+
+    if status == "Success" {
+        println!("Dataflow {}: {}", dataflow_id, "Concluído".green());
+        Ok(true)
+    } else {
+        eprintln!("Dataflow {} terminou com status {}.", dataflow_id, status);
+        Ok(false)
+    }
+}
+
+/// Relatórios e aplicativos construídos sobre cada dataset, indexados pelo ID do dataset.
+pub async fn impacted_items(session: &Session, datasets: &[String]) -> Result<HashMap<String, Vec<String>>, String> {
+
+    let token = session.current_token().await?;
+    let mut impact: HashMap<String, Vec<String>> = HashMap::new();
+
+    let mut add = |dataset_id: Option<String>, item: String| {
+        if let Some(dataset) = dataset_id.and_then(|id| datasets.iter().find(|dataset| dataset.eq_ignore_ascii_case(&id))) {
+            impact.entry(dataset.clone()).or_default().push(item);
+        }
+    };
+
+    for group in list_groups(&token).await? {
+        for report in list_reports(&group.id, &token).await? {
+            add(report.dataset_id, format!("Relatório {} / {}", group.name, report.name));
+        }
+    }
+
+    for app in list_apps(&token).await? {
+        for report in list_app_reports(&app.id, &token).await? {
+            add(report.dataset_id, format!("Aplicativo {} / {}", app.name, report.name));
+        }
+    }
+
+    Ok(impact)
+}
+
+/// Exibe, antes das atualizações, o que depende de cada dataset: o alcance de uma falha ou demora.
+pub async fn print_impact_preview(session: &Session, targets: &[(u32, DatasetConfig)]) -> Result<(), String> {
+
+    let datasets: Vec<String> = targets.iter().map(|(_, dataset)| dataset.id.clone()).collect();
+    let impact = impacted_items(session, &datasets).await?;
+
+    let mut by_company: BTreeMap<u32, Vec<&DatasetConfig>> = BTreeMap::new();
+    for (company, dataset) in targets {
+        by_company.entry(*company).or_default().push(dataset);
+    }
+
+    println!("Impacto das atualizações:");
+
+    for (company, datasets) in by_company {
+        println!("Empresa {}", company);
+
+        for dataset in datasets {
+            match impact.get(&dataset.id) {
+                Some(items) => {
+                    println!("\t- {}: {} item(ns)", dataset.id, items.len());
+                    for item in items {
+                        println!("\t\t{}", item);
+                    }
+                }
+                None => println!("\t- {}: {}", dataset.id, "nenhum relatório encontrado".yellow()),
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use std::{collections::HashMap, sync::Arc};
+use axum::{extract::{Path, Query, State}, response::{Html, IntoResponse}, http::StatusCode};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD as BASE64};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::config::ensure_writable;
+use crate::server::{escape_html, ServerState};
+
+/// Chave usada para assinar e validar os links de atualização.
+pub const ENV_LINKS_KEY: &str = "PBI_LINKS_KEY";
+
+pub fn links_key() -> Option<String> {
+    std::env::var(ENV_LINKS_KEY).ok().filter(|key| !key.is_empty())
+}
+
+fn mac(key: &str, company: u32, dataset: &str, expires: i64, nonce: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).unwrap();
+    mac.update(format!("{}\n{}\n{}\n{}", company, dataset, expires, nonce).as_bytes());
+    mac
+}
+
+/// Gera o link assinado que atualiza o dataset uma única vez até `expires`.
+pub fn signed_link(key: &str, base_url: &str, company: u32, dataset: &str, expires: DateTime<Utc>) -> String {
+
+    // O instante da geração basta para diferenciar links do mesmo dataset.
+    let nonce = format!("{:x}", Utc::now().timestamp_nanos_opt().unwrap_or_default());
+    let signature = BASE64.encode(mac(key, company, dataset, expires.timestamp(), &nonce).finalize().into_bytes());
+
+    format!("{}/link/{}/{}?expires={}&nonce={}&sig={}", base_url.trim_end_matches('/'), company, dataset, expires.timestamp(), nonce, signature)
+}
+
+fn page(status: StatusCode, message: &str) -> (StatusCode, Html<String>) {
+    (status, Html(format!(
+        "<!DOCTYPE html>\n<html lang=\"pt-BR\">\n<head><meta charset=\"utf-8\"><title>Atualização</title></head>\n<body><p>{}</p></body>\n</html>\n",
+        escape_html(message),
+    )))
+}
+
+/// Valida o link (assinatura, validade e uso único) e dispara a atualização do dataset.
+pub async fn handle_link(State(state): State<Arc<ServerState>>, Path((company, dataset)): Path<(u32, String)>, Query(query): Query<HashMap<String, String>>) -> impl IntoResponse {
+
+    let Some(key) = &state.links_key else {
+        return page(StatusCode::NOT_FOUND, "Links de atualização desativados.");
+    };
+
+    if let Err(e) = ensure_writable("A atualização") {
+        return page(StatusCode::FORBIDDEN, &e);
+    }
+
+    let (Some(expires), Some(nonce), Some(signature)) = (query.get("expires"), query.get("nonce"), query.get("sig")) else {
+        return page(StatusCode::BAD_REQUEST, "Link incompleto.");
+    };
+
+    let Ok(expires) = expires.parse::<i64>() else {
+        return page(StatusCode::BAD_REQUEST, "Link inválido.");
+    };
+
+    let valid = BASE64
+        .decode(signature)
+        .is_ok_and(|signature| mac(key, company, &dataset, expires, nonce).verify_slice(&signature).is_ok());
+
+    if !valid {
+        return page(StatusCode::FORBIDDEN, "Link inválido.");
+    }
+
+    if Utc::now().timestamp() > expires {
+        return page(StatusCode::FORBIDDEN, "Link expirado.");
+    }
+
+    // Vale enquanto o servidor estiver em execução; a validade limita o reaproveitamento após reinícios.
+    if !state.used_links.lock().await.insert(signature.clone()) {
+        return page(StatusCode::FORBIDDEN, "Link já utilizado.");
+    }
+
+    match state.session().dataset_target(company, &dataset) {
+        Ok(targets) => {
+            state.spawn_run(targets);
+            page(StatusCode::OK, "Atualização solicitada. Os dados estarão disponíveis ao término do processamento.")
+        }
+        Err(e) => page(StatusCode::NOT_FOUND, &e),
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::OnceLock;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// Idioma usado na formatação de datas, durações e números exibidos ao operador.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Locale {
+    #[default]
+    #[serde(rename = "pt-BR")]
+    PtBr,
+    #[serde(rename = "en-US")]
+    EnUs,
+}
+
+impl Locale {
+    /// Identificador do idioma, como no `dataset.json` (`pt-BR`, `en-US`).
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Locale::PtBr => "pt-BR",
+            Locale::EnUs => "en-US",
+        }
+    }
+}
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+static TIMEZONE: OnceLock<Tz> = OnceLock::new();
+
+/// Define o idioma da execução; chamadas posteriores são ignoradas.
+pub fn set_locale(locale: Locale) {
+    let _ = LOCALE.set(locale);
+}
+
+/// Define o fuso usado na exibição e, na falta de um fuso da empresa, nos horários configurados.
+/// Sem fuso definido vale o fuso do sistema.
+pub fn set_timezone(timezone: Tz) {
+    let _ = TIMEZONE.set(timezone);
+}
+
+/// Idioma da execução.
+pub fn locale() -> Locale {
+    LOCALE.get().copied().unwrap_or_default()
+}
+
+fn format_in_zone(value: DateTime<Utc>, format: &str) -> String {
+    match TIMEZONE.get() {
+        Some(timezone) => value.with_timezone(timezone).format(format).to_string(),
+        None => value.with_timezone(&Local).format(format).to_string(),
+    }
+}
+
+/// Data e hora no fuso configurado.
+pub fn datetime(value: DateTime<Utc>) -> String {
+    match locale() {
+        Locale::PtBr => format_in_zone(value, "%d/%m/%Y %H:%M:%S"),
+        Locale::EnUs => format_in_zone(value, "%m/%d/%Y %I:%M:%S %p"),
+    }
+}
+
+/// Horário no fuso configurado.
+pub fn time(value: DateTime<Utc>) -> String {
+    match locale() {
+        Locale::PtBr => format_in_zone(value, "%H:%M"),
+        Locale::EnUs => format_in_zone(value, "%I:%M %p"),
+    }
+}
+
+/// Data do instante no fuso configurado.
+pub fn local_date(value: DateTime<Utc>) -> NaiveDate {
+    match TIMEZONE.get() {
+        Some(timezone) => value.with_timezone(timezone).date_naive(),
+        None => value.with_timezone(&Local).date_naive(),
+    }
+}
+
+fn next_in_zone<Z: TimeZone>(zone: &Z, time: NaiveTime, from: DateTime<Utc>) -> DateTime<Utc> {
+
+    let local = from.with_timezone(zone);
+    let mut date = local.date_naive();
+
+    if local.time() > time {
+        date = match date.succ_opt() {
+            Some(next) => next,
+            None => return from + Duration::days(1),
+        };
+    }
+
+    let naive = date.and_time(time);
+
+    // Horário que não existe no dia da mudança para o horário de verão (ex.: 02:30): vale o instante após o salto.
+    let next = zone
+        .from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| zone.from_local_datetime(&(naive + Duration::hours(1))).earliest());
+
+    match next {
+        Some(next) => next.with_timezone(&Utc),
+        None => from + Duration::days(1),
+    }
+}
+
+/// Próxima ocorrência do horário a partir de `from`, no fuso informado (empresa) ou no configurado.
+pub fn next_occurrence(time: NaiveTime, from: DateTime<Utc>, zone: Option<Tz>) -> DateTime<Utc> {
+    match zone.or_else(|| TIMEZONE.get().copied()) {
+        Some(zone) => next_in_zone(&zone, time, from),
+        None => next_in_zone(&Local, time, from),
+    }
+}
+
+pub fn date(value: NaiveDate) -> String {
+    match locale() {
+        Locale::PtBr => value.format("%d/%m/%Y").to_string(),
+        Locale::EnUs => value.format("%m/%d/%Y").to_string(),
+    }
+}
+
+/// Duração legível, ex.: `1h 05min` ou `42s`.
+pub fn duration(seconds: i64) -> String {
+
+    let (hours, minutes, seconds) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+
+    if hours > 0 {
+        format!("{}h {:02}min", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}min {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Número com separadores decimal e de milhar do idioma.
+pub fn number(value: f64, decimals: usize) -> String {
+
+    let formatted = format!("{:.*}", decimals, value.abs());
+    let (integer, fraction) = formatted.split_once('.').unwrap_or((&formatted, ""));
+
+    let (thousands, decimal) = match locale() {
+        Locale::PtBr => ('.', ','),
+        Locale::EnUs => (',', '.'),
+    };
+
+    let mut grouped = String::new();
+    for (index, digit) in integer.chars().enumerate() {
+        if index > 0 && (integer.len() - index) % 3 == 0 {
+            grouped.push(thousands);
+        }
+        grouped.push(digit);
+    }
+
+    let sign = if value < 0.0 { "-" } else { "" };
+
+    if fraction.is_empty() {
+        format!("{}{}", sign, grouped)
+    } else {
+        format!("{}{}{}{}", sign, grouped, decimal, fraction)
+    }
+}
+
+/// Separador de colunas do CSV; com vírgula decimal, o padrão das planilhas é o ponto e vírgula.
+pub fn csv_separator() -> &'static str {
+    match locale() {
+        Locale::PtBr => ";",
+        Locale::EnUs => ",",
+    }
+}
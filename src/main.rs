@@ -1,369 +1,1048 @@
-use core::panic;
-use std::{fs::File, io::{Write, Read, self}, process::exit, collections::HashMap, env};
-use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
-use colored::Colorize;
-use dialoguer::{Select, theme::ColorfulTheme, Input};
-use figlet_rs::FIGfont;
-use config::{Config, File as ConfigFile};
-
-const FILENAME_TOKEN_JSON: &str = ".token";
-const FILENAME_CONFIG_JSON: &str = "dataset.json";
-const FILENAME_SECRETS_TOML: &str = "secrets.toml";
-const FONT: &'static str = include_str!("doom.flf");
-
-#[derive(Debug, Serialize, Deserialize)]
-struct TokenResponse {
-    token_type: String,
-    expires_on: String,
-    access_token: String,
-}
+mod auth;
+mod azure_cli;
+mod backup;
+mod cache;
+mod cancel;
+mod certificate;
+mod claims;
+mod config;
+mod control;
+mod deployment;
+mod device_code;
+mod eventgrid;
+mod explain;
+mod grpc;
+mod history;
+mod history_store;
+mod job;
+mod keystore;
+mod lineage;
+mod links;
+mod locale;
+mod maintenance;
+mod managed_identity;
+mod menu;
+mod monitor;
+mod notify;
+mod powerbi;
+mod profiles;
+mod queue;
+mod refresh;
+mod report;
+mod runbook;
+mod server;
+mod session;
+mod setup;
+mod simulation;
+mod sops;
+mod state;
+mod token;
+mod token_crypt;
+mod tray;
+mod troubleshoot;
+mod ui;
+mod vault;
+mod watermark;
+
+use std::{process::exit, net::SocketAddr, sync::Arc, time::Duration};
+use chrono::{NaiveDate, NaiveTime};
+use clap::{Parser, Subcommand};
+use dialoguer::{Confirm, theme::ColorfulTheme};
 
+use crate::config::{guid_entries_map, read_config_file, read_manifest, read_secrets_file, AppConfig, Secrets};
+use crate::queue::QueueBackend;
+use crate::report::ReportFormat;
+use crate::monitor::WaitOptions;
+use crate::powerbi::set_api_base_url;
+use crate::server::ServeOptions;
+use crate::session::Session;
+use crate::simulation::Scenario;
+use crate::token::{load_token, TokenResponse};
+use crate::ui::{pause, welcome_message};
 
-#[derive(Debug, Serialize, Deserialize)]
-struct GuidEntry {
-    id: u32,
-    #[serde(default)]
-    guid: Vec<String>,
+#[derive(Parser)]
+#[command(version, about = "Envia requisições de atualização dos relatórios publicados via PowerBi.")]
+struct Cli {
+    /// Cria um dataset.json modelo e encerra.
+    #[arg(long)]
+    init: bool,
+    /// Explica um código de erro do PowerBI (ex.: DM_GWPipeline_Gateway_MashupDataAccessError) e encerra.
+    #[arg(long, value_name = "CODIGO")]
+    explain: Option<String>,
+    /// Consulta novamente a API em vez de usar os metadados guardados em cache.
+    #[arg(long, global = true)]
+    refresh_cache: bool,
+    /// Perfil de credenciais (tabela do `secrets.toml`) usado na execução, no lugar do perfil padrão.
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Modo somente leitura, para auditoria e suporte: bloqueia atualizações e alterações, permitindo apenas
+    /// listas, status e histórico.
+    #[arg(long, global = true)]
+    read_only: bool,
+    /// Dispensa o banner e o "Pressione ENTER", para agendadores e scripts.
+    #[arg(long, global = true)]
+    quiet: bool,
+    /// Segundos que o "Pressione ENTER" aguarda antes de continuar sozinho (prevalece sobre `pause_timeout_seconds`).
+    #[arg(long, global = true, value_name = "SEGUNDOS")]
+    pause_timeout: Option<u64>,
+    /// Grava as atualizações feitas no menu interativo em um runbook, para reproduzir com `run runbook`.
+    #[arg(long, value_name = "ARQUIVO")]
+    record: Option<String>,
+    /// Simula um cenário de falha em uma API local, sem acessar o PowerBI, para treinamento e para testar os avisos.
+    #[arg(long, global = true, value_enum, value_name = "CENARIO")]
+    scenario: Option<Scenario>,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
+/// Opções de acompanhamento das atualizações até o resultado final.
+#[derive(clap::Args)]
+struct WaitArgs {
+    /// Aguarda o término de cada atualização aceita, alertando sobre prazos não cumpridos.
+    #[arg(long)]
+    wait: bool,
+    /// Intervalo, em segundos, entre as consultas de status.
+    #[arg(long, default_value_t = 30)]
+    poll_interval: u64,
+    /// Tempo máximo de espera, em minutos, por atualização.
+    #[arg(long, default_value_t = 180)]
+    wait_timeout: u64,
+    /// Tempo, em minutos, na fila sem iniciar após o qual a atualização é considerada travada.
+    #[arg(long, default_value_t = 15)]
+    max_queue_wait: u64,
+    /// Avisa quando uma atualização em andamento passa deste múltiplo da sua duração típica.
+    #[arg(long, default_value_t = 2.0)]
+    watchdog_factor: f64,
+}
 
-impl Default for TokenResponse {
-    fn default() -> Self {
-        TokenResponse {
-            token_type: String::new(),
-            expires_on: String::new(),
-            access_token: String::new(),
-        }
+impl WaitArgs {
+    fn options(&self) -> Option<WaitOptions> {
+        self.wait.then(|| WaitOptions {
+            poll_interval: Duration::from_secs(self.poll_interval),
+            timeout: Duration::from_secs(self.wait_timeout * 60),
+            max_queue_wait: Duration::from_secs(self.max_queue_wait * 60),
+            watchdog_factor: self.watchdog_factor,
+        })
     }
 }
 
+#[derive(Subcommand)]
+enum StateCommand {
+    /// Empacota dataset.json, errors.yaml, histórico local e cópias do dataset.json.
+    Export {
+        /// Arquivo zip gerado.
+        file: String,
+        /// Runbook incluído no pacote; pode ser repetido.
+        #[arg(long)]
+        runbook: Vec<String>,
+        /// Inclui o secrets.toml, cifrado com a senha do pacote.
+        #[arg(long)]
+        secrets: bool,
+        /// Inclui o cache de tokens, cifrado com a senha do pacote.
+        #[arg(long)]
+        tokens: bool,
+    },
+    /// Restaura um pacote na pasta do programa.
+    Import {
+        file: String,
+        /// Substitui os arquivos existentes (o dataset.json atual ganha uma cópia em backups).
+        #[arg(long)]
+        force: bool,
+    },
+}
 
-async fn acquire_new_token(secrets: HashMap<String, String>) -> Result<TokenResponse, String>{
+#[derive(Subcommand)]
+enum ControlCommand {
+    /// Relê o dataset.json (empresas, datasets, jobs e gatilhos) sem interromper as atualizações em andamento.
+    Reload,
+    /// Suspende as atualizações agendadas e por eventos.
+    Pause,
+    /// Retoma as atualizações agendadas e por eventos.
+    Resume,
+    /// Executa um job do dataset.json.
+    RunJob {
+        name: String,
+    },
+    /// Situação do servidor: pausa, próxima execução e configurações carregadas.
+    Status,
+}
 
-    let url = "https://login.windows.net/common/oauth2/token";
-    let params = [
-        ("client_id", secrets.get("client_id")),
-        ("grant_type", secrets.get("grant_type")),
-        ("resource", secrets.get("resource")),
-        ("username", secrets.get("username")),
-        ("password", secrets.get("password"))
-    ];
+impl ControlCommand {
 
-    let client = reqwest::Client::new();
+    /// Linha enviada pelo socket de controle.
+    fn line(&self) -> String {
+        match self {
+            ControlCommand::Reload => "reload".to_string(),
+            ControlCommand::Pause => "pause".to_string(),
+            ControlCommand::Resume => "resume".to_string(),
+            ControlCommand::RunJob { name } => format!("run-job {}", name),
+            ControlCommand::Status => "status".to_string(),
+        }
+    }
+}
 
-    let res = client.post(url)
-    .body("Something")
-    .form(&params)
-    .send()
-    .await
-    .expect("send");
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Remove, com confirmação, datasets que não existem mais ou deixaram de ser atualizáveis.
+    Prune,
+    /// Compara o dataset.json com os datasets existentes nos workspaces configurados.
+    Diff,
+    /// Restaura uma cópia anterior do dataset.json.
+    Rollback {
+        /// Arquivo da cópia (em `backups/`); sem ele, a cópia é escolhida na lista.
+        file: Option<String>,
+    },
+}
 
-    if res.status().is_success() {
-        let token_response: TokenResponse = res.json().await.expect("Falha ao converter JSON.");
-        Ok(token_response)
-    } else {
-        let text_response: String = res.text().await.expect("Falha ao receber mensagem de erro.");
-        Err(text_response)
+#[derive(Subcommand)]
+enum ReportCommand {
+    /// Taxa de sucesso, duração média e cumprimento de prazos por dataset.
+    Sla {
+        /// Data inicial (AAAA-MM-DD).
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Data final (AAAA-MM-DD), inclusive.
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        #[arg(long, value_enum, default_value = "csv")]
+        format: ReportFormat,
+        /// Arquivo gerado; por padrão `sla.csv` ou `sla.html`.
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Agenda (.ics) com os horários de atualização automática de cada empresa.
+    Calendar {
+        #[arg(long, default_value = "agenda.ics")]
+        output: String,
+    },
+}
 
-    }
+#[derive(Subcommand)]
+enum RunCommand {
+    /// Reproduz as atualizações de uma sessão interativa gravada com `--record`.
+    Runbook {
+        /// Arquivo do runbook (ex.: `fechamento.json`).
+        file: String,
+        #[command(flatten)]
+        wait: WaitArgs,
+    },
+}
 
+#[derive(Subcommand)]
+enum Command {
+    /// Assistente para criar os arquivos secrets.toml e dataset.json.
+    Init,
+    /// Atualiza as empresas informadas (ou todas) sem o menu interativo.
+    Refresh {
+        /// ID da empresa; pode ser repetido. Sem o parâmetro, todas as empresas são atualizadas.
+        #[arg(long = "company")]
+        companies: Vec<u32>,
+        /// Manifesto YAML com a especificação completa da execução, no lugar do dataset.json.
+        #[arg(long)]
+        manifest: Option<String>,
+        /// Atualiza apenas as empresas marcadas como favoritas.
+        #[arg(long, conflicts_with = "companies")]
+        favorites: bool,
+        /// Executa um job nomeado definido no arquivo de configurações.
+        #[arg(long, conflicts_with_all = ["companies", "favorites"])]
+        job: Option<String>,
+        /// Atualiza os datasets de todas as empresas com o apelido informado (ex.: `vendas`).
+        #[arg(long, conflicts_with_all = ["companies", "favorites", "job"])]
+        alias: Option<String>,
+        /// Atualiza as empresas de todos os perfis do secrets.toml, cada uma com as credenciais do seu perfil.
+        #[arg(long, conflicts_with_all = ["companies", "favorites", "job", "alias", "manifest", "impact"])]
+        all_profiles: bool,
+        /// Máximo de atualizações simultâneas por perfil com --all-profiles.
+        #[arg(long, default_value_t = 2)]
+        profile_concurrency: usize,
+        /// Motivo da atualização, gravado no histórico e enviado nas notificações.
+        #[arg(long)]
+        note: Option<String>,
+        /// Exibe os relatórios e aplicativos afetados e pede confirmação antes de atualizar.
+        #[arg(long)]
+        impact: bool,
+        #[command(flatten)]
+        wait: WaitArgs,
+    },
+    /// Executa uma sequência gravada de atualizações.
+    Run {
+        #[command(subcommand)]
+        target: RunCommand,
+    },
+    /// Inicia o modo servidor com painel web para acompanhar e disparar atualizações.
+    Serve {
+        /// Endereço em que o painel web ficará disponível.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        bind: SocketAddr,
+        /// Intervalo, em minutos, entre atualizações automáticas de todas as empresas.
+        #[arg(long)]
+        interval: Option<u64>,
+        /// Horário (HH:MM, no fuso configurado) de atualização diária de todas as empresas; pode ser repetido.
+        #[arg(long = "at", value_parser = parse_time, conflicts_with = "interval")]
+        daily_at: Vec<NaiveTime>,
+        /// Endereço da API gRPC de controle (desativada quando omitido).
+        #[arg(long)]
+        grpc: Option<SocketAddr>,
+        /// Chave exigida no webhook do Event Grid (`/events/eventgrid?key=...`).
+        #[arg(long)]
+        events_key: Option<String>,
+        /// Intervalo, em minutos, da busca por datasets atualizáveis não configurados, avisados aos webhooks de `notify`.
+        #[arg(long)]
+        discover: Option<u64>,
+        /// Socket de controle (named pipe no Windows) para `control`; sem valor, usa o endereço padrão.
+        #[arg(long, num_args = 0..=1, default_missing_value = control::DEFAULT_ADDRESS)]
+        control: Option<String>,
+        #[command(flatten)]
+        wait: WaitArgs,
+    },
+    /// Exporta ou importa o estado da instalação (configurações, histórico, runbooks e tokens) em um zip.
+    State {
+        #[command(subcommand)]
+        action: StateCommand,
+    },
+    /// Envia um comando ao `serve` em execução, sem reiniciá-lo.
+    Control {
+        #[command(subcommand)]
+        action: ControlCommand,
+        /// Socket de controle (named pipe no Windows) informado no `serve --control`.
+        #[arg(long, default_value = control::DEFAULT_ADDRESS)]
+        socket: String,
+    },
+    /// Lista os datasets que dependem de um dataflow e, opcionalmente, os atualiza quando o dataflow terminar.
+    Dataflow {
+        /// ID do dataflow.
+        id: String,
+        /// ID do workspace do dataflow, usado para acompanhar suas execuções.
+        #[arg(long)]
+        workspace: String,
+        /// Aguarda o término do dataflow e atualiza os datasets dependentes.
+        #[arg(long)]
+        refresh: bool,
+        #[command(flatten)]
+        wait: WaitArgs,
+    },
+    /// Gera um link assinado, de uso único, que atualiza o dataset pelo modo servidor.
+    Link {
+        /// ID da empresa.
+        #[arg(long)]
+        company: u32,
+        /// GUID do dataset.
+        #[arg(long)]
+        dataset: String,
+        /// Endereço do modo servidor usado no link.
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        base_url: String,
+        /// Validade do link, em horas.
+        #[arg(long, default_value_t = 24)]
+        hours: i64,
+    },
+    /// Lista os workspaces e datasets acessíveis e em qual empresa cada um está configurado.
+    List,
+    /// Exibe a conta ou aplicativo do token atual, com tenant, permissões e validade.
+    Whoami,
+    /// Último resultado de cada dataset configurado, pelo histórico local.
+    Status {
+        /// Compara a última atualização no PowerBI dos datasets presentes em dois perfis (ex.: `--compare dev prod`).
+        #[arg(long, num_args = 2, value_names = ["PERFIL", "OUTRO_PERFIL"])]
+        compare: Option<Vec<String>>,
+    },
+    /// Exibe as últimas atualizações registradas no PowerBI para os datasets de uma empresa.
+    History {
+        /// ID da empresa.
+        #[arg(long)]
+        company: u32,
+        /// Quantidade de atualizações por dataset.
+        #[arg(long, default_value_t = 10)]
+        top: u32,
+    },
+    /// Cancela as atualizações em andamento dos datasets de uma empresa, com uma etiqueta ou de um único dataset.
+    Cancel {
+        /// ID da empresa.
+        #[arg(long, required_unless_present_any = ["tag", "dataset"])]
+        company: Option<u32>,
+        /// Etiqueta dos datasets (`tags` no dataset.json), em qualquer empresa.
+        #[arg(long, conflicts_with = "company")]
+        tag: Option<String>,
+        /// GUID do dataset; lista as atualizações em andamento e pergunta qual cancelar.
+        #[arg(long, conflicts_with_all = ["company", "tag"])]
+        dataset: Option<String>,
+        /// Identificador (`requestId`) da atualização a cancelar, dispensando a pergunta.
+        #[arg(long, requires = "dataset")]
+        request: Option<String>,
+    },
+    /// Confere, após a promoção de um pipeline de implantação, parâmetros, gateway e atualização inicial dos datasets.
+    VerifyDeployment {
+        /// Arquivo YAML com o workspace (ou pipeline e estágio) e o esperado de cada dataset.
+        spec: String,
+    },
+    /// Manutenção do arquivo de configurações.
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Relatórios a partir do histórico de atualizações.
+    Report {
+        #[command(subcommand)]
+        report: ReportCommand,
+    },
+    /// Ícone na bandeja do Windows com o status da última execução e ações no menu de contexto.
+    Tray {
+        /// Endereço do painel web aberto pela ação "Abrir painel".
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        bind: SocketAddr,
+        /// Intervalo, em minutos, entre atualizações automáticas de todas as empresas.
+        #[arg(long)]
+        interval: Option<u64>,
+        #[command(flatten)]
+        wait: WaitArgs,
+    },
+    /// Consome pedidos de atualização de uma fila e publica o resultado de cada pedido.
+    Consume {
+        /// Serviço de mensageria utilizado.
+        #[arg(long, value_enum)]
+        backend: QueueBackend,
+        /// URL AMQP (RabbitMQ) ou connection string do namespace (Service Bus).
+        #[arg(long)]
+        url: String,
+        /// Fila com os pedidos de atualização.
+        #[arg(long)]
+        queue: String,
+        /// Fila onde os resultados serão publicados.
+        #[arg(long)]
+        result_queue: Option<String>,
+    },
 }
 
-async fn send_request_update_dataset(dataset_id: String, token: &TokenResponse) -> Result<reqwest::StatusCode, reqwest::StatusCode> {
+#[tokio::main]
+async fn main() {
 
-    let url = format!("https://api.powerbi.com/v1.0/myorg/datasets/{}/refreshes", dataset_id);
-    let access_token = token.access_token.clone();
+    let cli = Cli::parse();
 
-    let client = reqwest::Client::new();
-    let res = client.post(url)
-    .bearer_auth(access_token)
-    .header("Content-Length", 0)
-    .send()
-    .await
-    .expect("Falha ao enviar solicitação de atualização.");
+    ui::configure_output();
+    config::enter_home_dir();
 
-    if res.status().is_success() {
-        Ok(res.status())
-    } else {
-        Err(res.status())
+    if cli.quiet {
+        ui::set_quiet();
+    }
+    if let Some(seconds) = cli.pause_timeout {
+        ui::set_pause_timeout(seconds);
     }
-}
-
-fn validate_token(token: &TokenResponse) -> bool {
 
-    let now: DateTime<Utc> = Utc::now();
+    // Mensagem inicial escrita em Figlet.
+    welcome_message();
 
-    let expire_token: i64 = token.expires_on.trim().parse::<i64>().unwrap_or_default();
+    cache::set_bypass(cli.refresh_cache);
 
-    let expire_token_date: DateTime<Utc> = DateTime::from_timestamp(expire_token, 0).unwrap();
+    if cli.read_only {
+        config::set_read_only();
+    }
 
-    now < expire_token_date
-}
+    if cli.init {
+        ensure_writable("A criação do dataset.json");
+        match config::write_config_template() {
+            Ok(_) => exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+    }
 
-fn read_token_file() -> Option<TokenResponse> {
+    // A simulação cobre o menu e o `refresh` com o perfil padrão; os demais comandos consultariam a API real.
+    if cli.scenario.is_some() && !matches!(&cli.command, None | Some(Command::Refresh { all_profiles: false, .. })) {
+        eprintln!("--scenario simula apenas o menu interativo e o refresh (sem --all-profiles).");
+        exit(1);
+    }
 
-    let current_dir = env::current_dir().expect("Erro ao obter diretório de execução");
-    let full_current_dir = current_dir.join(&FILENAME_TOKEN_JSON);
-    
-    let mut file = match File::open(full_current_dir) {
-        Ok(file) => file,
-        Err(_) => return None,
-    };
+    if let Some(code) = &cli.explain {
+        match explain::explain(code) {
+            Ok(found) => exit(if found { 0 } else { 1 }),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+    }
 
-    let mut content: String = String::new();
-    if let Err(_) = file.read_to_string(&mut content) {
-        return None;
+    if let Some(Command::Init) = cli.command {
+        ensure_writable("A execução do assistente de configuração");
+        ui::require_interactive("O assistente de configuração");
+        match setup::run_wizard().await {
+            Ok(_) => exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
     }
 
-    match serde_json::from_str(&content) {
-        Ok(token) => token,
-        Err(_) => None
+    // Relatórios dependem apenas do histórico e do arquivo de configurações.
+    if let Some(Command::Report { report: ReportCommand::Sla { from, to, format, output } }) = &cli.command {
+        let app_config = read_config_file();
+        apply_settings(&app_config);
+        let output = output.clone().unwrap_or_else(|| format.default_filename().to_string());
+
+        match report::export_sla(&app_config.companies, *from, *to, *format, &output) {
+            Ok(count) => {
+                println!("Relatório gerado em {} ({} datasets).", output, count);
+                exit(0);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
     }
-}
 
-fn read_config_file() -> Vec<GuidEntry> {
+    if let Some(Command::Config { action: ConfigCommand::Rollback { file } }) = &cli.command {
+        ensure_writable("A restauração do dataset.json");
+        match backup::rollback(file.as_deref()) {
+            Ok(_) => exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+    }
 
-    let mut file = match File::open(FILENAME_CONFIG_JSON) {
-        Ok(file) => file,
-        Err(e) => {
-            eprintln!("{}{}", "Erro ao ler arquivo de configurações\n", e);
-            pause();
+    // Links são assinados localmente, sem acesso à API.
+    if let Some(Command::Link { company, dataset, base_url, hours }) = &cli.command {
+        let Some(key) = links::links_key() else {
+            eprintln!("Defina a variável {} com a chave usada também pelo modo servidor.", links::ENV_LINKS_KEY);
             exit(1);
-        },
-    };
+        };
 
-    let mut content = String::new();
-    file.read_to_string(&mut content).expect("Erro ao ler arquivo de configurações.");
+        let app_config = read_config_file();
+        apply_settings(&app_config);
+        ensure_writable("A geração de links de atualização");
 
-    match serde_json::from_str::<Vec<GuidEntry>>(&content) {
-        Ok(entries) => entries,
-        Err(_) => {
-            eprintln!("Erro ao desserializar arquivo de dataset.");
-            pause();
+        let configured = app_config.companies.iter().any(|entry| entry.id == *company && entry.guid.iter().any(|config| config.id == *dataset));
+        if !configured {
+            eprintln!("Dataset {} não encontrado na empresa {}.", dataset, company);
             exit(1);
         }
+
+        let expires = chrono::Utc::now() + chrono::Duration::hours(*hours);
+        println!("{}", links::signed_link(&key, base_url, *company, dataset, expires));
+        exit(0);
     }
-}
 
-fn read_secrets_file() -> HashMap<String, String>{
-    let current_dir = env::current_dir().expect("Erro ao obter diretório de execução");
-    let settings_file = current_dir.join(&FILENAME_SECRETS_TOML);
+    if let Some(Command::Report { report: ReportCommand::Calendar { output } }) = &cli.command {
+        let app_config = read_config_file();
 
-    let settings_builder = Config::builder()
-    .add_source(ConfigFile::with_name(&settings_file.to_str().unwrap()))
-    .build();
+        match report::export_calendar(&app_config, output) {
+            Ok(count) => {
+                println!("Agenda gerada em {} ({} eventos).", output, count);
+                exit(0);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+    }
 
-    match settings_builder {
-        Ok(settings) => {
+    // O pacote de estado lida apenas com arquivos locais.
+    if let Some(Command::State { action }) = &cli.command {
+        let result = match action {
+            StateCommand::Export { file, runbook, secrets, tokens } => state::export(file, runbook, *secrets, *tokens)
+                .map(|count| println!("{} arquivo(s) exportado(s) para {}.", count, file)),
+            StateCommand::Import { file, force } => {
+                ensure_writable("A importação do estado");
+                state::import(file, *force).map(|count| println!("{} arquivo(s) restaurado(s) de {}.", count, file))
+            }
+        };
 
-            let settings = settings.try_deserialize::<HashMap<String, String>>().unwrap();
-            return settings;
+        match result {
+            Ok(_) => exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
         }
-        Err(e) => {
-            eprintln!("{}{}", "Falha ao ler arquivo de segredos.\n", e);
-            pause();
-            exit(1);
+    }
+
+    // O controle fala com o processo do `serve`, que já tem as próprias configurações e token.
+    if let Some(Command::Control { action, socket }) = &cli.command {
+        match control::send(socket, &action.line()).await {
+            Ok(message) => {
+                println!("{}", message);
+                exit(0);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
         }
     }
-}
 
-fn export_token(token: &TokenResponse) {
-    let filename = FILENAME_TOKEN_JSON;
-    let content = serde_json::to_string(&token).unwrap();
+    // O status local dispensa o token; a comparação usa os segredos de cada perfil.
+    if let Some(Command::Status { compare }) = &cli.command {
+        let app_config = read_config_file();
+        apply_settings(&app_config);
 
-    let mut file = match File::create(filename) {
-        Ok(file) => file,
-        Err(e) => {
-            panic!("Falha ao criar arquivo de token.\nErro: {}", e);
-        }
-    };
+        let Some(profiles) = compare else {
+            history::print_status(&app_config.companies);
+            exit(0);
+        };
 
-    match file.write_all(content.as_bytes()) {
-        Ok(_) => {}
-        Err(e) => {
-            panic!("Erro ao gravar arquivo.\nErro: {}", e);
+        let secrets = |name: &str| read_secrets_file((name != "padrão").then_some(name));
+
+        match profiles::compare(&app_config, secrets(&profiles[0]), secrets(&profiles[1])).await {
+            Ok(_) => exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
         }
     }
-}
 
-fn pause() {
-    let message = "\nPressione ENTER para finalizar\n".yellow();
-    println!("{}", message);
-    let mut buffer = String::new();
-    io::stdin().read_line(&mut buffer).expect("Falha ao ler entrada do usuário.");
-}
+    // Cada perfil tem o próprio token, então os segredos são lidos à parte.
+    if let Some(Command::Refresh { all_profiles: true, profile_concurrency, note, wait, .. }) = &cli.command {
+        if cli.profile.is_some() {
+            eprintln!("--profile e --all-profiles não podem ser usados juntos.");
+            exit(2);
+        }
 
-fn welcome_message() {
-    let standard_font = FIGfont::from_content(FONT).unwrap();
-    let figure = standard_font.convert("PowerBI    Updater");
-    println!("{}", figure.unwrap());
-}
+        let mut app_config = read_config_file();
+        apply_settings(&app_config);
+        ensure_writable("A atualização");
 
-#[tokio::main]
-async fn main() {
-    
-    // Mensagem inicial escrita em Figlet.
-    welcome_message();
+        for company in app_config.companies.iter_mut() {
+            for dataset in company.guid.iter_mut() {
+                dataset.note = note.clone();
+            }
+        }
 
-    // Realiza a leitura do arquivo de senhas e segredos.
-    let secrets: HashMap<String, String> = read_secrets_file();
+        let records = profiles::run_all_profiles(&app_config, config::read_all_profiles(), *profile_concurrency, wait.options()).await;
 
-    // Cria uma hashtable para armazenar os valores de maneira mais fácil.
-    let mut hash_guid_entries: HashMap<u32, Vec<String>> = HashMap::new();
+        if wait.wait {
+            monitor::print_summary(&records);
+        }
 
-    /*
-    Recupera do arquivo os GUID de atualização.
-    Salva cada guid em um novo registro.
-    */
-    for config in read_config_file() {
-        hash_guid_entries.insert(config.id, config.guid);
+        let success = !records.is_empty() && records.iter().all(|record| !record.outcome().is_failure(wait.wait));
+
+        exit(if success { 0 } else { 1 });
     }
 
-    let mut token: TokenResponse = TokenResponse::default();
-    let mut is_loaded_token: bool = false;
+    if cli.command.is_none() {
+        ui::require_interactive("O menu");
+    } else if cli.record.is_some() {
+        eprintln!("--record grava apenas sessões do menu interativo (sem subcomando).");
+        exit(1);
+    }
 
-    // Realiza leitura do arquivo com o token salvo (caso houver)
-    match read_token_file() {
-        // Se o arquivo for lido, será usado o token.
-        Some(token_loaded) => {
+    // Na primeira execução interativa, oferece o assistente em vez de encerrar por falta de arquivos.
+    if cli.command.is_none() && setup::missing_config_files() {
+        let run_wizard = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Arquivos de configuração não encontrados. Deseja executar o assistente?")
+            .default(true)
+            .interact()
+            .unwrap_or(false);
 
-            // Verifica se o token já perdeu a validade
-            if validate_token(&token_loaded) {
-                token = token_loaded;
-                is_loaded_token = true;
+        if run_wizard {
+            if let Err(e) = setup::run_wizard().await {
+                eprintln!("{}", e);
+                pause();
+                exit(1);
             }
         }
-        None => {}
     }
 
-    // Será feito uma tentativa de obtenção de um novo token.
-    if is_loaded_token == false {
-        match acquire_new_token(secrets).await {
-            // Caso o token seja gerado com sucesso.
-            Ok(token_loaded) => {
-                println!("Novo token gerado !");
-                token = token_loaded;
-                is_loaded_token = true;
-                export_token(&token);
-            },
-            // Caso ocorra erro ao gerar o novo token.
-            Err(_) => {
-                eprintln!("Erro ao gerar novo token.\nConsidere validar o arquivo de segredos.");
+    // Realiza a leitura do arquivo de senhas e segredos.
+    let secrets: Secrets = match cli.scenario {
+        Some(scenario) => match simulation::start(scenario).await {
+            Ok(secrets) => secrets,
+            Err(e) => {
+                eprintln!("{}", e);
                 pause();
                 exit(1);
             }
+        },
+        None => read_secrets_file(cli.profile.as_deref()),
+    };
+    set_api_base_url(secrets.api_base_url());
+
+    // Reaproveita o token salvo ou tenta obter um novo, em paralelo com a leitura das configurações.
+    let token_task = tokio::spawn({
+        let secrets = secrets.clone();
+        async move { load_token(&secrets).await }
+    });
+
+    /*
+    Recupera do arquivo os GUID de atualização.
+    Salva cada guid em um novo registro.
+    */
+    let mut manifest_wait = None;
+    let mut app_config: AppConfig = match &cli.command {
+        Some(Command::Refresh { manifest: Some(path), .. }) => match read_manifest(path) {
+            Ok(manifest) => {
+                manifest_wait = manifest.wait.map(|wait| wait.options());
+                manifest.config
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        },
+        _ => read_config_file(),
+    };
+
+    // Na simulação, todas as empresas são atualizadas pela API simulada, qualquer que seja o perfil.
+    if cli.scenario.is_some() {
+        for company in app_config.companies.iter_mut() {
+            company.profile = secrets.profile.clone();
         }
     }
+    apply_settings(&app_config);
 
-    // Não foi possível obter o token de nenhuma maneira, o programa encerra.
-    if is_loaded_token == false {
-        eprintln!("Não foi possível carregar o token de atualização.");
+    if let Err(e) = config::check_workspaces(&app_config, &secrets) {
+        eprintln!("{}", e);
         pause();
-        exit(500);
+        exit(1);
+    }
+
+    // Listas, status, histórico e o painel seguem disponíveis no modo somente leitura.
+    match &cli.command {
+        Some(Command::Refresh { .. }) | Some(Command::Run { .. }) | Some(Command::Dataflow { refresh: true, .. }) => ensure_writable("A atualização"),
+        Some(Command::Consume { .. }) => ensure_writable("A leitura da fila de atualizações"),
+        Some(Command::Cancel { .. }) => ensure_writable("A interrupção de atualizações"),
+        Some(Command::Config { action: ConfigCommand::Prune }) => ensure_writable("A alteração do dataset.json"),
+        _ => {}
     }
-    
-    // Opções para seleção do usuário.
-    let prompt_options = vec!["Todas empresas", "Uma empresa", "Configurações", "Sair"];
-    
-    // Exibe o menu iterativo para o usuário.
-    let prompt_selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Opção: ")
-        .default(0)
-        .items(&prompt_options)
-        .interact()
-        .unwrap();
-
-    match prompt_selection {
-        0 => {
-            // Iterar sobre todos os registros na HashMap.
-            for (key, value) in hash_guid_entries.iter() {
-
-                println!("Empresa: {}", key);
-
-                for dataset in value {
-
-                    // Após o token ser carregado, será enviado uma requisição.
-                    let update = send_request_update_dataset(dataset.to_string(), &token).await;
-        
-                    match update {
-                        // Caso a requisição retorne sucesso.
-                        Ok(_) => {
-                            let status = "Aceita".green();
-                            println!("\t- Requisição: {}", status);
-                        }
-                        // Caso a requisição retorne falha.
-                        Err(_) => {
-                            let status = "Negada".red();
-                            eprintln!("\t- Requisição: {}", status);
-                        }
+
+    let token: TokenResponse = match token_task.await {
+        Ok(Ok(token)) => token,
+        // Caso ocorra erro ao gerar o novo token, o usuário pode tentar recuperar a autenticação.
+        Ok(Err(e)) => match troubleshoot::troubleshoot(&secrets, &e).await {
+            Some(token) => token,
+            None => {
+                pause();
+                exit(1);
+            }
+        },
+        Err(e) => {
+            eprintln!("Erro ao gerar novo token.\n{}", e);
+            pause();
+            exit(1);
+        }
+    };
+
+    claims::warn_token_problems(&secrets, &token);
+
+    // Na primeira execução, o histórico é semeado com as atualizações que o PowerBI ainda guarda.
+    let mut entries = guid_entries_map(&app_config.companies);
+    entries.retain(|id, _| app_config.companies.iter().any(|company| company.id == *id && company.profile == secrets.profile));
+    history::backfill(&entries, &token).await;
+
+    match cli.command {
+        Some(Command::Init) | Some(Command::Report { .. }) | Some(Command::Link { .. }) | Some(Command::Status { .. }) | Some(Command::Control { .. }) | Some(Command::State { .. }) | Some(Command::Config { action: ConfigCommand::Rollback { .. } }) => unreachable!(),
+        Some(Command::Refresh { companies, favorites, job, alias, note, impact, wait, .. }) => {
+            let session = Arc::new(Session::new(secrets, &app_config, token));
+
+            let job = job.map(|name| match app_config.jobs.get(&name) {
+                Some(job) => job,
+                None => {
+                    let available: Vec<&str> = app_config.jobs.keys().map(String::as_str).collect();
+                    eprintln!("Job \"{}\" não encontrado. Jobs disponíveis: {}", name, if available.is_empty() { "nenhum".to_string() } else { available.join(", ") });
+                    exit(1);
+                }
+            });
+
+            let mut targets = if let Some(job) = job {
+                session.job_targets(job)
+            } else if let Some(alias) = &alias {
+                let targets = session.alias_targets(alias);
+                if targets.is_empty() {
+                    let available = session.aliases();
+                    eprintln!("Apelido \"{}\" não encontrado. Apelidos disponíveis: {}", alias, if available.is_empty() { "nenhum".to_string() } else { available.join(", ") });
+                    exit(1);
+                }
+                targets
+            } else if favorites {
+                session.favorite_targets()
+            } else if companies.is_empty() {
+                session.all_targets()
+            } else {
+                let mut targets = Vec::new();
+                for company in companies {
+                    match session.company_targets(company) {
+                        Ok(company_targets) => targets.extend(company_targets),
+                        Err(e) => eprintln!("{}", e),
                     }
                 }
+                targets
+            };
+
+            for (_, dataset) in targets.iter_mut() {
+                dataset.note = note.clone();
             }
-        }
-        1 => {
-            // Inicia um loop aguardando o usuário digitar uma entrada válida.
-            loop {
-                // Espera o usuário informar a chave que deseja atualizar.
-                let hash_map_key: u32 = Input::with_theme(&ColorfulTheme::default())
-                    .with_prompt("ID Empresa")
-                    .interact_text()
-                    .expect("Erro ao obter entrada do usuário.");
-                
-                // Verifica se a chave existe no HashMap.
-                match hash_guid_entries.get(&hash_map_key) {
-                    Some(value) => {
-                        println!("Empresa: {}", hash_map_key);
-
-                        for dataset in value {
-
-                            // Após o token ser carregado, será enviado uma requisição.
-                            let update = send_request_update_dataset(dataset.to_string(), &token).await;
-
-                            match update {
-                                // Caso a requisição retorne sucesso.
-                                Ok(_) => {
-                                    let status = "Aceita".green();
-                                    println!("\t- Requisição: {}", status);
-                                }
-                                // Caso a requisição retorne falha.
-                                Err(_) => {
-                                    let status = "Negada".red();
-                                    eprintln!("\t- Requisição: {}", status);
-                                }
-                            }
-                        }
-                        break;
+
+            if impact {
+                ui::require_interactive("A confirmação do --impact");
+
+                if let Err(e) = lineage::print_impact_preview(&session, &targets).await {
+                    eprintln!("Falha ao consultar relatórios afetados.\n{}", e);
+                }
+                history::print_quota(&targets);
+
+                let proceed = Confirm::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Continuar com as atualizações?")
+                    .default(true)
+                    .interact()
+                    .unwrap_or(false);
+
+                if !proceed {
+                    exit(0);
+                }
+            }
+
+            // As opções da linha de comando prevalecem sobre as do job e do manifesto.
+            let job_wait = job.and_then(|job| job.wait.as_ref()).map(|wait| wait.options());
+            let wait_options = wait.options().or(job_wait).or(manifest_wait);
+
+            // Etapas dependem do término da anterior, então são sempre acompanhadas.
+            if let Some(job) = job.filter(|job| !job.stages.is_empty()) {
+                match job::run_stages(&session, job, note.as_deref(), wait_options.unwrap_or_default()).await {
+                    Ok((records, completed)) => {
+                        session.flush().await;
+                        monitor::print_summary(&records);
+                        exit(if completed { 0 } else { 1 });
                     }
-                    None => {
-                        // Caso não seja encontrada uma chave, o loop reinicia.
-                        eprintln!("Valor não encotrado !");
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        exit(1);
                     }
                 }
             }
+
+            let records = match session.run(targets, wait_options).await {
+                Ok(records) => records,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            };
+            session.flush().await;
+
+            if wait_options.is_some() {
+                monitor::print_summary(&records);
+            }
+
+            if let Some(alias) = &alias {
+                monitor::print_alias_summary(alias, &records, wait_options.is_some());
+            }
+
+            // Acompanhando, o sucesso é a conclusão; sem acompanhar, basta a requisição ser aceita. Ignorados não falham.
+            let success = !records.is_empty() && records.iter().all(|record| !record.outcome().is_failure(wait_options.is_some()));
+
+            exit(if success { 0 } else { 1 });
         }
-        2 => {
-            match open::that(FILENAME_CONFIG_JSON) {
-                Ok(_) => {}
-                Err(_e) => {
-                    println!("Falha ao abrir arquivo para edição.");
+        Some(Command::Run { target: RunCommand::Runbook { file, wait } }) => {
+            let runbook = match runbook::read_runbook(&file) {
+                Ok(runbook) => runbook,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            };
+
+            let session = Arc::new(Session::new(secrets, &app_config, token));
+            let records = match runbook::replay(&session, &runbook, wait.options()).await {
+                Ok(records) => records,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
                 }
+            };
+            session.flush().await;
+
+            if wait.wait {
+                monitor::print_summary(&records);
             }
-            println!("{}", "Reinicie a aplicação para aplicar as mudanças.".on_red());
-            pause();
-            exit(0);
+
+            let success = !records.is_empty() && records.iter().all(|record| !record.outcome().is_failure(wait.wait));
+
+            exit(if success { 0 } else { 1 });
         }
-        3 => {
-            println!("{}", "Bye".green());
-            exit(0);
+        Some(Command::Serve { bind, interval, mut daily_at, grpc, events_key, discover, control, wait }) => {
+            if interval.is_none() && daily_at.is_empty() {
+                daily_at = app_config.schedule.clone();
+            }
+            let options = ServeOptions { bind, grpc_bind: grpc, interval_minutes: interval, daily_at, events_key, wait: wait.options(), discover_minutes: discover, control };
+            let session = Arc::new(Session::new(secrets, &app_config, token));
+            server::serve(options, session, app_config).await;
+        }
+        Some(Command::Tray { bind, interval, wait }) => {
+            let options = ServeOptions { bind, grpc_bind: None, interval_minutes: interval, daily_at: Vec::new(), events_key: None, wait: wait.options(), discover_minutes: None, control: None };
+            let session = Arc::new(Session::new(secrets, &app_config, token));
+
+            // O painel segue disponível enquanto o ícone ocupa a thread principal.
+            tokio::spawn(server::serve(options, session.clone(), app_config));
+
+            let dashboard_url = format!("http://{}", bind);
+            if let Err(e) = tokio::task::block_in_place(|| tray::run(session, dashboard_url, wait.options())) {
+                eprintln!("{}", e);
+                pause();
+                exit(1);
+            }
         }
-        _ => {
-            eprintln!("Entrada não reconhecida.");
+        Some(Command::Dataflow { id, workspace, refresh, wait }) => {
+            let session = Arc::new(Session::new(secrets, &app_config, token));
+
+            let result = lineage::run_dataflow_command(session.clone(), &id, &workspace, refresh, wait.options()).await;
+            session.flush().await;
+
+            match result {
+                Ok(success) => exit(if success { 0 } else { 1 }),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+        Some(Command::VerifyDeployment { spec }) => {
+            let spec = match deployment::read_spec(&spec) {
+                Ok(spec) => spec,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            };
+
+            // Os datasets recém-promovidos não estão no cache de metadados.
+            cache::set_bypass(true);
+
+            match deployment::verify(&spec, &token).await {
+                Ok(true) => {}
+                Ok(false) => exit(1),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+        Some(Command::Cancel { dataset: Some(dataset), request, .. }) => {
+            if let Err(e) = cancel::cancel_one(&dataset, request, &token).await {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+        Some(Command::Cancel { company, tag, .. }) => {
+            let session = Session::new(secrets, &app_config, token.clone());
+
+            let targets = match (company, &tag) {
+                (Some(company), _) => session.company_targets(company).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    exit(1);
+                }),
+                (None, Some(tag)) => match session.tag_targets(tag) {
+                    targets if targets.is_empty() => {
+                        eprintln!("Nenhum dataset com a etiqueta \"{}\".", tag);
+                        exit(1);
+                    }
+                    targets => targets,
+                },
+                (None, None) => unreachable!(),
+            };
+
+            if !cancel::cancel_in_progress(&targets, &token).await {
+                exit(1);
+            }
+        }
+        Some(Command::History { company, top }) => {
+            let session = Session::new(secrets, &app_config, token.clone());
+
+            match session.company_targets(company) {
+                Ok(targets) => {
+                    let datasets: Vec<_> = targets.into_iter().map(|(_, dataset)| dataset).collect();
+                    history::print_service_history(&datasets, top, &token).await;
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+        Some(Command::Whoami) => {
+            if let Err(e) = claims::print_identity(&secrets, &token) {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+        Some(Command::List) => {
+            if let Err(e) = maintenance::list(&app_config, &token).await {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+        Some(Command::Config { action: ConfigCommand::Prune }) => {
+            if let Err(e) = maintenance::prune(app_config, &token).await {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+        Some(Command::Config { action: ConfigCommand::Diff }) => {
+            if let Err(e) = maintenance::diff(&app_config, &token).await {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+        Some(Command::Consume { backend, url, queue, result_queue }) => {
+            let session = Arc::new(Session::new(secrets, &app_config, token));
+
+            if let Err(e) = queue::consume(backend, &url, &queue, result_queue.as_deref(), session).await {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+        None => {
+            menu::Menu::new(app_config, secrets, token, cli.record).run().await;
+        }
+    }
+}
+
+/// Idioma e fuso usados nas mensagens, no painel e nos relatórios, a antecedência de renovação do token, onde o
+/// histórico é guardado e o modo somente leitura.
+fn apply_settings(app_config: &AppConfig) {
+    locale::set_locale(app_config.locale.unwrap_or_default());
+    if let Some(timezone) = app_config.timezone {
+        locale::set_timezone(timezone);
+    }
+    if let Some(minutes) = app_config.token_skew_minutes {
+        token::set_token_skew(minutes);
+    }
+    if app_config.read_only {
+        config::set_read_only();
+    }
+    if let Some(policy) = app_config.retry {
+        refresh::set_retry_policy(policy);
+    }
+    if let Some(limit) = app_config.max_parallel {
+        refresh::set_max_parallel(limit);
+    }
+    if let Some(seconds) = app_config.pause_timeout_seconds {
+        ui::set_pause_timeout_default(seconds);
+    }
+    powerbi::set_workspaces(config::dataset_workspaces(app_config));
+    if let Some(backend) = &app_config.history {
+        if let Err(e) = history::set_backend(backend) {
+            eprintln!("{}", e);
             pause();
             exit(1);
         }
     }
+}
 
-    pause();
+/// Horário no formato `HH:MM`.
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| format!("Horário inválido: \"{}\" (use HH:MM).", value))
+}
 
+/// Encerra quando a ação altera algo e o modo somente leitura está ativo.
+fn ensure_writable(action: &str) {
+    if let Err(e) = config::ensure_writable(action) {
+        eprintln!("{}", e);
+        exit(2);
+    }
 }
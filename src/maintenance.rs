@@ -0,0 +1,188 @@
+use colored::Colorize;
+use dialoguer::{MultiSelect, theme::ColorfulTheme};
+
+use std::collections::HashSet;
+
+use crate::config::{write_config_file, AppConfig, DatasetConfig};
+use crate::powerbi::{get_dataset, list_datasets, list_groups, Dataset, Group};
+use crate::token::TokenResponse;
+use crate::ui::require_interactive;
+
+/// Dataset configurado que não deveria mais estar no arquivo.
+struct StaleEntry {
+    company: u32,
+    dataset: String,
+    reason: &'static str,
+}
+
+/// Consulta cada GUID configurado e oferece remover os que não existem mais ou deixaram de ser atualizáveis.
+pub async fn prune(mut config: AppConfig, token: &TokenResponse) -> Result<(), String> {
+
+    let mut stale = Vec::new();
+
+    for company in &config.companies {
+        for dataset in &company.guid {
+            let reason = match get_dataset(&dataset.id, token).await {
+                Ok(None) => "não encontrado (404)",
+                Ok(Some(found)) if !found.is_refreshable => "não é mais atualizável",
+                Ok(Some(_)) => continue,
+                Err(e) => {
+                    eprintln!("Falha ao consultar dataset {} (empresa {}).\n{}", dataset.id, company.id, e);
+                    continue;
+                }
+            };
+
+            stale.push(StaleEntry { company: company.id, dataset: dataset.id.clone(), reason });
+        }
+    }
+
+    if stale.is_empty() {
+        println!("Nenhuma entrada obsoleta encontrada.");
+        return Ok(());
+    }
+
+    let items: Vec<String> = stale
+        .iter()
+        .map(|entry| format!("Empresa {} - {}: {}", entry.company, entry.dataset, entry.reason))
+        .collect();
+
+    require_interactive("A seleção das entradas a remover");
+
+    let selection = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Entradas a remover (espaço para marcar, enter para confirmar)")
+        .items(&items)
+        .defaults(&vec![true; items.len()])
+        .interact()
+        .map_err(|e| e.to_string())?;
+
+    if selection.is_empty() {
+        println!("Nenhuma entrada removida.");
+        return Ok(());
+    }
+
+    for index in &selection {
+        let entry = &stale[*index];
+        if let Some(company) = config.companies.iter_mut().find(|company| company.id == entry.company) {
+            company.guid.retain(|dataset| dataset.id != entry.dataset);
+        }
+    }
+
+    // Empresas sem datasets restantes também saem do arquivo.
+    config.companies.retain(|company| !company.guid.is_empty());
+
+    write_config_file(&config)?;
+    println!("{} entrada(s) removida(s).", selection.len());
+
+    Ok(())
+}
+
+/// Lista os workspaces e datasets acessíveis, indicando em qual empresa cada dataset está configurado.
+pub async fn list(config: &AppConfig, token: &TokenResponse) -> Result<(), String> {
+
+    for group in list_groups(token).await? {
+
+        println!("{} ({})", group.name, group.id);
+
+        for dataset in list_datasets(&group.id, token).await? {
+
+            let company = config
+                .companies
+                .iter()
+                .find(|company| company.guid.iter().any(|configured| configured.id == dataset.id))
+                .map(|company| format!("empresa {}", company.id).green())
+                .unwrap_or_else(|| "não configurado".normal());
+
+            let refreshable = if dataset.is_refreshable { "" } else { " (não atualizável)" };
+
+            println!("\t- {} ({}){} [{}]", dataset.name, dataset.id, refreshable, company);
+        }
+    }
+
+    Ok(())
+}
+
+/// Todos os datasets acessíveis, com o workspace de cada um.
+async fn live_datasets(token: &TokenResponse) -> Result<Vec<(Group, Dataset)>, String> {
+
+    let mut live = Vec::new();
+
+    for group in list_groups(token).await? {
+        for dataset in list_datasets(&group.id, token).await? {
+            live.push((group.clone(), dataset));
+        }
+    }
+
+    Ok(live)
+}
+
+/// Datasets atualizáveis que não estão no `dataset.json`, apenas nos workspaces que já contêm algum dataset
+/// configurado (os demais workspaces não são gerenciados pelo programa).
+fn unmanaged<'a>(live: &'a [(Group, Dataset)], config: &AppConfig) -> Vec<&'a (Group, Dataset)> {
+
+    let configured: HashSet<&str> = config
+        .companies
+        .iter()
+        .flat_map(|company| company.guid.iter().map(|dataset| dataset.id.as_str()))
+        .collect();
+
+    let workspaces: HashSet<&str> = live
+        .iter()
+        .filter(|(_, dataset)| configured.contains(dataset.id.as_str()))
+        .map(|(group, _)| group.id.as_str())
+        .collect();
+
+    live.iter()
+        .filter(|(group, dataset)| workspaces.contains(group.id.as_str()) && dataset.is_refreshable && !configured.contains(dataset.id.as_str()))
+        .collect()
+}
+
+/// Datasets novos, que ninguém atualiza, para a descoberta periódica do `serve`.
+pub async fn unmanaged_datasets(config: &AppConfig, token: &TokenResponse) -> Result<Vec<(Group, Dataset)>, String> {
+    let live = live_datasets(token).await?;
+    Ok(unmanaged(&live, config).into_iter().cloned().collect())
+}
+
+/// Compara o `dataset.json` com os datasets existentes nos workspaces que contêm datasets configurados,
+/// no estilo de um plano: inclusões possíveis, remoções necessárias e renomeações.
+pub async fn diff(config: &AppConfig, token: &TokenResponse) -> Result<(), String> {
+
+    let live = live_datasets(token).await?;
+
+    let configured: Vec<(u32, &DatasetConfig)> = config
+        .companies
+        .iter()
+        .flat_map(|company| company.guid.iter().map(move |dataset| (company.id, dataset)))
+        .collect();
+
+    let mut additions = 0;
+    let mut removals = 0;
+    let mut renames = 0;
+
+    for (group, dataset) in unmanaged(&live, config) {
+        additions += 1;
+        println!("{}", format!("+ {} / {} ({}): não configurado", group.name, dataset.name, dataset.id).green());
+    }
+
+    for (company, configured) in &configured {
+        match live.iter().find(|(_, dataset)| dataset.id == configured.id) {
+            None => {
+                removals += 1;
+                println!("{}", format!("- empresa {} / {}: não encontrado", company, configured.id).red());
+            }
+            Some((group, dataset)) if !dataset.is_refreshable => {
+                removals += 1;
+                println!("{}", format!("- empresa {} / {} / {} ({}): não é mais atualizável", company, group.name, dataset.name, dataset.id).red());
+            }
+            Some((group, dataset)) => {
+                if let Some(name) = configured.name.as_ref().filter(|name| **name != dataset.name) {
+                    renames += 1;
+                    println!("{}", format!("~ empresa {} / {} ({}): \"{}\" -> \"{}\"", company, group.name, dataset.id, name, dataset.name).yellow());
+                }
+            }
+        }
+    }
+
+    println!("Plano: {} a incluir, {} a remover, {} renomeado(s).", additions, removals, renames);
+
+    Ok(())
+}
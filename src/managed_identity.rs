@@ -0,0 +1,103 @@
+use std::{env, fs, time::Duration};
+use reqwest::{header::WWW_AUTHENTICATE, StatusCode};
+use serde::Deserialize;
+
+use crate::config::Secrets;
+use crate::token::TokenResponse;
+
+/// Endpoint do serviço de metadados (IMDS) das VMs do Azure.
+const IMDS_ENDPOINT: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+const IMDS_API_VERSION: &str = "2018-02-01";
+/// Variáveis definidas pela Automação do Azure, App Service e servidores Azure Arc (workers híbridos).
+const ENV_IDENTITY_ENDPOINT: &str = "IDENTITY_ENDPOINT";
+const ENV_IDENTITY_HEADER: &str = "IDENTITY_HEADER";
+const IDENTITY_API_VERSION: &str = "2019-08-01";
+const ARC_API_VERSION: &str = "2020-06-01";
+
+/// Resposta dos endpoints de identidade; `expires_on` vem como número ou texto conforme o serviço.
+#[derive(Debug, Deserialize)]
+struct IdentityToken {
+    access_token: String,
+    #[serde(default)]
+    token_type: Option<String>,
+    expires_on: serde_json::Value,
+}
+
+/// Token da identidade gerenciada da máquina, sem credenciais gravadas. `client_id`, quando informado,
+/// escolhe a identidade atribuída pelo usuário; sem ele, vale a identidade do sistema.
+pub async fn acquire_managed_identity_token(secrets: &Secrets, resource: &str) -> Result<TokenResponse, String> {
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let client_id = Some(secrets.client_id.as_str()).filter(|client_id| !client_id.is_empty());
+
+    let res = match (env::var(ENV_IDENTITY_ENDPOINT), env::var(ENV_IDENTITY_HEADER)) {
+        // Automação do Azure e App Service.
+        (Ok(endpoint), Ok(header)) => {
+            let mut query = vec![("api-version", IDENTITY_API_VERSION), ("resource", resource)];
+            query.extend(client_id.map(|client_id| ("client_id", client_id)));
+            client.get(endpoint).header("X-IDENTITY-HEADER", header).query(&query).send().await
+        }
+        // Azure Arc: a primeira resposta aponta um arquivo local cujo conteúdo prova o acesso à máquina.
+        (Ok(endpoint), Err(_)) => {
+            let query = [("api-version", ARC_API_VERSION), ("resource", resource)];
+            let challenge = client
+                .get(&endpoint)
+                .header("Metadata", "true")
+                .query(&query)
+                .send()
+                .await
+                .map_err(|e| format!("Falha ao acessar o endpoint de identidade do Azure Arc.\nErro: {}", e))?;
+
+            if challenge.status() != StatusCode::UNAUTHORIZED {
+                return Err(format!("Resposta inesperada do Azure Arc ({}).\n{}", challenge.status(), challenge.text().await.unwrap_or_default()));
+            }
+
+            let path = challenge
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split("realm=").nth(1))
+                .map(|path| path.trim().to_string())
+                .ok_or("Desafio do Azure Arc sem o arquivo de chave.")?;
+            let key = fs::read_to_string(&path).map_err(|e| format!("Falha ao ler a chave do Azure Arc ({}); execute como administrador.\nErro: {}", path, e))?;
+
+            client
+                .get(&endpoint)
+                .header("Metadata", "true")
+                .header("Authorization", format!("Basic {}", key.trim()))
+                .query(&query)
+                .send()
+                .await
+        }
+        // VMs do Azure.
+        _ => {
+            let mut query = vec![("api-version", IMDS_API_VERSION), ("resource", resource)];
+            query.extend(client_id.map(|client_id| ("client_id", client_id)));
+            client.get(IMDS_ENDPOINT).header("Metadata", "true").query(&query).send().await
+        }
+    }
+    .map_err(|e| format!("Falha ao acessar o endpoint de identidade gerenciada; a máquina está no Azure?\nErro: {}", e))?;
+
+    if !res.status().is_success() {
+        return Err(res.text().await.unwrap_or_default());
+    }
+
+    let token: IdentityToken = res.json().await.map_err(|e| format!("Falha ao converter JSON.\nErro: {}", e))?;
+
+    let expires_on = match &token.expires_on {
+        serde_json::Value::Number(number) => number.to_string(),
+        serde_json::Value::String(text) => text.clone(),
+        other => return Err(format!("Validade do token inválida: {}", other)),
+    };
+
+    Ok(TokenResponse {
+        token_type: token.token_type.unwrap_or_else(|| "Bearer".to_string()),
+        expires_on,
+        access_token: token.access_token,
+        refresh_token: None,
+    })
+}
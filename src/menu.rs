@@ -0,0 +1,468 @@
+use std::process::exit;
+use colored::Colorize;
+use dialoguer::{Input, MultiSelect, Select, theme::ColorfulTheme};
+
+use crate::backup::backup_config_file;
+use crate::claims::print_identity;
+use crate::config::{ensure_writable, guid_entries_map, has_default_profile, profile_names, read_secrets_file, write_config_file, AppConfig, DatasetConfig, Secrets, FILENAME_CONFIG_JSON, FILENAME_SECRETS_TOML};
+use crate::history::{print_service_history, print_status, read_records};
+use crate::locale;
+use crate::maintenance;
+use crate::refresh::{refresh_companies, refresh_company};
+use crate::report::{export_sla, ReportFormat};
+use crate::runbook::{write_runbook, Runbook, RunbookStep};
+use crate::profiles::profile_name;
+use crate::token::{acquire_new_token, export_token, load_token, read_saved_token, renew_saved_token, validate_token, TokenResponse};
+use crate::troubleshoot::troubleshoot;
+use crate::ui::edit_file;
+
+/// Quantidade de requisições exibidas em "Histórico".
+const HISTORY_SIZE: usize = 20;
+
+/// Estado do menu interativo; o token é renovado quando expira entre uma ação e outra.
+pub struct Menu {
+    config: AppConfig,
+    secrets: Secrets,
+    token: TokenResponse,
+    /// Arquivo do runbook gravado ao sair, com as atualizações escolhidas na sessão.
+    record: Option<String>,
+    steps: Vec<RunbookStep>,
+}
+
+/// Exibe as opções com o caminho percorrido. Em submenus, "Voltar" (ou ESC) devolve `None`.
+fn choose(path: &[&str], options: &[&str], back: bool) -> Option<usize> {
+
+    let mut items: Vec<&str> = options.to_vec();
+    if back {
+        items.push("Voltar");
+    }
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(path.join(" > "))
+        .default(0)
+        .items(&items)
+        .interact_opt();
+
+    match selection {
+        Ok(Some(index)) if index < options.len() => Some(index),
+        Ok(_) => None,
+        Err(e) => {
+            // Sem terminal (ex.: fim da entrada) não há como continuar no menu.
+            eprintln!("Falha ao ler a opção do menu.\nErro: {}", e);
+            exit(1);
+        }
+    }
+}
+
+/// Motivo opcional da atualização manual, gravado no histórico e enviado nas notificações.
+fn ask_note() -> Option<String> {
+
+    let note: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Motivo (opcional, ENTER para pular)")
+        .allow_empty(true)
+        .interact_text()
+        .unwrap_or_default();
+
+    let note = note.trim();
+    (!note.is_empty()).then(|| note.to_string())
+}
+
+/// Itens marcados como ativos; ESC cancela sem alterar nada.
+fn ask_active(prompt: &str, labels: &[String], active: &[bool]) -> Option<Vec<usize>> {
+
+    if labels.is_empty() {
+        println!("Nada configurado.");
+        return None;
+    }
+
+    MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("{} (ESPAÇO marca ou desmarca, ENTER confirma)", prompt))
+        .items(labels)
+        .defaults(active)
+        .interact_opt()
+        .ok()
+        .flatten()
+}
+
+impl Menu {
+
+    pub fn new(config: AppConfig, secrets: Secrets, token: TokenResponse, record: Option<String>) -> Self {
+        Menu { config, secrets, token, record, steps: Vec::new() }
+    }
+
+    /// Menu principal; encerra o programa em "Sair".
+    pub async fn run(&mut self) {
+
+        loop {
+            match choose(&["Menu"], &["Atualização", "Histórico", "Status", "Configurações", "Autenticação", "Sair"], false) {
+                Some(0) => match ensure_writable("A atualização") {
+                    Ok(_) => self.refresh_menu().await,
+                    Err(e) => eprintln!("{}", e),
+                },
+                Some(1) => self.history_menu().await,
+                Some(2) => self.status_menu(),
+                Some(3) => self.config_menu().await,
+                Some(4) => self.auth_menu().await,
+                _ => {
+                    self.save_runbook();
+                    println!("{}", "Bye".green());
+                    exit(0);
+                }
+            }
+        }
+    }
+
+    /// Grava as atualizações da sessão no runbook informado em `--record`.
+    fn save_runbook(&self) {
+
+        let Some(path) = &self.record else {
+            return;
+        };
+
+        if self.steps.is_empty() {
+            println!("Nenhuma atualização realizada; runbook {} não gravado.", path);
+            return;
+        }
+
+        let runbook = Runbook { created: Some(chrono::Utc::now()), steps: self.steps.clone() };
+        match write_runbook(path, &runbook) {
+            Ok(_) => println!("Runbook gravado em {} ({} passos). Reproduza com `run runbook {}`.", path, runbook.steps.len(), path),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    /// Token atual, renovado caso tenha expirado durante o uso do menu.
+    async fn token(&mut self) -> Option<&TokenResponse> {
+
+        if !validate_token(&self.token) {
+            match renew_saved_token(&self.secrets, Some(&self.token)).await {
+                Ok(token) => self.token = token,
+                Err(e) => match troubleshoot(&self.secrets, &e).await {
+                    Some(token) => self.token = token,
+                    None => return None,
+                },
+            }
+        }
+
+        Some(&self.token)
+    }
+
+    async fn refresh_menu(&mut self) {
+
+        // Apenas as empresas do perfil em uso, cujo token é o da sessão.
+        let mut entries = guid_entries_map(&self.config.companies);
+        for company in &self.config.companies {
+            if company.profile != self.secrets.profile {
+                entries.remove(&company.id);
+            }
+        }
+        let secrets = self.secrets.clone();
+        let mut companies: Vec<&u32> = entries.keys().collect();
+        companies.sort();
+
+        while let Some(selection) = choose(&["Menu", "Atualização"], &["Todas empresas", "Uma empresa"], true) {
+
+            let note = ask_note();
+
+            let Some(token) = self.token().await else {
+                continue;
+            };
+
+            // Token recusado pela API dentro da validade: renova e grava para as próximas requisições.
+            let renew = || renew_saved_token(&secrets, Some(token));
+
+            match selection {
+                0 => {
+                    let targets: Vec<(u32, &[DatasetConfig])> = companies.iter().map(|company| (**company, entries[*company].as_slice())).collect();
+                    refresh_companies(&targets, note.as_deref(), token, renew).await;
+                    self.steps.push(RunbookStep::RefreshAll { note });
+                }
+                _ => {
+                    // Aguarda o usuário digitar uma empresa existente; erro de leitura volta ao submenu.
+                    loop {
+                        let company: u32 = match Input::with_theme(&ColorfulTheme::default())
+                            .with_prompt("ID Empresa")
+                            .interact_text()
+                        {
+                            Ok(company) => company,
+                            Err(e) => {
+                                eprintln!("Falha ao ler o ID da empresa.\nErro: {}", e);
+                                break;
+                            }
+                        };
+
+                        match entries.get(&company) {
+                            Some(datasets) => {
+                                refresh_company(company, datasets, note.as_deref(), token, renew).await;
+                                self.steps.push(RunbookStep::RefreshCompany { company, note });
+                                break;
+                            }
+                            None => eprintln!("Valor não encotrado !"),
+                        }
+                    }
+                }
+            }
+
+            // Um token renovado durante as requisições foi gravado em disco.
+            if let Some(saved) = read_saved_token(&self.secrets).filter(validate_token) {
+                self.token = saved;
+            }
+        }
+    }
+
+    async fn history_menu(&mut self) {
+
+        let options = ["Últimas requisições", "Atualizações no PowerBI (uma empresa)", "Gerar relatório de SLA (CSV)"];
+
+        while let Some(selection) = choose(&["Menu", "Histórico"], &options, true) {
+            match selection {
+                0 => {
+                    let records = read_records();
+
+                    if records.is_empty() {
+                        println!("Nenhuma requisição registrada.");
+                    }
+
+                    for record in records.iter().rev().take(HISTORY_SIZE) {
+                        let note = record.note.as_ref().map(|note| format!(" ({})", note)).unwrap_or_default();
+                        println!("{} Empresa {} - {}: {}{}", locale::datetime(record.timestamp), record.company, record.dataset, record.describe(), note);
+                    }
+                }
+                1 => {
+                    let company: u32 = match Input::with_theme(&ColorfulTheme::default()).with_prompt("ID Empresa").interact_text() {
+                        Ok(company) => company,
+                        Err(e) => {
+                            eprintln!("Falha ao ler o ID da empresa.\nErro: {}", e);
+                            continue;
+                        }
+                    };
+
+                    // Apenas as empresas do perfil em uso, cujo token é o da sessão.
+                    let entry = self.config.companies.iter().find(|entry| entry.id == company && entry.profile == self.secrets.profile);
+                    let Some(datasets) = entry.map(|entry| entry.guid.clone()) else {
+                        eprintln!("Empresa {} não encontrada.", company);
+                        continue;
+                    };
+
+                    if let Some(token) = self.token().await {
+                        print_service_history(&datasets, HISTORY_SIZE as u32, token).await;
+                    }
+                }
+                _ => {
+                    let format = ReportFormat::Csv;
+                    match export_sla(&self.config.companies, None, None, format, format.default_filename()) {
+                        Ok(count) => println!("Relatório gerado em {} ({} datasets).", format.default_filename(), count),
+                        Err(e) => eprintln!("{}", e),
+                    }
+                }
+            }
+        }
+    }
+
+    fn status_menu(&self) {
+
+        while choose(&["Menu", "Status"], &["Último resultado por dataset"], true).is_some() {
+            print_status(&self.config.companies);
+        }
+    }
+
+    async fn config_menu(&mut self) {
+
+        let options = ["Editar dataset.json", "Ativar/desativar empresas e datasets", "Listar workspaces e datasets", "Comparar com os workspaces"];
+
+        while let Some(selection) = choose(&["Menu", "Configurações"], &options, true) {
+
+            // Edições do dataset.json; listar e comparar seguem disponíveis no modo somente leitura.
+            if selection < 2 {
+                if let Err(e) = ensure_writable("A alteração do dataset.json") {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            }
+
+            match selection {
+                0 => {
+                    // A edição é externa, então a cópia é feita antes de abrir o arquivo.
+                    if let Err(e) = backup_config_file() {
+                        eprintln!("{}", e);
+                    }
+                    match edit_file(FILENAME_CONFIG_JSON) {
+                        Ok(_) => println!("{}", "Reinicie a aplicação para aplicar as mudanças.".on_red()),
+                        Err(e) => eprintln!("{}", e),
+                    }
+                }
+                1 => self.toggle_menu(),
+                _ => {
+                    let Some(token) = self.token().await.cloned() else {
+                        continue;
+                    };
+
+                    let result = if selection == 2 { maintenance::list(&self.config, &token).await } else { maintenance::diff(&self.config, &token).await };
+                    if let Err(e) = result {
+                        eprintln!("{}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ativa ou desativa empresas e datasets sem editar o JSON. A alteração é gravada no `dataset.json` e vale
+    /// também para as próximas atualizações deste menu.
+    fn toggle_menu(&mut self) {
+
+        while let Some(selection) = choose(&["Menu", "Configurações", "Ativar/desativar"], &["Empresas", "Datasets de uma empresa"], true) {
+
+            let changed = match selection {
+                0 => self.toggle_companies(),
+                _ => self.toggle_datasets(),
+            };
+
+            if changed {
+                match write_config_file(&self.config) {
+                    Ok(_) => println!("{} atualizado.", FILENAME_CONFIG_JSON),
+                    Err(e) => eprintln!("{}\nA alteração vale apenas até o fim desta sessão.", e),
+                }
+            }
+        }
+    }
+
+    fn toggle_companies(&mut self) -> bool {
+
+        let labels: Vec<String> = self.config.companies.iter().map(|company| format!("Empresa {} ({} datasets)", company.id, company.guid.len())).collect();
+        let active: Vec<bool> = self.config.companies.iter().map(|company| !company.disabled).collect();
+
+        let Some(selected) = ask_active("Empresas ativas", &labels, &active) else {
+            return false;
+        };
+
+        let mut changed = false;
+
+        for (index, company) in self.config.companies.iter_mut().enumerate() {
+            let disabled = !selected.contains(&index);
+            if company.disabled != disabled {
+                company.disabled = disabled;
+                changed = true;
+                println!("Empresa {}: {}", company.id, if disabled { "desativada".yellow() } else { "ativada".green() });
+            }
+        }
+
+        changed
+    }
+
+    fn toggle_datasets(&mut self) -> bool {
+
+        let labels: Vec<String> = self.config.companies.iter().map(|company| format!("Empresa {}", company.id)).collect();
+
+        let Some(company) = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Empresa")
+            .default(0)
+            .items(&labels)
+            .interact_opt()
+            .ok()
+            .flatten()
+        else {
+            return false;
+        };
+
+        let company = &mut self.config.companies[company];
+        if company.disabled {
+            println!("{}", format!("Empresa {} desativada; seus datasets seguem ignorados até ela ser ativada.", company.id).yellow());
+        }
+
+        let labels: Vec<String> = company
+            .guid
+            .iter()
+            .map(|dataset| match &dataset.name {
+                Some(name) => format!("{} ({})", name, dataset.id),
+                None => dataset.id.clone(),
+            })
+            .collect();
+        let active: Vec<bool> = company.guid.iter().map(|dataset| !dataset.disabled).collect();
+
+        let Some(selected) = ask_active("Datasets ativos", &labels, &active) else {
+            return false;
+        };
+
+        let mut changed = false;
+
+        for (index, dataset) in company.guid.iter_mut().enumerate() {
+            let disabled = !selected.contains(&index);
+            if dataset.disabled != disabled {
+                dataset.disabled = disabled;
+                changed = true;
+                println!("Dataset {}: {}", dataset.id, if disabled { "desativado".yellow() } else { "ativado".green() });
+            }
+        }
+
+        changed
+    }
+
+    async fn auth_menu(&mut self) {
+
+        loop {
+            let switch = format!("Trocar perfil (atual: {})", profile_name(&self.secrets));
+            let Some(selection) = choose(&["Menu", "Autenticação"], &["Validade do token", "Identidade", "Gerar novo token", &switch], true) else {
+                break;
+            };
+
+            match selection {
+                0 => {
+                    let expires = self.token.expires_on.trim().parse::<i64>().ok().and_then(|expires| chrono::DateTime::from_timestamp(expires, 0));
+                    match expires {
+                        Some(expires) if validate_token(&self.token) => println!("Token válido até {}.", locale::datetime(expires)),
+                        Some(expires) => println!("{}", format!("Token expirado em {}.", locale::datetime(expires)).red()),
+                        None => println!("{}", "Validade do token desconhecida.".red()),
+                    }
+                }
+                1 => {
+                    if let Err(e) = print_identity(&self.secrets, &self.token) {
+                        eprintln!("{}", e);
+                    }
+                }
+                2 => match acquire_new_token(&self.secrets).await {
+                    Ok(token) => {
+                        export_token(&self.secrets, &token);
+                        self.token = token;
+                        println!("Novo token gerado !");
+                    }
+                    Err(e) => eprintln!("Erro ao gerar novo token.\n{}", e),
+                },
+                _ => self.switch_profile().await,
+            }
+        }
+    }
+
+    /// Passa a usar as credenciais de outro perfil do `secrets.toml`; o menu passa a exibir as empresas dele.
+    async fn switch_profile(&mut self) {
+
+        let names = profile_names();
+        if names.is_empty() {
+            println!("Nenhum perfil nomeado no {}.", FILENAME_SECRETS_TOML);
+            return;
+        }
+
+        // Sem chaves no topo do `secrets.toml`, não há perfil padrão para escolher.
+        let default = has_default_profile();
+        let mut options = if default { vec!["padrão"] } else { Vec::new() };
+        options.extend(names.iter().map(String::as_str));
+
+        let Some(selection) = choose(&["Menu", "Autenticação", "Perfil"], &options, true) else {
+            return;
+        };
+
+        let profile = if default { (selection > 0).then(|| names[selection - 1].as_str()) } else { Some(names[selection].as_str()) };
+        let secrets = read_secrets_file(profile);
+
+        let token = match load_token(&secrets).await {
+            Ok(token) => token,
+            Err(e) => match troubleshoot(&secrets, &e).await {
+                Some(token) => token,
+                None => return,
+            },
+        };
+
+        println!("Perfil {} em uso.", profile_name(&secrets));
+        self.secrets = secrets;
+        self.token = token;
+    }
+}
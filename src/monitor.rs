@@ -0,0 +1,333 @@
+use std::{cmp::Reverse, collections::{BTreeMap, BinaryHeap, HashMap}, sync::OnceLock, time::Duration};
+use chrono::Utc;
+use colored::Colorize;
+use tokio::{sync::{mpsc, oneshot}, time::Instant};
+
+use crate::locale;
+use crate::history::{median_duration, previous_outcome, Outcome, RefreshRecord};
+use crate::powerbi::{list_refreshes, TOKEN_REJECTED};
+use crate::session::Session;
+use crate::ui::alert;
+
+/// Status devolvido pela API enquanto a atualização não terminou.
+pub const STATUS_IN_PROGRESS: &str = "Unknown";
+/// Duração acima deste múltiplo da mediana histórica é considerada anômala.
+const ANOMALY_FACTOR: i64 = 3;
+
+/// Status estendido da atualização que ainda não saiu da fila.
+const EXTENDED_STATUS_QUEUED: &str = "NotStarted";
+
+/// Consultas de status por minuto, somando todos os datasets acompanhados, para não esbarrar no limite da API.
+const MAX_STATUS_REQUESTS_PER_MINUTE: u64 = 60;
+/// Sem mudança de status, o intervalo do dataset cresce até este múltiplo do intervalo configurado.
+const MAX_BACKOFF_FACTOR: u32 = 4;
+
+/// Pedido de vez para consultar o status, a partir de `due`.
+struct PollRequest {
+    due: Instant,
+    grant: oneshot::Sender<()>,
+}
+
+/// Agenda única das consultas de status: atende os datasets acompanhados em ordem de horário previsto,
+/// com espaçamento mínimo entre consultas para respeitar o limite da API.
+#[derive(Default)]
+pub struct PollScheduler {
+    requests: OnceLock<mpsc::UnboundedSender<PollRequest>>,
+}
+
+impl PollScheduler {
+
+    /// Aguarda a vez de consultar: não antes de `due` e sem ultrapassar o limite global de consultas.
+    pub async fn wait_turn(&self, due: Instant) {
+
+        let requests = self.requests.get_or_init(|| {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            tokio::spawn(run_scheduler(receiver));
+            sender
+        });
+
+        let (grant, granted) = oneshot::channel();
+
+        // Sem agenda em execução, consulta diretamente no horário previsto.
+        if requests.send(PollRequest { due, grant }).is_err() {
+            tokio::time::sleep_until(due).await;
+            return;
+        }
+
+        let _ = granted.await;
+    }
+}
+
+async fn run_scheduler(mut requests: mpsc::UnboundedReceiver<PollRequest>) {
+
+    let spacing = Duration::from_millis(60_000 / MAX_STATUS_REQUESTS_PER_MINUTE);
+    let mut queue: BinaryHeap<Reverse<(Instant, u64)>> = BinaryHeap::new();
+    let mut grants: HashMap<u64, oneshot::Sender<()>> = HashMap::new();
+    let mut sequence = 0u64;
+    let mut last_grant = Instant::now() - spacing;
+
+    loop {
+        let next = queue.peek().map(|Reverse((due, _))| (*due).max(last_grant + spacing));
+
+        tokio::select! {
+            request = requests.recv() => match request {
+                Some(request) => {
+                    sequence += 1;
+                    queue.push(Reverse((request.due, sequence)));
+                    grants.insert(sequence, request.grant);
+                }
+                None => return,
+            },
+            _ = tokio::time::sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                let Reverse((_, id)) = queue.pop().unwrap();
+
+                // Quem desistiu de esperar não consome a vez.
+                if let Some(grant) = grants.remove(&id) {
+                    if grant.send(()).is_ok() {
+                        last_grant = Instant::now();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Parâmetros do acompanhamento das atualizações enviadas.
+#[derive(Debug, Clone, Copy)]
+pub struct WaitOptions {
+    pub poll_interval: Duration,
+    pub timeout: Duration,
+    /// Tempo na fila (sem iniciar) a partir do qual a atualização é considerada travada.
+    pub max_queue_wait: Duration,
+    /// Múltiplo da duração típica a partir do qual uma atualização ainda em andamento gera aviso.
+    pub watchdog_factor: f64,
+}
+
+impl Default for WaitOptions {
+    fn default() -> Self {
+        WaitOptions {
+            poll_interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(180 * 60),
+            max_queue_wait: Duration::from_secs(15 * 60),
+            watchdog_factor: 2.0,
+        }
+    }
+}
+
+/// Acompanha uma atualização aceita até o resultado final, alertando sobre o prazo do dataset.
+pub async fn wait_for_refresh(session: &Session, record: &RefreshRecord, options: WaitOptions) -> RefreshRecord {
+
+    let deadline = session
+        .dataset_config(&record.dataset)
+        .and_then(|config| config.deadline.map(|deadline| locale::next_occurrence(deadline, record.timestamp, config.timezone)));
+
+    let median = median_duration(&record.dataset, record.timestamp);
+
+    // Previsão a partir da duração típica registrada no histórico.
+    if let (Some(deadline), Some(median)) = (deadline, median) {
+        let predicted = record.timestamp + chrono::Duration::seconds(median);
+        if predicted > deadline {
+            alert(&format!(
+                "Dataset {} (empresa {}) deve terminar por volta de {}, após o prazo de {}.",
+                record.dataset, record.company,
+                locale::time(predicted),
+                locale::time(deadline),
+            ));
+        }
+    }
+
+    let started = Utc::now();
+    let mut deadline_alerted = false;
+    let mut queue_alerted = false;
+    let mut watchdog_alerted = false;
+
+    // Intervalo adaptativo: cresce enquanto o status não muda e volta ao configurado quando muda.
+    let max_interval = options.poll_interval * MAX_BACKOFF_FACTOR;
+    let mut interval = options.poll_interval;
+    let mut last_status = None;
+
+    loop {
+        session.poller.wait_turn(Instant::now() + interval).await;
+
+        let latest = match session.current_token().await {
+            Ok(token) => match list_refreshes(&record.dataset, 1, &token).await {
+                // Um token revogado no servidor é recusado mesmo dentro da validade.
+                Err(e) if e.starts_with(TOKEN_REJECTED) => match session.force_renew(&token).await {
+                    Ok(token) => list_refreshes(&record.dataset, 1, &token).await,
+                    Err(e) => Err(e),
+                },
+                latest => latest,
+            },
+            Err(e) => Err(e),
+        };
+
+        match latest {
+            Ok(refreshes) => {
+                // Ignora a atualização anterior enquanto a nova ainda não aparece no histórico da API.
+                let current = refreshes.into_iter().next().filter(|refresh| {
+                    refresh.start_time.map(|start| start >= record.timestamp - chrono::Duration::minutes(1)).unwrap_or(false)
+                });
+
+                let status = current.as_ref().map(|refresh| (refresh.status.clone(), refresh.extended_status.clone()));
+                interval = if status == last_status { (interval * 3 / 2).min(max_interval) } else { options.poll_interval };
+                last_status = status;
+
+                // Disputa pela capacidade pode deixar a atualização na fila indefinidamente, sem falhar.
+                let queued = current.as_ref().is_none_or(|refresh| refresh.extended_status.as_deref() == Some(EXTENDED_STATUS_QUEUED));
+                let queued_for = Utc::now() - record.timestamp;
+
+                if queued && !queue_alerted && queued_for > chrono::Duration::from_std(options.max_queue_wait).unwrap() {
+                    queue_alerted = true;
+                    alert(&format!(
+                        "Dataset {} (empresa {}) está na fila há {} minutos sem iniciar; verifique a capacidade.",
+                        record.dataset, record.company, queued_for.num_minutes(),
+                    ));
+                }
+
+                // Aviso intermediário: ainda em andamento e já bem acima da duração típica.
+                let running_for = current.as_ref().and_then(|refresh| refresh.start_time).map(|start| (Utc::now() - start).num_seconds());
+                if let (Some(running_for), Some(median)) = (running_for, median) {
+                    if !watchdog_alerted && median > 0 && running_for as f64 > median as f64 * options.watchdog_factor {
+                        watchdog_alerted = true;
+                        let message = format!(
+                            "Dataset {} (empresa {}) está em andamento há {}, acima de {}x a duração típica de {}.",
+                            record.dataset, record.company,
+                            locale::duration(running_for),
+                            locale::number(options.watchdog_factor, 1),
+                            locale::duration(median),
+                        );
+                        alert(&message);
+                        session.notify(&record.dataset, &message);
+                    }
+                }
+
+                if let Some(refresh) = current.filter(|refresh| refresh.status != STATUS_IN_PROGRESS) {
+
+                    let completed_at = refresh.end_time.unwrap_or_else(Utc::now);
+                    let duration_secs = refresh.start_time.map(|start| (completed_at - start).num_seconds());
+
+                    if let Some(deadline) = deadline {
+                        if completed_at > deadline {
+                            alert(&format!(
+                                "Dataset {} (empresa {}) terminou às {}, após o prazo de {}.",
+                                record.dataset, record.company,
+                                locale::time(completed_at),
+                                locale::time(deadline),
+                            ));
+                        }
+                    }
+
+                    return RefreshRecord {
+                        completed_at: Some(completed_at),
+                        started_at: refresh.start_time,
+                        duration_secs,
+                        error_code: refresh.error_code(),
+                        result: Some(refresh.status),
+                        request_id: refresh.request_id.or_else(|| record.request_id.clone()),
+                        ..record.clone()
+                    };
+                }
+            }
+            Err(e) => {
+                eprintln!("Falha ao consultar atualização do dataset {}.\n{}", record.dataset, e);
+                interval = (interval * 2).min(max_interval);
+            }
+        }
+
+        if let Some(deadline) = deadline {
+            if !deadline_alerted && Utc::now() > deadline {
+                deadline_alerted = true;
+                alert(&format!("Dataset {} (empresa {}) ainda não terminou e o prazo já passou.", record.dataset, record.company));
+            }
+        }
+
+        if Utc::now() - started > chrono::Duration::from_std(options.timeout).unwrap() {
+            eprintln!("Tempo de espera esgotado para o dataset {}.", record.dataset);
+            return RefreshRecord { result: Some("Timeout".to_string()), ..record.clone() };
+        }
+    }
+}
+
+/// Exibe o resultado final de uma atualização acompanhada.
+pub fn print_result(record: &RefreshRecord) {
+
+    let status = record.describe();
+
+    match record.warmup_ms {
+        Some(warmup_ms) => println!("Empresa {} - {}: {} (aquecimento: {} ms)", record.company, record.dataset, status, locale::number(warmup_ms as f64, 0)),
+        None => println!("Empresa {} - {}: {}", record.company, record.dataset, status),
+    }
+
+    if let Some(request_id) = record.request_id.as_ref().filter(|_| record.outcome().is_failure(true)) {
+        eprintln!("\tRequestId: {}", request_id);
+    }
+}
+
+/// Resultado de um apelido agrupado por empresa: a empresa conta como concluída quando todos os seus datasets
+/// foram concluídos (ou aceitos, sem acompanhamento).
+pub fn print_alias_summary(alias: &str, records: &[RefreshRecord], waited: bool) {
+
+    let mut companies: BTreeMap<u32, Vec<&RefreshRecord>> = BTreeMap::new();
+    for record in records {
+        companies.entry(record.company).or_default().push(record);
+    }
+
+    let succeeded = |record: &RefreshRecord| !record.outcome().is_failure(waited);
+    let completed = companies.values().filter(|records| records.iter().all(|record| succeeded(record))).count();
+
+    println!("{}: {} de {} empresas {}.", alias.cyan(), completed, companies.len(), if waited { "concluídas" } else { "com requisições aceitas" });
+
+    for (company, records) in &companies {
+        let failed: Vec<&str> = records.iter().filter(|record| !succeeded(record)).map(|record| record.dataset.as_str()).collect();
+
+        if failed.is_empty() {
+            println!("\tEmpresa {}: {}", company, "Ok".green());
+        } else {
+            println!("\tEmpresa {}: {} ({})", company, "Falha".red(), failed.join(", "));
+        }
+    }
+}
+
+/// Resumo da execução acompanhada, destacando durações muito acima do histórico do dataset:
+/// a atualização foi concluída, mas alguma consulta de origem pode ter regredido.
+pub fn print_summary(records: &[RefreshRecord]) {
+
+    let mut outcomes: BTreeMap<Outcome, usize> = BTreeMap::new();
+    for record in records {
+        *outcomes.entry(record.outcome()).or_default() += 1;
+    }
+
+    let completed = outcomes.get(&Outcome::Completed).copied().unwrap_or_default();
+    println!("Resumo: {} de {} atualizações concluídas.", completed, records.len());
+
+    for (outcome, count) in outcomes.iter().filter(|(outcome, _)| **outcome != Outcome::Completed) {
+        println!("\t{}: {}", outcome.label(), count);
+    }
+
+    // Destaca apenas o que mudou desde a execução anterior de cada dataset.
+    for record in records.iter().filter(|record| !record.outcome().is_skipped()) {
+        let succeeded = record.outcome() == Outcome::Completed;
+
+        match previous_outcome(&record.dataset, record.timestamp) {
+            Some(true) if !succeeded => println!("\t{} Empresa {} - {}: falhou; a execução anterior foi concluída.", "Nova falha".red(), record.company, record.dataset),
+            Some(false) if succeeded => println!("\t{} Empresa {} - {}: concluída; a execução anterior falhou.", "Recuperado".green(), record.company, record.dataset),
+            _ => {}
+        }
+    }
+
+    for record in records {
+        let (Some(duration), Some(median)) = (record.duration_secs, median_duration(&record.dataset, record.timestamp)) else {
+            continue;
+        };
+
+        if median > 0 && duration > median * ANOMALY_FACTOR {
+            alert(&format!(
+                "Dataset {} (empresa {}) levou {}, {}x a mediana de {}.",
+                record.dataset, record.company,
+                locale::duration(duration),
+                locale::number(duration as f64 / median as f64, 1),
+                locale::duration(median),
+            ));
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+use serde_json::json;
+
+use crate::config::{AnnotationFormat, Annotations};
+use crate::explain::localized_explanation;
+use crate::history::RefreshRecord;
+use crate::locale;
+
+/// Tempo máximo de espera pela resposta de um callback.
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Token enviado no `Authorization` das anotações (ex.: service account do Grafana).
+pub const ENV_ANNOTATIONS_TOKEN: &str = "PBI_ANNOTATIONS_TOKEN";
+
+/// Envia o resultado final da atualização (JSON do histórico) para a URL de callback do dataset.
+pub async fn send_callback(url: &str, record: &RefreshRecord) {
+
+    let client = reqwest::Client::new();
+    let res = client.post(url)
+    .timeout(CALLBACK_TIMEOUT)
+    .json(record)
+    .send()
+    .await;
+
+    match res {
+        Ok(res) if res.status().is_success() => {}
+        Ok(res) => eprintln!("Callback do dataset {} respondeu com status {}.", record.dataset, res.status()),
+        Err(e) => eprintln!("Falha ao enviar callback do dataset {}.\nErro: {}", record.dataset, e),
+    }
+}
+
+/// Envia uma mensagem de texto a um webhook (Teams, Slack ou compatível).
+pub async fn send_message(url: &str, dataset: &str, text: &str) {
+
+    let client = reqwest::Client::new();
+    let res = client.post(url)
+    .timeout(CALLBACK_TIMEOUT)
+    .json(&json!({ "text": text }))
+    .send()
+    .await;
+
+    match res {
+        Ok(res) if res.status().is_success() => {}
+        Ok(res) => eprintln!("Notificação do dataset {} respondeu com status {}.", dataset, res.status()),
+        Err(e) => eprintln!("Falha ao enviar notificação do dataset {}.\nErro: {}", dataset, e),
+    }
+}
+
+/// Avisa um webhook sobre a falha da atualização.
+pub async fn send_failure_alert(url: &str, record: &RefreshRecord) {
+
+    let outcome = match &record.result {
+        Some(result) => format!("terminou com status {}", result),
+        None => format!("foi negada (status {})", record.status),
+    };
+
+    let mut text = format!("Falha na atualização do dataset {} (empresa {}): {} em {}.", record.dataset, record.company, outcome, locale::datetime(record.timestamp));
+    if let Some(note) = &record.note {
+        text.push_str(&format!(" Motivo: {}", note));
+    }
+    if let Some(request_id) = &record.request_id {
+        text.push_str(&format!(" RequestId: {}", request_id));
+    }
+    if let Some(code) = &record.error_code {
+        match localized_explanation(code) {
+            Some(explanation) => text.push_str(&format!("\n\n{}: {}", code, explanation)),
+            None => text.push_str(&format!(" Código: {}", code)),
+        }
+    }
+
+    send_message(url, &record.dataset, &text).await;
+}
+
+/// Marca a janela da atualização no serviço (`startTime` a `endTime`) e o resultado no painel de monitoramento.
+/// Sem o resultado acompanhado (`--wait`), não há janela a marcar e nada é enviado.
+pub async fn send_annotation(annotations: &Annotations, record: &RefreshRecord) {
+
+    let (Some(start), Some(end), Some(result)) = (record.started_at, record.completed_at, record.result.as_deref()) else {
+        return;
+    };
+
+    let mut tags = vec![format!("empresa:{}", record.company), format!("dataset:{}", record.dataset), result.to_lowercase()];
+    tags.extend(annotations.tags.iter().cloned());
+
+    let body = match annotations.format {
+        AnnotationFormat::Grafana => json!({
+            "dashboardUID": annotations.dashboard_uid,
+            "time": start.timestamp_millis(),
+            "timeEnd": end.timestamp_millis(),
+            "tags": tags,
+            "text": format!("Dataset {} (empresa {}): {}", record.dataset, record.company, record.outcome().label()),
+        }),
+        AnnotationFormat::Generic => json!({
+            "company": record.company,
+            "dataset": record.dataset,
+            "start": start,
+            "end": end,
+            "result": result,
+            "outcome": record.outcome().label(),
+            "request_id": record.request_id,
+            "tags": tags,
+        }),
+    };
+
+    let mut req = reqwest::Client::new().post(&annotations.url).timeout(CALLBACK_TIMEOUT).json(&body);
+    if let Some(token) = std::env::var(ENV_ANNOTATIONS_TOKEN).ok().filter(|token| !token.is_empty()) {
+        req = req.bearer_auth(token);
+    }
+
+    match req.send().await {
+        Ok(res) if res.status().is_success() => {}
+        Ok(res) => eprintln!("Anotação do dataset {} respondeu com status {}.", record.dataset, res.status()),
+        Err(e) => eprintln!("Falha ao enviar anotação do dataset {}.\nErro: {}", record.dataset, e),
+    }
+}
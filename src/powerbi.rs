@@ -0,0 +1,561 @@
+use std::{collections::{BTreeMap, HashMap}, future::Future, sync::{OnceLock, RwLock}, time::Duration};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{json, Value};
+
+use crate::cache;
+use crate::claims::cache_scope;
+use crate::config::{ensure_writable, MY_WORKSPACE};
+use crate::token::TokenResponse;
+
+/// Endereço da API na nuvem pública, usado enquanto nenhum outro é definido.
+const DEFAULT_API_BASE_URL: &str = "https://api.powerbi.com/v1.0/myorg";
+
+static API_BASE_URL: OnceLock<String> = OnceLock::new();
+/// Workspace de cada dataset configurado, para endereçá-lo pelo grupo em vez de pela conta.
+static WORKSPACES: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+
+/// Define o endereço da API do PowerBI da execução, conforme a nuvem dos segredos em uso.
+pub fn set_api_base_url(url: &str) {
+    let _ = API_BASE_URL.set(url.to_string());
+}
+
+fn api_base_url() -> &'static str {
+    API_BASE_URL.get().map(String::as_str).unwrap_or(DEFAULT_API_BASE_URL)
+}
+
+/// Define o workspace de cada dataset; substitui o anterior ao recarregar as configurações.
+pub fn set_workspaces(workspaces: HashMap<String, String>) {
+    *WORKSPACES.write().unwrap() = Some(workspaces);
+}
+
+fn workspace(dataset_id: &str) -> Option<String> {
+    WORKSPACES.read().unwrap().as_ref().and_then(|workspaces| workspaces.get(dataset_id).cloned())
+}
+
+/// Endereço do dataset: pelo grupo, quando o workspace é conhecido, ou pela conta (`myorg`), que só encontra datasets
+/// de workspaces acessíveis a ela e o "Meu workspace".
+fn dataset_url(dataset_id: &str) -> String {
+    match workspace(dataset_id) {
+        Some(group_id) if group_id != MY_WORKSPACE => format!("{}/groups/{}/datasets/{}", api_base_url(), group_id, dataset_id),
+        _ => format!("{}/datasets/{}", api_base_url(), dataset_id),
+    }
+}
+
+/// Workspace (grupo) do PowerBI.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+    /// Workspace em capacidade dedicada (Premium, PPU, Fabric).
+    #[serde(default)]
+    pub is_on_dedicated_capacity: bool,
+}
+
+/// Dataset publicado em um workspace.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dataset {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub is_refreshable: bool,
+    /// Endereço do dataset no portal, com o workspace (`/groups/<id>/...`).
+    #[serde(default)]
+    pub web_url: Option<String>,
+}
+
+/// Entrada do histórico de atualizações de um dataset.
+/// `status` é `Unknown` enquanto a atualização está em andamento.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Refresh {
+    pub status: String,
+    /// `NotStarted` enquanto a atualização aguarda na fila da capacidade.
+    #[serde(default)]
+    pub extended_status: Option<String>,
+    #[serde(default)]
+    pub start_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub end_time: Option<DateTime<Utc>>,
+    /// Identificador da solicitação que originou a atualização.
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// Origem da atualização: `ViaApi`, `Scheduled`, `OnDemand`...
+    #[serde(default)]
+    pub refresh_type: Option<String>,
+    /// Detalhe da falha, um JSON em texto com `errorCode` e `errorDescription`.
+    #[serde(default)]
+    pub service_exception_json: Option<String>,
+}
+
+impl Refresh {
+
+    fn service_exception(&self) -> Option<Value> {
+        serde_json::from_str(self.service_exception_json.as_deref()?).ok()
+    }
+
+    /// Código do erro de uma atualização com falha (ex.: `DM_GWPipeline_Gateway_MashupDataAccessError`).
+    pub fn error_code(&self) -> Option<String> {
+        self.service_exception()?["errorCode"].as_str().map(str::to_string)
+    }
+
+    /// Descrição do erro informada pelo serviço.
+    pub fn error_description(&self) -> Option<String> {
+        self.service_exception()?["errorDescription"].as_str().map(str::to_string)
+    }
+}
+
+/// Ligação entre um dataset e o dataflow do qual ele depende.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataflowLink {
+    pub dataset_object_id: String,
+    pub dataflow_object_id: String,
+}
+
+/// Transação (execução) de atualização de um dataflow.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataflowTransaction {
+    pub status: String,
+    #[serde(default)]
+    pub start_time: Option<DateTime<Utc>>,
+}
+
+/// Relatório, em um workspace ou em um aplicativo, e o dataset em que é baseado.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub name: String,
+    #[serde(default)]
+    pub dataset_id: Option<String>,
+}
+
+/// Aplicativo publicado.
+#[derive(Debug, Clone, Deserialize)]
+pub struct App {
+    pub id: String,
+    pub name: String,
+}
+
+/// Estágio de um pipeline de implantação e o workspace associado a ele.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineStage {
+    /// Posição do estágio: 0 desenvolvimento, 1 teste, 2 produção.
+    pub order: u32,
+    #[serde(default)]
+    pub workspace_id: Option<String>,
+    #[serde(default)]
+    pub workspace_name: Option<String>,
+}
+
+/// Parâmetro do modelo semântico e o valor em uso.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Parameter {
+    pub name: String,
+    #[serde(default)]
+    pub current_value: Option<String>,
+}
+
+/// Fonte de dados do dataset e o gateway a que está vinculada.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Datasource {
+    #[serde(default)]
+    pub datasource_type: Option<String>,
+    #[serde(default)]
+    pub gateway_id: Option<String>,
+}
+
+/// Cabeçalho com o identificador da chamada, pedido pelo suporte da Microsoft na análise de falhas.
+const REQUEST_ID_HEADER: &str = "RequestId";
+/// Identificador gerado pelo programa para a solicitação.
+const CLIENT_REQUEST_ID_HEADER: &str = "x-ms-request-id";
+
+/// Identificador da chamada devolvido pela API.
+fn request_id(res: &reqwest::Response) -> Option<String> {
+    res.headers().get(REQUEST_ID_HEADER).and_then(|value| value.to_str().ok()).map(str::to_string)
+}
+
+/// Início da mensagem de erro quando a API recusa o token (401), para que quem consulta possa renová-lo.
+pub const TOKEN_REJECTED: &str = "A API recusou o token";
+
+/// Mensagem de erro de uma resposta sem sucesso, com o RequestId quando disponível.
+fn status_error(res: &reqwest::Response) -> String {
+
+    let status = match res.status() {
+        reqwest::StatusCode::UNAUTHORIZED => format!("{} (status {})", TOKEN_REJECTED, res.status()),
+        status => format!("A API respondeu com status {}", status),
+    };
+
+    match request_id(res) {
+        Some(request_id) => format!("{} (RequestId: {}).", status, request_id),
+        None => format!("{}.", status),
+    }
+}
+
+/// Envelope das respostas de listagem da API; `@odata.nextLink` aponta a próxima página, quando houver.
+#[derive(Debug, Deserialize)]
+struct ODataList<T> {
+    value: Vec<T>,
+    #[serde(rename = "@odata.nextLink")]
+    next_link: Option<String>,
+}
+
+/// Itens por página nas listagens paginadas por `$top`/`$skip` (ex.: workspaces).
+const PAGE_SIZE: usize = 1000;
+
+async fn get_page<T: DeserializeOwned>(url: &str, token: &TokenResponse) -> Result<ODataList<T>, String> {
+
+    let client = reqwest::Client::new();
+    let res = client.get(url)
+    .bearer_auth(&token.access_token)
+    .send()
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if !res.status().is_success() {
+        return Err(status_error(&res));
+    }
+
+    res.json().await.map_err(|e| e.to_string())
+}
+
+/// Lista completa, seguindo o `@odata.nextLink` até a última página.
+async fn get_list<T: DeserializeOwned>(url: &str, token: &TokenResponse) -> Result<Vec<T>, String> {
+
+    let mut items = Vec::new();
+    let mut next = Some(url.to_string());
+
+    while let Some(url) = next {
+        let page: ODataList<T> = get_page(&url, token).await?;
+        items.extend(page.value);
+        next = page.next_link;
+    }
+
+    Ok(items)
+}
+
+/// Lista completa de endpoints paginados por `$top`/`$skip`, que devolvem no máximo uma página por chamada.
+async fn get_list_skip<T: DeserializeOwned>(url: &str, token: &TokenResponse) -> Result<Vec<T>, String> {
+
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let mut items = Vec::new();
+
+    loop {
+        let page_url = format!("{}{}$top={}&$skip={}", url, separator, PAGE_SIZE, items.len());
+        let page: ODataList<T> = get_page(&page_url, token).await?;
+        let count = page.value.len();
+        items.extend(page.value);
+
+        // A API também pode indicar a continuação pelo nextLink.
+        if let Some(next_link) = page.next_link {
+            items.extend(get_list::<T>(&next_link, token).await?);
+            break;
+        }
+
+        if count < PAGE_SIZE {
+            break;
+        }
+    }
+
+    Ok(items)
+}
+
+/// Reaproveita a lista guardada em disco enquanto estiver válida; senão, obtém com `fetch` e guarda.
+/// Usado apenas para metadados que mudam pouco; status de atualizações sempre vem da API.
+/// A mesma URL devolve listas diferentes por tenant e conta, então o cache é separado pela identidade do token.
+async fn cached<T: DeserializeOwned>(url: &str, token: &TokenResponse, fetch: impl Future<Output = Result<Vec<Value>, String>>) -> Result<Vec<T>, String> {
+
+    let scope = cache_scope(token);
+
+    if let Some(list) = cache::get(&scope, url).and_then(|value| serde_json::from_value(value).ok()) {
+        return Ok(list);
+    }
+
+    let list: Vec<Value> = fetch.await?;
+    cache::put(&scope, url, &Value::Array(list.clone()));
+
+    list.into_iter().map(serde_json::from_value).collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+/// Como `get_list`, mas com o cache de metadados.
+async fn get_list_cached<T: DeserializeOwned>(url: &str, token: &TokenResponse) -> Result<Vec<T>, String> {
+    cached(url, token, get_list(url, token)).await
+}
+
+/// Dataset pelo ID; `None` quando não existe mais ou não está acessível (404).
+pub async fn get_dataset(dataset_id: &str, token: &TokenResponse) -> Result<Option<Dataset>, String> {
+
+    let client = reqwest::Client::new();
+    let res = client.get(dataset_url(dataset_id))
+    .bearer_auth(&token.access_token)
+    .send()
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    if !res.status().is_success() {
+        return Err(status_error(&res));
+    }
+
+    res.json().await.map(Some).map_err(|e| e.to_string())
+}
+
+/// Indica se o workspace do dataset aceita a atualização aprimorada (capacidade dedicada).
+/// Datasets do "Meu workspace" não aceitam.
+pub async fn supports_enhanced_refresh(dataset_id: &str, token: &TokenResponse) -> Result<bool, String> {
+
+    // O workspace configurado dispensa a consulta do dataset.
+    let group_id = match workspace(dataset_id) {
+        Some(workspace) => Some(workspace).filter(|workspace| workspace != MY_WORKSPACE),
+        None => {
+            let dataset = get_dataset(dataset_id, token).await?.ok_or_else(|| format!("Dataset {} não encontrado.", dataset_id))?;
+            dataset
+                .web_url
+                .as_deref()
+                .and_then(|url| url.split("/groups/").nth(1))
+                .and_then(|rest| rest.split('/').next())
+                .filter(|group| *group != "me")
+                .map(str::to_string)
+        }
+    };
+
+    let Some(group_id) = group_id else {
+        return Ok(false);
+    };
+
+    let groups = list_groups(token).await?;
+    Ok(groups.iter().any(|group| group.id.eq_ignore_ascii_case(&group_id) && group.is_on_dedicated_capacity))
+}
+
+pub async fn list_groups(token: &TokenResponse) -> Result<Vec<Group>, String> {
+    let url = format!("{}/groups", api_base_url());
+    cached(&url, token, get_list_skip(&url, token)).await
+}
+
+pub async fn list_datasets(group_id: &str, token: &TokenResponse) -> Result<Vec<Dataset>, String> {
+    get_list_cached(&format!("{}/groups/{}/datasets", api_base_url(), group_id), token).await
+}
+
+pub async fn list_reports(group_id: &str, token: &TokenResponse) -> Result<Vec<Report>, String> {
+    get_list_cached(&format!("{}/groups/{}/reports", api_base_url(), group_id), token).await
+}
+
+pub async fn list_apps(token: &TokenResponse) -> Result<Vec<App>, String> {
+    get_list_cached(&format!("{}/apps", api_base_url()), token).await
+}
+
+pub async fn list_app_reports(app_id: &str, token: &TokenResponse) -> Result<Vec<Report>, String> {
+    get_list_cached(&format!("{}/apps/{}/reports", api_base_url(), app_id), token).await
+}
+
+/// Datasets do workspace que dependem de dataflows.
+pub async fn list_upstream_dataflows(group_id: &str, token: &TokenResponse) -> Result<Vec<DataflowLink>, String> {
+    get_list_cached(&format!("{}/groups/{}/datasets/upstreamDataflows", api_base_url(), group_id), token).await
+}
+
+pub async fn list_dataflow_transactions(group_id: &str, dataflow_id: &str, token: &TokenResponse) -> Result<Vec<DataflowTransaction>, String> {
+    get_list(&format!("{}/groups/{}/dataflows/{}/transactions", api_base_url(), group_id, dataflow_id), token).await
+}
+
+pub async fn list_pipeline_stages(pipeline_id: &str, token: &TokenResponse) -> Result<Vec<PipelineStage>, String> {
+    get_list(&format!("{}/pipelines/{}/stages", api_base_url(), pipeline_id), token).await
+}
+
+pub async fn list_parameters(group_id: &str, dataset_id: &str, token: &TokenResponse) -> Result<Vec<Parameter>, String> {
+    get_list(&format!("{}/groups/{}/datasets/{}/parameters", api_base_url(), group_id, dataset_id), token).await
+}
+
+pub async fn list_datasources(group_id: &str, dataset_id: &str, token: &TokenResponse) -> Result<Vec<Datasource>, String> {
+    get_list(&format!("{}/groups/{}/datasets/{}/datasources", api_base_url(), group_id, dataset_id), token).await
+}
+
+/// Solicita a atualização do dataflow, sem notificação por e-mail.
+pub async fn refresh_dataflow(group_id: &str, dataflow_id: &str, token: &TokenResponse) -> Result<(), String> {
+
+    ensure_writable("A atualização")?;
+
+    let url = format!("{}/groups/{}/dataflows/{}/refreshes", api_base_url(), group_id, dataflow_id);
+
+    let client = reqwest::Client::new();
+    let res = client.post(url)
+    .bearer_auth(&token.access_token)
+    .json(&json!({ "notifyOption": "NoNotification" }))
+    .send()
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if !res.status().is_success() {
+        return Err(status_error(&res));
+    }
+
+    Ok(())
+}
+
+/// Cancela uma atualização em andamento, identificada pelo `requestId` do histórico de atualizações.
+pub async fn cancel_refresh(dataset_id: &str, request_id: &str, token: &TokenResponse) -> Result<(), String> {
+
+    ensure_writable("A interrupção de atualizações")?;
+
+    let url = format!("{}/refreshes/{}", dataset_url(dataset_id), request_id);
+
+    let client = reqwest::Client::new();
+    let res = client.delete(url)
+    .bearer_auth(&token.access_token)
+    .send()
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if !res.status().is_success() {
+        return Err(status_error(&res));
+    }
+
+    Ok(())
+}
+
+/// Últimas `top` atualizações do dataset, da mais recente para a mais antiga.
+pub async fn list_refreshes(dataset_id: &str, top: u32, token: &TokenResponse) -> Result<Vec<Refresh>, String> {
+    get_list(&format!("{}/refreshes?$top={}", dataset_url(dataset_id), top), token).await
+}
+
+/// Altera os valores dos parâmetros do dataset; vale a partir da próxima atualização.
+pub async fn update_parameters(dataset_id: &str, parameters: &BTreeMap<String, String>, token: &TokenResponse) -> Result<(), String> {
+
+    ensure_writable("A alteração de parâmetros")?;
+
+    let url = format!("{}/Default.UpdateParameters", dataset_url(dataset_id));
+    let details: Vec<_> = parameters.iter().map(|(name, value)| json!({ "name": name, "newValue": value })).collect();
+
+    let client = reqwest::Client::new();
+    let res = client.post(url)
+    .bearer_auth(&token.access_token)
+    .json(&json!({ "updateDetails": details }))
+    .send()
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if !res.status().is_success() {
+        return Err(status_error(&res));
+    }
+
+    Ok(())
+}
+
+/// Executa uma consulta DAX no dataset, devolvendo a resposta da API.
+pub async fn execute_query(dataset_id: &str, query: &str, token: &TokenResponse) -> Result<Value, String> {
+
+    let url = format!("{}/executeQueries", dataset_url(dataset_id));
+    let body = json!({
+        "queries": [{ "query": query }],
+        "serializerSettings": { "includeNulls": true },
+    });
+
+    let client = reqwest::Client::new();
+    let res = client.post(url)
+    .bearer_auth(&token.access_token)
+    .json(&body)
+    .send()
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if !res.status().is_success() {
+        return Err(status_error(&res));
+    }
+
+    res.json().await.map_err(|e| e.to_string())
+}
+
+/// Primeiro valor da primeira linha do resultado da consulta, quando numérico.
+pub async fn query_scalar(dataset_id: &str, query: &str, token: &TokenResponse) -> Result<Option<f64>, String> {
+
+    let response = execute_query(dataset_id, query, token).await?;
+
+    Ok(response
+        .pointer("/results/0/tables/0/rows/0")
+        .and_then(Value::as_object)
+        .and_then(|row| row.values().next())
+        .and_then(Value::as_f64))
+}
+
+/// Tempo máximo de espera pela resposta à solicitação de atualização.
+const SUBMIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Falha ao solicitar a atualização de um dataset.
+#[derive(Debug)]
+pub enum SubmitError {
+    /// A API respondeu recusando a requisição; inclui o RequestId da resposta.
+    Rejected(reqwest::StatusCode, Option<String>),
+    /// Limite de requisições atingido (429), com a espera pedida em `Retry-After`.
+    Throttled(Option<Duration>, Option<String>),
+    /// Sem resposta (tempo esgotado, conexão perdida): a requisição pode ter sido registrada ou não.
+    Ambiguous(String),
+}
+
+/// Identificador aleatório (formato GUID) enviado em cada solicitação de atualização.
+pub fn new_request_id() -> String {
+
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+
+    // Versão 4 (aleatório), variante RFC 4122.
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// Solicita a atualização do dataset, devolvendo o status e o RequestId da resposta.
+/// Sem `body`, é a atualização simples (corpo vazio); com ele, a atualização aprimorada. O `client_request_id` vai no
+/// cabeçalho `x-ms-request-id`, para reconhecer a solicitação no histórico quando a resposta se perde.
+pub async fn send_request_update_dataset(dataset_id: String, body: Option<&Value>, client_request_id: &str, token: &TokenResponse) -> Result<(reqwest::StatusCode, Option<String>), SubmitError> {
+
+    let url = format!("{}/refreshes", dataset_url(&dataset_id));
+    let access_token = token.access_token.clone();
+
+    let client = reqwest::Client::new();
+    let request = client.post(url)
+    .bearer_auth(access_token)
+    .header(CLIENT_REQUEST_ID_HEADER, client_request_id)
+    .timeout(SUBMIT_TIMEOUT);
+
+    let request = match body {
+        Some(body) => request.json(body),
+        None => request.header("Content-Length", 0),
+    };
+
+    let res = request
+    .send()
+    .await
+    .map_err(|e| SubmitError::Ambiguous(e.to_string()))?;
+
+    if res.status().is_success() {
+        Ok((res.status(), request_id(&res)))
+    } else if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        Err(SubmitError::Throttled(retry_after(&res), request_id(&res)))
+    } else {
+        Err(SubmitError::Rejected(res.status(), request_id(&res)))
+    }
+}
+
+/// Espera pedida no cabeçalho `Retry-After`, em segundos ou como data HTTP.
+fn retry_after(res: &reqwest::Response) -> Option<Duration> {
+
+    let value = res.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
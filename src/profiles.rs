@@ -0,0 +1,156 @@
+use std::{collections::BTreeMap, sync::Arc};
+use colored::Colorize;
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::config::{check_workspaces, AppConfig, Secrets};
+use crate::history::RefreshRecord;
+use crate::locale;
+use crate::monitor::{WaitOptions, STATUS_IN_PROGRESS};
+use crate::powerbi::{get_dataset, list_refreshes, Refresh};
+use crate::session::{round_robin, Session};
+use crate::token::load_token;
+
+/// Nome exibido do perfil.
+pub fn profile_name(secrets: &Secrets) -> &str {
+    secrets.profile.as_deref().unwrap_or("padrão")
+}
+
+/// Atualiza as empresas de todos os perfis na mesma execução. Cada perfil usa o próprio token e tem até
+/// `concurrency` atualizações simultâneas; os perfis avançam intercalados.
+pub async fn run_all_profiles(config: &AppConfig, profiles: Vec<Secrets>, concurrency: usize, wait: Option<WaitOptions>) -> Vec<RefreshRecord> {
+
+    let mut tasks: JoinSet<Result<Vec<RefreshRecord>, String>> = JoinSet::new();
+    let mut sessions = Vec::new();
+
+    for secrets in profiles {
+
+        let name = profile_name(&secrets).to_string();
+
+        if let Err(e) = check_workspaces(config, &secrets) {
+            eprintln!("{}", format!("Perfil {}: empresas do perfil ignoradas.\n{}", name, e).red());
+            continue;
+        }
+
+        // Cada perfil reaproveita o próprio token gravado.
+        let token = match load_token(&secrets).await {
+            Ok(token) => token,
+            Err(e) => {
+                eprintln!("{}", format!("Perfil {}: erro ao gerar token; empresas do perfil ignoradas.\n{}", name, e).red());
+                continue;
+            }
+        };
+
+        let session = Arc::new(Session::new(secrets, config, token));
+        sessions.push(session.clone());
+        let targets = round_robin(session.all_targets());
+        println!("Perfil {}: {} dataset(s).", name, targets.len());
+
+        let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+
+        for target in targets {
+            let (session, semaphore) = (session.clone(), semaphore.clone());
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await.unwrap();
+                session.run(vec![target], wait).await
+            });
+        }
+    }
+
+    let mut records = Vec::new();
+
+    while let Some(result) = tasks.join_next().await {
+        match result {
+            Ok(Ok(mut task_records)) => records.append(&mut task_records),
+            Ok(Err(e)) => eprintln!("{}", e),
+            Err(e) => eprintln!("Falha na tarefa de atualização.\n{}", e),
+        }
+    }
+
+    for session in sessions {
+        session.flush().await;
+    }
+
+    records
+}
+
+/// Última atualização registrada no PowerBI de cada dataset das empresas do perfil, pelo nome do dataset (os GUIDs
+/// mudam de um ambiente para outro).
+async fn last_refreshes(config: &AppConfig, secrets: Secrets) -> Result<BTreeMap<String, Option<Refresh>>, String> {
+
+    let name = profile_name(&secrets).to_string();
+    let token = load_token(&secrets).await.map_err(|e| format!("Perfil {}: erro ao gerar token.\n{}", name, e))?;
+    let session = Session::new(secrets, config, token.clone());
+
+    let mut refreshes = BTreeMap::new();
+
+    for (_, dataset) in session.all_targets() {
+        let found = match get_dataset(&dataset.id, &token).await {
+            Ok(Some(found)) => found,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("Perfil {}: falha ao consultar dataset {}.\n{}", name, dataset.id, e);
+                continue;
+            }
+        };
+
+        let last = list_refreshes(&dataset.id, 1, &token).await.ok().and_then(|refreshes| refreshes.into_iter().next());
+        refreshes.insert(found.name, last);
+    }
+
+    Ok(refreshes)
+}
+
+fn describe_refresh(refresh: Option<&Refresh>) -> String {
+    match refresh {
+        Some(refresh) => {
+            let status = if refresh.status == STATUS_IN_PROGRESS { "Em andamento" } else { refresh.status.as_str() };
+            match refresh.end_time.or(refresh.start_time) {
+                Some(time) => format!("{} em {}", status, locale::datetime(time)),
+                None => status.to_string(),
+            }
+        }
+        None => "nunca atualizado".to_string(),
+    }
+}
+
+/// Compara, lado a lado, a última atualização dos datasets presentes nos dois perfis (ex.: `dev` e `prod`),
+/// destacando os que terminaram com status diferente, sinal de divergência entre os ambientes após uma implantação.
+pub async fn compare(config: &AppConfig, left: Secrets, right: Secrets) -> Result<(), String> {
+
+    let (left_name, right_name) = (profile_name(&left).to_string(), profile_name(&right).to_string());
+    let left = last_refreshes(config, left).await?;
+    let right = last_refreshes(config, right).await?;
+
+    let mut drift = 0;
+
+    for (dataset, left_refresh) in &left {
+        let Some(right_refresh) = right.get(dataset) else {
+            continue;
+        };
+
+        let line = format!(
+            "{}: {} {} | {} {}",
+            dataset,
+            left_name, describe_refresh(left_refresh.as_ref()),
+            right_name, describe_refresh(right_refresh.as_ref()),
+        );
+
+        let same = left_refresh.as_ref().map(|refresh| &refresh.status) == right_refresh.as_ref().map(|refresh| &refresh.status);
+        if same {
+            println!("{}", line);
+        } else {
+            drift += 1;
+            println!("{}", line.yellow());
+        }
+    }
+
+    let only_left = left.keys().filter(|dataset| !right.contains_key(*dataset)).count();
+    let only_right = right.keys().filter(|dataset| !left.contains_key(*dataset)).count();
+
+    println!(
+        "{} dataset(s) com resultado diferente; {} apenas em {} e {} apenas em {}.",
+        drift, only_left, left_name, only_right, right_name
+    );
+
+    Ok(())
+}
@@ -0,0 +1,378 @@
+use std::{future::Future, sync::{atomic::{AtomicUsize, Ordering}, OnceLock}, time::{Duration, Instant}};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use crate::config::{DatasetConfig, RefreshMode, RetryPolicy, Validation};
+use crate::history::{append_record, last_success_at, last_watermark, previous_day_validation, RefreshRecord, SkipReason};
+use crate::locale;
+use crate::monitor::STATUS_IN_PROGRESS;
+use crate::session::round_robin;
+use crate::powerbi::{execute_query, list_refreshes, new_request_id, query_scalar, send_request_update_dataset, supports_enhanced_refresh, Refresh, SubmitError};
+use crate::token::TokenResponse;
+use crate::ui::alert;
+use crate::watermark;
+
+/// Status registrado quando nenhuma tentativa obteve resposta.
+const STATUS_NO_RESPONSE: u16 = 0;
+/// Esperas por limite de requisições (429) antes de desistir do dataset; não contam como tentativas da política.
+const THROTTLE_RETRIES: u32 = 10;
+/// Espera máxima por um `Retry-After`.
+const MAX_THROTTLE_WAIT: Duration = Duration::from_secs(10 * 60);
+/// Atualizações recentes consultadas à procura de um envio sem resposta.
+const RECENT_REFRESHES: u32 = 5;
+/// Margem para diferenças de relógio entre a máquina e o serviço.
+const CLOCK_SKEW_SECONDS: i64 = 30;
+
+static RETRY_POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+static MAX_PARALLEL: AtomicUsize = AtomicUsize::new(1);
+
+/// Define as novas tentativas do envio (`retry` no `dataset.json`).
+pub fn set_retry_policy(policy: RetryPolicy) {
+    let _ = RETRY_POLICY.set(policy);
+}
+
+/// Define o máximo de envios simultâneos (`max_parallel` no `dataset.json`).
+pub fn set_max_parallel(limit: usize) {
+    MAX_PARALLEL.store(limit.max(1), Ordering::Relaxed);
+}
+
+pub fn max_parallel() -> usize {
+    MAX_PARALLEL.load(Ordering::Relaxed)
+}
+
+/// Envio sem resposta encontrado no histórico do PowerBI: pelo identificador enviado ou, sem ele, por uma atualização
+/// via API iniciada depois do envio (presumida).
+fn registered<'a>(refreshes: &'a [Refresh], sent: &[(String, DateTime<Utc>)]) -> Option<(&'a Refresh, bool)> {
+
+    let exact = refreshes
+        .iter()
+        .find(|refresh| refresh.request_id.as_ref().is_some_and(|id| sent.iter().any(|(sent_id, _)| sent_id.eq_ignore_ascii_case(id))));
+    if let Some(refresh) = exact {
+        return Some((refresh, false));
+    }
+
+    let first_sent = sent.first()?.1;
+    refresh_via_api_since(refreshes, first_sent).map(|refresh| (refresh, true))
+}
+
+fn refresh_via_api_since(refreshes: &[Refresh], since: DateTime<Utc>) -> Option<&Refresh> {
+    refreshes.iter().find(|refresh| {
+        refresh.refresh_type.as_deref().is_none_or(|kind| kind == "ViaApi") && refresh.start_time.is_some_and(|start| start >= since)
+    })
+}
+
+/// Solicita a atualização, repetindo o envio em falhas transitórias: sem resposta ou erro 5xx. Antes de repetir, confere
+/// se a tentativa anterior chegou a ser registrada, para não disparar a atualização duas vezes e consumir a cota à toa.
+/// Quando a API limita as requisições (429), aguarda o `Retry-After` e envia de novo.
+/// Devolve se foi aceita, o status HTTP, o RequestId da última resposta e se o registro foi presumido pelo horário.
+async fn submit_idempotent(dataset: &str, body: Option<&Value>, token: &TokenResponse) -> (bool, u16, Option<String>, bool) {
+
+    let policy = RETRY_POLICY.get().copied().unwrap_or_default();
+    let mut last = (false, STATUS_NO_RESPONSE, None, false);
+    let mut attempt = 1;
+    let mut throttled = 0;
+
+    // Identificador e horário de cada envio, tomado logo antes dele.
+    let mut sent: Vec<(String, DateTime<Utc>)> = Vec::new();
+
+    loop {
+
+        let request_id = new_request_id();
+        sent.push((request_id.clone(), Utc::now() - chrono::Duration::seconds(CLOCK_SKEW_SECONDS)));
+
+        match send_request_update_dataset(dataset.to_string(), body, &request_id, token).await {
+            Ok((status, request_id)) => return (true, status.as_u16(), request_id, false),
+            Err(SubmitError::Throttled(wait, _)) if throttled < THROTTLE_RETRIES => {
+                throttled += 1;
+                let wait = wait.unwrap_or_else(|| policy.delay(throttled)).min(MAX_THROTTLE_WAIT);
+                println!("Dataset {}: limite de requisições da API atingido; nova solicitação em {} segundos.", dataset, wait.as_secs());
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+            Err(SubmitError::Throttled(_, request_id)) => return (false, StatusCode::TOO_MANY_REQUESTS.as_u16(), request_id, false),
+            Err(SubmitError::Rejected(status, request_id)) if status.is_server_error() => {
+                eprintln!("Dataset {}: a API respondeu {} (tentativa {}).", dataset, status, attempt);
+                last = (false, status.as_u16(), request_id, false);
+            }
+            Err(SubmitError::Rejected(status, request_id)) => return (false, status.as_u16(), request_id, false),
+            Err(SubmitError::Ambiguous(e)) => eprintln!("Sem resposta ao solicitar atualização do dataset {} (tentativa {}).\n{}", dataset, attempt, e),
+        }
+
+        if let Ok(refreshes) = list_refreshes(dataset, RECENT_REFRESHES, token).await {
+            match registered(&refreshes, &sent) {
+                Some((refresh, false)) => {
+                    println!("Dataset {}: a solicitação anterior foi registrada; não será reenviada.", dataset);
+                    return (true, StatusCode::ACCEPTED.as_u16(), refresh.request_id.clone(), false);
+                }
+                Some((refresh, true)) => {
+                    println!(
+                        "Dataset {}: uma atualização via API começou depois do envio (RequestId {}); presumida como a solicitação anterior, sem reenvio.",
+                        dataset,
+                        refresh.request_id.as_deref().unwrap_or("-")
+                    );
+                    return (true, StatusCode::ACCEPTED.as_u16(), refresh.request_id.clone(), true);
+                }
+                None => {}
+            }
+        }
+
+        if attempt >= policy.attempts {
+            return last;
+        }
+
+        tokio::time::sleep(policy.delay(attempt)).await;
+        attempt += 1;
+    }
+}
+
+/// Corpo da solicitação conforme o `refresh_mode` e as `refresh_options` do dataset; `None` é a atualização simples.
+/// No modo `auto`, sem como consultar o workspace, vale a simples, aceita em qualquer capacidade. Com `objects` ou
+/// `refresh_options` no modo `simple`, a atualização é aprimorada: a simples processaria o modelo inteiro e ignoraria
+/// as opções.
+async fn request_body(dataset: &DatasetConfig, token: &TokenResponse) -> Option<Value> {
+
+    let enhanced = match dataset.refresh_mode {
+        _ if !dataset.objects.is_empty() => true,
+        RefreshMode::Simple => dataset.refresh_options.is_some(),
+        RefreshMode::Enhanced => true,
+        RefreshMode::Auto => supports_enhanced_refresh(&dataset.id, token).await.unwrap_or_else(|e| {
+            eprintln!("Falha ao verificar a capacidade do dataset {}; usando a atualização simples.\n{}", dataset.id, e);
+            false
+        }),
+    };
+
+    if !enhanced && dataset.refresh_options.is_some() {
+        eprintln!("Dataset {}: workspace sem capacidade dedicada; refresh_options ignoradas na atualização simples.", dataset.id);
+    }
+
+    enhanced.then(|| {
+        let mut body = dataset.refresh_options.clone().unwrap_or_default().body();
+        if !dataset.objects.is_empty() {
+            body["objects"] = json!(dataset.objects);
+        }
+        body
+    })
+}
+
+/// Registro de um dataset que não foi enviado, gravado no histórico com o motivo.
+fn skipped_record(company: u32, dataset: &str, note: Option<&str>, reason: SkipReason) -> RefreshRecord {
+
+    let record = RefreshRecord {
+        timestamp: Utc::now(),
+        company,
+        dataset: dataset.to_string(),
+        accepted: false,
+        status: STATUS_NO_RESPONSE,
+        completed_at: None,
+        started_at: None,
+        duration_secs: None,
+        result: None,
+        warmup_ms: None,
+        validation_value: None,
+        note: note.map(str::to_string),
+        skipped: Some(reason),
+        request_id: None,
+        presumed: false,
+        watermark: None,
+        error_code: None,
+    };
+
+    append_record(&record);
+    record
+}
+
+/// Envia a requisição de atualização de um dataset e registra o resultado no histórico, com o motivo informado.
+/// Quando a API recusa o token (401/403), obtém outro com `renew` e repete o envio uma única vez.
+/// Datasets desativados, ou com a marca d'água da origem igual à da última atualização, não são enviados.
+pub async fn refresh_dataset<F, Fut>(company: u32, config: &DatasetConfig, note: Option<&str>, token: &TokenResponse, renew: F) -> RefreshRecord
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<TokenResponse, String>>,
+{
+    let timestamp = Utc::now();
+    let dataset = config.id.as_str();
+
+    if config.disabled {
+        return skipped_record(company, dataset, note, SkipReason::Disabled);
+    }
+
+    if let Some(minutes) = config.min_interval_minutes {
+        if let Some(last) = last_success_at(dataset).filter(|last| timestamp - *last < chrono::Duration::minutes(minutes as i64)) {
+            println!("Dataset {}: atualizado com sucesso em {}, há menos de {} minutos.", dataset, locale::datetime(last), minutes);
+            return skipped_record(company, dataset, note, SkipReason::Recent);
+        }
+    }
+
+    // A API recusa (400) uma nova atualização enquanto outra está em andamento; sem a consulta, ficaria como "Negada".
+    // Se a consulta falhar, o envio segue normalmente.
+    if let Ok(refreshes) = list_refreshes(dataset, 1, token).await {
+        if refreshes.first().is_some_and(|refresh| refresh.status == STATUS_IN_PROGRESS) {
+            println!("Dataset {}: já existe uma atualização em andamento; nova solicitação não enviada.", dataset);
+            return skipped_record(company, dataset, note, SkipReason::InProgress);
+        }
+    }
+
+    let watermark = match &config.watermark {
+        None => None,
+        Some(source) => match watermark::read(source).await {
+            Ok(current) if last_watermark(dataset).as_deref() == Some(current.as_str()) => {
+                println!("Dataset {}: marca d'água {} sem alteração desde a última atualização.", dataset, current);
+                return skipped_record(company, dataset, note, SkipReason::Stale);
+            }
+            Ok(current) => Some(current),
+            Err(e) => {
+                eprintln!("Falha ao consultar a marca d'água do dataset {}.\n{}", dataset, e);
+                return skipped_record(company, dataset, note, SkipReason::Gate);
+            }
+        },
+    };
+
+    let body = request_body(config, token).await;
+    let (mut accepted, mut status, mut request_id, mut presumed) = submit_idempotent(dataset, body.as_ref(), token).await;
+
+    // Um token invalidado no servidor (senha alterada, revogação) é recusado mesmo dentro da validade.
+    if !accepted && (status == StatusCode::UNAUTHORIZED.as_u16() || status == StatusCode::FORBIDDEN.as_u16()) {
+        println!("Dataset {}: token recusado pela API ({}); renovando e tentando novamente.", dataset, status);
+        match renew().await {
+            Ok(token) => (accepted, status, request_id, presumed) = submit_idempotent(dataset, body.as_ref(), &token).await,
+            Err(e) => eprintln!("Falha ao renovar token.\n{}", e),
+        }
+    }
+
+    let record = RefreshRecord {
+        timestamp,
+        company,
+        dataset: dataset.to_string(),
+        accepted,
+        status,
+        completed_at: None,
+        started_at: None,
+        duration_secs: None,
+        result: None,
+        warmup_ms: None,
+        validation_value: None,
+        note: note.map(str::to_string),
+        skipped: None,
+        request_id,
+        presumed,
+        watermark,
+        error_code: None,
+    };
+
+    append_record(&record);
+    record
+}
+
+/// Atualiza todos os datasets de uma empresa exibindo o resultado de cada requisição.
+pub async fn refresh_company<F, Fut>(company: u32, datasets: &[DatasetConfig], note: Option<&str>, token: &TokenResponse, renew: F) -> Vec<RefreshRecord>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<TokenResponse, String>>,
+{
+    refresh_companies(&[(company, datasets)], note, token, renew).await
+}
+
+/// Atualiza os datasets das empresas com até `max_parallel` requisições simultâneas, enviadas em rodízio entre as
+/// empresas. Os resultados são exibidos na ordem das empresas, como na atualização em sequência.
+pub async fn refresh_companies<F, Fut>(companies: &[(u32, &[DatasetConfig])], note: Option<&str>, token: &TokenResponse, renew: F) -> Vec<RefreshRecord>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<TokenResponse, String>>,
+{
+
+    let renew = &renew;
+    let ordered: Vec<(u32, &DatasetConfig)> = companies
+        .iter()
+        .flat_map(|(company, datasets)| datasets.iter().map(move |dataset| (*company, dataset)))
+        .collect();
+
+    // Cada envio leva a posição do dataset na ordem das empresas, usada na exibição.
+    let interleaved = round_robin(ordered.iter().enumerate().map(|(index, (company, dataset))| (*company, (index, *dataset))).collect());
+
+    let mut results = stream::iter(interleaved)
+        .map(|(company, (index, dataset))| async move { (index, refresh_dataset(company, dataset, note, token, renew).await) })
+        .buffer_unordered(max_parallel());
+
+    let mut pending: Vec<Option<RefreshRecord>> = ordered.iter().map(|_| None).collect();
+    let mut records = Vec::new();
+    let mut current = None;
+
+    while let Some((index, record)) = results.next().await {
+        pending[index] = Some(record);
+
+        // Exibe os resultados que já têm todos os anteriores (na ordem das empresas) exibidos.
+        while let Some(record) = pending.get_mut(records.len()).and_then(Option::take) {
+            let company = ordered[records.len()].0;
+            print_company_result(company, &mut current, &record);
+            records.push(record);
+        }
+    }
+
+    records
+}
+
+/// Exibe o resultado do dataset, com o cabeçalho da empresa quando ela muda.
+fn print_company_result(company: u32, current: &mut Option<u32>, record: &RefreshRecord) {
+
+    if *current != Some(company) {
+        println!("Empresa: {}", company);
+        *current = Some(company);
+    }
+
+    if record.outcome().is_failure(false) {
+        // Caso a requisição retorne falha.
+        eprintln!("\t- Requisição: {}", record.describe());
+        if let Some(request_id) = &record.request_id {
+            eprintln!("\t  RequestId: {}", request_id);
+        }
+    } else {
+        // Caso a requisição retorne sucesso.
+        println!("\t- Requisição: {}", record.describe());
+    }
+}
+
+/// Executa as consultas de aquecimento do dataset, registrando o tempo total no resultado.
+pub async fn warm_up(dataset: &DatasetConfig, record: RefreshRecord, token: &TokenResponse) -> RefreshRecord {
+
+    let started = Instant::now();
+
+    for query in &dataset.warmup {
+        if let Err(e) = execute_query(&dataset.id, query, token).await {
+            eprintln!("Falha na consulta de aquecimento do dataset {}.\n{}", dataset.id, e);
+        }
+    }
+
+    RefreshRecord { warmup_ms: Some(started.elapsed().as_millis() as u64), ..record }
+}
+
+/// Executa a consulta de validação e alerta quando o valor cai mais que o tolerado em relação ao dia anterior,
+/// sinal de carga parcial mesmo com a atualização concluída.
+pub async fn validate(dataset: &DatasetConfig, validation: &Validation, record: RefreshRecord, token: &TokenResponse) -> RefreshRecord {
+
+    let value = match query_scalar(&dataset.id, &validation.query, token).await {
+        Ok(Some(value)) => value,
+        Ok(None) => {
+            eprintln!("Consulta de validação do dataset {} não devolveu um número.", dataset.id);
+            return record;
+        }
+        Err(e) => {
+            eprintln!("Falha na consulta de validação do dataset {}.\n{}", dataset.id, e);
+            return record;
+        }
+    };
+
+    if let Some(previous) = previous_day_validation(&dataset.id, record.timestamp) {
+        let drop_pct = if previous > 0.0 { (previous - value) / previous * 100.0 } else { 0.0 };
+
+        if drop_pct > validation.max_drop_pct {
+            alert(&format!(
+                "Dataset {} (empresa {}): validação caiu {:.1}% ({} -> {}), acima do limite de {}%.",
+                dataset.id, record.company, drop_pct, previous, value, validation.max_drop_pct,
+            ));
+        }
+    }
+
+    RefreshRecord { validation_value: Some(value), ..record }
+}
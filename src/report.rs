@@ -0,0 +1,219 @@
+use std::{collections::BTreeMap, fs};
+use chrono::{Local, NaiveDate, Utc};
+use clap::ValueEnum;
+
+use crate::config::{AppConfig, GuidEntry};
+use crate::history::{read_records, RefreshRecord};
+use crate::locale;
+use crate::server::escape_html;
+
+/// Formato de exportação do relatório.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    Csv,
+    Html,
+}
+
+impl ReportFormat {
+    pub fn default_filename(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "sla.csv",
+            ReportFormat::Html => "sla.html",
+        }
+    }
+}
+
+/// Indicadores de um dataset no período.
+struct SlaRow {
+    company: u32,
+    dataset: String,
+    requests: usize,
+    accepted: usize,
+    finished: usize,
+    completed: usize,
+    total_duration_secs: i64,
+    with_deadline: usize,
+    within_deadline: usize,
+}
+
+impl SlaRow {
+
+    fn percent(part: usize, total: usize) -> String {
+        if total == 0 { "-".to_string() } else { locale::number(part as f64 * 100.0 / total as f64, 1) }
+    }
+
+    fn values(&self) -> [String; 7] {
+        let average_minutes = if self.completed == 0 {
+            "-".to_string()
+        } else {
+            locale::number(self.total_duration_secs as f64 / self.completed as f64 / 60.0, 1)
+        };
+
+        [
+            self.company.to_string(),
+            self.dataset.clone(),
+            self.requests.to_string(),
+            Self::percent(self.accepted, self.requests),
+            Self::percent(self.completed, self.finished),
+            average_minutes,
+            Self::percent(self.within_deadline, self.with_deadline),
+        ]
+    }
+}
+
+const HEADERS: [&str; 7] = ["empresa", "dataset", "requisicoes", "aceitas_pct", "concluidas_pct", "duracao_media_min", "prazo_cumprido_pct"];
+
+fn build_rows(records: &[RefreshRecord], companies: &[GuidEntry]) -> Vec<SlaRow> {
+
+    let mut rows: BTreeMap<(u32, String), SlaRow> = BTreeMap::new();
+
+    // Datasets ignorados não geraram requisição.
+    for record in records.iter().filter(|record| record.skipped.is_none()) {
+
+        let row = rows.entry((record.company, record.dataset.clone())).or_insert_with(|| SlaRow {
+            company: record.company,
+            dataset: record.dataset.clone(),
+            requests: 0,
+            accepted: 0,
+            finished: 0,
+            completed: 0,
+            total_duration_secs: 0,
+            with_deadline: 0,
+            within_deadline: 0,
+        });
+
+        // O histórico guarda o envio e, quando acompanhado, o resultado final em outro registro.
+        let Some(result) = &record.result else {
+            row.requests += 1;
+            row.accepted += record.accepted as usize;
+            continue;
+        };
+
+        row.finished += 1;
+
+        if result != "Completed" {
+            continue;
+        }
+
+        row.completed += 1;
+        row.total_duration_secs += record.duration_secs.unwrap_or(0);
+
+        let deadline = companies
+            .iter()
+            .flat_map(|company| company.guid.iter().map(move |dataset| (company, dataset)))
+            .find(|(_, dataset)| dataset.id == record.dataset)
+            .and_then(|(company, dataset)| dataset.deadline.map(|deadline| (deadline, dataset.timezone.or(company.timezone))));
+
+        if let (Some((deadline, timezone)), Some(completed_at)) = (deadline, record.completed_at) {
+            row.with_deadline += 1;
+            row.within_deadline += (completed_at <= locale::next_occurrence(deadline, record.timestamp, timezone)) as usize;
+        }
+    }
+
+    rows.into_values().collect()
+}
+
+fn render_csv(rows: &[SlaRow]) -> String {
+
+    let separator = locale::csv_separator();
+    let mut content = HEADERS.join(separator);
+    content.push('\n');
+
+    for row in rows {
+        content.push_str(&row.values().join(separator));
+        content.push('\n');
+    }
+
+    content
+}
+
+fn render_html(rows: &[SlaRow], from: Option<NaiveDate>, to: Option<NaiveDate>) -> String {
+
+    let period = format!(
+        "{} a {}",
+        from.map(locale::date).unwrap_or_else(|| "início".to_string()),
+        to.map(locale::date).unwrap_or_else(|| "hoje".to_string()),
+    );
+
+    let header: String = HEADERS.iter().map(|header| format!("<th>{}</th>", header)).collect();
+    let body: String = rows
+        .iter()
+        .map(|row| {
+            let cells: String = row.values().iter().map(|value| format!("<td>{}</td>", escape_html(value))).collect();
+            format!("        <tr>{}</tr>\n", cells)
+        })
+        .collect();
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"pt-BR\">\n<head>\n    <meta charset=\"utf-8\">\n    <title>SLA das atualizações</title>\n</head>\n<body>\n    <h1>SLA das atualizações</h1>\n    <p>Período: {}</p>\n    <table border=\"1\">\n        <tr>{}</tr>\n{}    </table>\n</body>\n</html>\n",
+        period, header, body,
+    )
+}
+
+/// Gera o relatório de SLA por dataset a partir do histórico, no período informado.
+pub fn export_sla(companies: &[GuidEntry], from: Option<NaiveDate>, to: Option<NaiveDate>, format: ReportFormat, output: &str) -> Result<usize, String> {
+
+    let records: Vec<RefreshRecord> = read_records()
+        .into_iter()
+        .filter(|record| from.is_none_or(|from| locale::local_date(record.timestamp) >= from))
+        .filter(|record| to.is_none_or(|to| locale::local_date(record.timestamp) <= to))
+        .collect();
+
+    let rows = build_rows(&records, companies);
+
+    let content = match format {
+        ReportFormat::Csv => render_csv(&rows),
+        ReportFormat::Html => render_html(&rows, from, to),
+    };
+
+    fs::write(output, content).map_err(|e| format!("Falha ao gravar relatório {}.\nErro: {}", output, e))?;
+
+    Ok(rows.len())
+}
+
+/// Texto de uma propriedade do iCalendar, com os caracteres especiais escapados.
+fn ics_text(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+/// Gera um iCalendar com um evento diário por empresa em cada horário de `schedule`, no fuso configurado
+/// (o mesmo usado pelo `serve`); sem fuso, os horários ficam no horário local de quem abrir a agenda.
+pub fn export_calendar(config: &AppConfig, output: &str) -> Result<usize, String> {
+
+    if config.schedule.is_empty() {
+        return Err("Nenhum horário em \"schedule\" no dataset.json; não há atualizações automáticas para exportar.".to_string());
+    }
+
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+    let today = Local::now().date_naive();
+    let mut events = Vec::new();
+
+    for company in &config.companies {
+        for time in &config.schedule {
+
+            let start = today.and_time(*time).format("%Y%m%dT%H%M%S");
+            let start = match config.timezone {
+                Some(timezone) => format!("DTSTART;TZID={}:{}", timezone.name(), start),
+                None => format!("DTSTART:{}", start),
+            };
+
+            let datasets: Vec<&str> = company.guid.iter().map(|dataset| dataset.name.as_deref().unwrap_or(&dataset.id)).collect();
+
+            events.push(format!(
+                "BEGIN:VEVENT\r\nUID:power-bi-updater-{}-{}\r\nDTSTAMP:{}\r\n{}\r\nDURATION:PT30M\r\nRRULE:FREQ=DAILY\r\nSUMMARY:{}\r\nDESCRIPTION:{}\r\nEND:VEVENT\r\n",
+                company.id, time.format("%H%M"), stamp, start,
+                ics_text(&format!("Atualização PowerBI - empresa {}", company.id)),
+                ics_text(&format!("Datasets: {}", datasets.join(", "))),
+            ));
+        }
+    }
+
+    let content = format!(
+        "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//power-bi-updater//agenda//PT\r\nCALSCALE:GREGORIAN\r\n{}END:VCALENDAR\r\n",
+        events.concat(),
+    );
+
+    fs::write(output, content).map_err(|e| format!("Falha ao gravar agenda {}.\nErro: {}", output, e))?;
+
+    Ok(events.len())
+}
@@ -0,0 +1,80 @@
+use std::{fs, sync::Arc};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+use crate::history::RefreshRecord;
+use crate::monitor::WaitOptions;
+use crate::session::Session;
+
+/// Escolha feita no menu interativo, reproduzida na mesma ordem.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RunbookStep {
+    RefreshAll {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        note: Option<String>,
+    },
+    RefreshCompany {
+        company: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        note: Option<String>,
+    },
+}
+
+impl RunbookStep {
+    fn describe(&self) -> String {
+        match self {
+            RunbookStep::RefreshAll { .. } => "Atualizar todas as empresas".to_string(),
+            RunbookStep::RefreshCompany { company, .. } => format!("Atualizar empresa {}", company),
+        }
+    }
+}
+
+/// Sessão interativa gravada com `--record`, reproduzida com `run runbook`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Runbook {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub steps: Vec<RunbookStep>,
+}
+
+pub fn read_runbook(path: &str) -> Result<Runbook, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Falha ao ler runbook {}.\nErro: {}", path, e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Runbook {} inválido.\nErro: {}", path, e))
+}
+
+pub fn write_runbook(path: &str, runbook: &Runbook) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(runbook).map_err(|e| e.to_string())?;
+    fs::write(path, content).map_err(|e| format!("Falha ao gravar runbook {}.\nErro: {}", path, e))
+}
+
+/// Executa os passos em sequência. Uma empresa que não existe mais no `dataset.json` interrompe a execução,
+/// já que o runbook deixou de corresponder à configuração.
+pub async fn replay(session: &Arc<Session>, runbook: &Runbook, wait: Option<WaitOptions>) -> Result<Vec<RefreshRecord>, String> {
+
+    if runbook.steps.is_empty() {
+        return Err("Runbook sem passos gravados.".to_string());
+    }
+
+    let mut records = Vec::new();
+
+    for (index, step) in runbook.steps.iter().enumerate() {
+
+        println!("{}", format!("Passo {}: {}", index + 1, step.describe()).cyan());
+
+        let (mut targets, note) = match step {
+            RunbookStep::RefreshAll { note } => (session.all_targets(), note),
+            RunbookStep::RefreshCompany { company, note } => (session.company_targets(*company)?, note),
+        };
+
+        for (_, dataset) in targets.iter_mut() {
+            dataset.note = note.clone();
+        }
+
+        records.extend(session.run(targets, wait).await?);
+    }
+
+    Ok(records)
+}
@@ -0,0 +1,481 @@
+use std::{collections::{HashMap, HashSet}, net::SocketAddr, sync::{atomic::{AtomicBool, Ordering}, Arc, RwLock}, time::Duration};
+use axum::{Router, routing::{get, post}, extract::{Path, Query, State}, response::{Html, Json, Redirect, IntoResponse}, http::{header::AUTHORIZATION, HeaderMap, StatusCode}};
+use chrono::{DateTime, NaiveTime, Utc};
+use tokio::sync::Mutex;
+
+use crate::config::{dataset_workspaces, ensure_writable, load_config_file, AppConfig, DatasetConfig};
+use crate::control;
+use crate::eventgrid;
+use crate::grpc;
+use crate::history::{append_record, last_outcomes, read_dataset_records, read_records, Outcome, RefreshRecord};
+use crate::history_store::history_key;
+use crate::job;
+use crate::links;
+use crate::locale;
+use crate::maintenance;
+use crate::monitor::WaitOptions;
+use crate::notify::send_message;
+use crate::powerbi::set_workspaces;
+use crate::session::Session;
+
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+/// Chave exigida pelos botões de atualização do painel; sem ela, o painel apenas exibe o status.
+pub const ENV_DASHBOARD_KEY: &str = "PBI_DASHBOARD_KEY";
+
+/// Parâmetros do modo servidor.
+pub struct ServeOptions {
+    pub bind: SocketAddr,
+    pub grpc_bind: Option<SocketAddr>,
+    pub interval_minutes: Option<u64>,
+    /// Horários de atualização diária, no fuso configurado.
+    pub daily_at: Vec<NaiveTime>,
+    /// Chave exigida (via `?key=`) no webhook do Event Grid.
+    pub events_key: Option<String>,
+    /// Acompanha as atualizações enviadas até o resultado final (prazos, histórico de duração).
+    pub wait: Option<WaitOptions>,
+    /// Intervalo, em minutos, da busca por datasets atualizáveis que não estão configurados.
+    pub discover_minutes: Option<u64>,
+    /// Socket (ou named pipe, no Windows) de controle do processo em execução.
+    pub control: Option<String>,
+}
+
+/// Configurações em uso pelo servidor, substituídas por inteiro no `reload` do socket de controle.
+pub struct Loaded {
+    pub session: Arc<Session>,
+    pub config: Arc<AppConfig>,
+    /// Padrões de assunto de eventos e a empresa que cada um atualiza.
+    pub triggers: Vec<(glob::Pattern, u32)>,
+    pub loaded_at: DateTime<Utc>,
+}
+
+impl Loaded {
+
+    fn new(session: Arc<Session>, config: AppConfig) -> Self {
+
+        let mut triggers = Vec::new();
+
+        for entry in &config.companies {
+            for trigger in &entry.triggers {
+                match glob::Pattern::new(trigger) {
+                    Ok(pattern) => triggers.push((pattern, entry.id)),
+                    Err(e) => eprintln!("Padrão de evento inválido na empresa {}: {}\nErro: {}", entry.id, trigger, e),
+                }
+            }
+        }
+
+        Loaded { session, config: Arc::new(config), triggers, loaded_at: Utc::now() }
+    }
+}
+
+pub struct ServerState {
+    loaded: RwLock<Arc<Loaded>>,
+    pub events_key: Option<String>,
+    /// Chave dos links assinados de atualização; sem ela, os links ficam desativados.
+    pub links_key: Option<String>,
+    /// Chave das ações do painel (`?key=` ou `Authorization: Bearer`); sem ela, as ações ficam desativadas.
+    dashboard_key: Option<String>,
+    /// Assinaturas dos links já utilizados.
+    pub used_links: Mutex<HashSet<String>>,
+    wait: Option<WaitOptions>,
+    next_run: Mutex<Option<DateTime<Utc>>>,
+    /// Suspende as execuções automáticas (agendamentos e eventos) sem parar o processo.
+    pub paused: AtomicBool,
+}
+
+impl ServerState {
+
+    pub fn loaded(&self) -> Arc<Loaded> {
+        self.loaded.read().unwrap().clone()
+    }
+
+    pub fn session(&self) -> Arc<Session> {
+        self.loaded().session.clone()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub async fn next_run(&self) -> Option<DateTime<Utc>> {
+        *self.next_run.lock().await
+    }
+
+    /// Executa as atualizações em segundo plano, acompanhando-as quando `--wait` estiver ativo.
+    pub fn spawn_run(&self, targets: Vec<(u32, DatasetConfig)>) {
+
+        let session = self.session();
+        let wait = self.wait;
+
+        tokio::spawn(async move {
+            if let Err(e) = session.run(targets, wait).await {
+                eprintln!("Falha ao executar atualizações.\n{}", e);
+            }
+        });
+    }
+
+    /// Executa um job do `dataset.json` em segundo plano. Jobs com etapas são sempre acompanhados.
+    pub fn spawn_job(&self, name: &str) -> Result<(), String> {
+
+        let loaded = self.loaded();
+        let Some(job) = loaded.config.jobs.get(name) else {
+            return Err(format!("Job \"{}\" não encontrado.", name));
+        };
+
+        if job.stages.is_empty() {
+            self.spawn_run(loaded.session.job_targets(job));
+            return Ok(());
+        }
+
+        let name = name.to_string();
+        let wait = self.wait.or(job.wait.as_ref().map(|wait| wait.options())).unwrap_or_default();
+
+        tokio::spawn(async move {
+            let job = &loaded.config.jobs[&name];
+            if let Err(e) = job::run_stages(&loaded.session, job, None, wait).await {
+                eprintln!("Falha ao executar o job {}.\n{}", name, e);
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Relê o `dataset.json` e passa a usá-lo nas próximas execuções, mantendo credenciais, token e os assinantes
+    /// dos eventos. Atualizações em andamento terminam com as configurações anteriores.
+    pub async fn reload(&self) -> Result<Arc<Loaded>, String> {
+
+        let config = load_config_file()?;
+        let session = self.session().reloaded(&config).await?;
+        set_workspaces(dataset_workspaces(&config));
+        let loaded = Arc::new(Loaded::new(Arc::new(session), config));
+
+        *self.loaded.write().unwrap() = loaded.clone();
+        Ok(loaded)
+    }
+}
+
+/// Inicia o servidor HTTP com o painel web e, opcionalmente, o agendamento periódico e a API gRPC.
+pub async fn serve(options: ServeOptions, session: Arc<Session>, config: AppConfig) {
+
+    let state = Arc::new(ServerState {
+        loaded: RwLock::new(Arc::new(Loaded::new(session, config))),
+        events_key: options.events_key,
+        links_key: links::links_key(),
+        dashboard_key: std::env::var(ENV_DASHBOARD_KEY).ok().filter(|key| !key.is_empty()),
+        used_links: Mutex::new(HashSet::new()),
+        wait: options.wait,
+        next_run: Mutex::new(None),
+        paused: AtomicBool::new(false),
+    });
+
+    if let Some(minutes) = options.interval_minutes {
+        tokio::spawn(scheduler(state.clone(), minutes));
+    } else if !options.daily_at.is_empty() {
+        tokio::spawn(daily_scheduler(state.clone(), options.daily_at));
+    }
+
+    if let Some(grpc_bind) = options.grpc_bind {
+        tokio::spawn(grpc::serve(grpc_bind, state.clone()));
+    }
+
+    if let Some(minutes) = options.discover_minutes {
+        tokio::spawn(discovery(state.clone(), minutes));
+    }
+
+    if let Some(address) = options.control {
+        tokio::spawn(control::listen(state.clone(), address));
+    }
+
+    let app = Router::new()
+        .route("/", get(dashboard))
+        .route("/refresh", post(refresh_all))
+        .route("/refresh/:company/:dataset", post(refresh_one))
+        .route("/link/:company/:dataset", get(links::handle_link))
+        .route("/events/eventgrid", post(eventgrid::handle_events))
+        .route("/history", get(history_records).post(history_append))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(state);
+
+    println!("Painel disponível em http://{}", options.bind);
+
+    if let Err(e) = axum::Server::bind(&options.bind).serve(app.into_make_service()).await {
+        eprintln!("Falha ao iniciar servidor.\nErro: {}", e);
+    }
+}
+
+async fn scheduler(state: Arc<ServerState>, minutes: u64) {
+
+    let interval = Duration::from_secs(minutes * 60);
+
+    loop {
+        *state.next_run.lock().await = Some(Utc::now() + chrono::Duration::from_std(interval).unwrap());
+        tokio::time::sleep(interval).await;
+
+        if state.is_paused() {
+            println!("Atualização agendada ignorada: servidor em pausa.");
+            continue;
+        }
+
+        let session = state.session();
+        if let Err(e) = session.run(session.all_targets(), state.wait).await {
+            eprintln!("Falha na atualização agendada.\n{}", e);
+        }
+    }
+}
+
+async fn daily_scheduler(state: Arc<ServerState>, times: Vec<NaiveTime>) {
+
+    loop {
+        // Um segundo adiante evita repetir o horário que acabou de ser executado.
+        let now = Utc::now() + chrono::Duration::seconds(1);
+        let next_run = times.iter().map(|time| locale::next_occurrence(*time, now, None)).min().unwrap();
+
+        *state.next_run.lock().await = Some(next_run);
+        tokio::time::sleep((next_run - Utc::now()).to_std().unwrap_or_default()).await;
+
+        if state.is_paused() {
+            println!("Atualização agendada ignorada: servidor em pausa.");
+            continue;
+        }
+
+        let session = state.session();
+        if let Err(e) = session.run(session.all_targets(), state.wait).await {
+            eprintln!("Falha na atualização agendada.\n{}", e);
+        }
+    }
+}
+
+/// Procura periodicamente datasets atualizáveis que não estão no `dataset.json` e avisa os webhooks gerais
+/// (`notify`) uma vez por dataset, para que nenhum fique sem atualização por esquecimento.
+async fn discovery(state: Arc<ServerState>, minutes: u64) {
+
+    let interval = Duration::from_secs(minutes * 60);
+    let mut reported: HashSet<String> = HashSet::new();
+
+    if state.loaded().config.notify.is_empty() {
+        println!("Nenhum webhook em `notify`; datasets não configurados serão apenas exibidos no console.");
+    }
+
+    loop {
+        let loaded = state.loaded();
+        let config = &loaded.config;
+
+        let found = match loaded.session.current_token().await {
+            Ok(token) => maintenance::unmanaged_datasets(config, &token).await,
+            Err(e) => Err(e),
+        };
+
+        match found {
+            Ok(found) => {
+                for (group, dataset) in found {
+                    if !reported.insert(dataset.id.clone()) {
+                        continue;
+                    }
+
+                    let text = format!(
+                        "Dataset {} ({}) no workspace {} é atualizável e não está configurado em nenhuma empresa.",
+                        dataset.name, dataset.id, group.name
+                    );
+                    println!("{}", text);
+
+                    for url in &config.notify {
+                        send_message(url, &dataset.id, &text).await;
+                    }
+                }
+            }
+            Err(e) => eprintln!("Falha ao procurar datasets não configurados.\n{}", e),
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Confere a chave das ações do painel, recebida no `?key=` (formulários) ou no cabeçalho `Authorization`.
+fn authorize_dashboard(state: &ServerState, headers: &HeaderMap, query: &HashMap<String, String>) -> Result<(), (StatusCode, String)> {
+
+    ensure_writable("A atualização").map_err(|e| (StatusCode::FORBIDDEN, e))?;
+
+    let Some(key) = &state.dashboard_key else {
+        return Err((StatusCode::NOT_FOUND, format!("Ações do painel desativadas; defina {} no servidor.", ENV_DASHBOARD_KEY)));
+    };
+
+    let bearer = headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("Bearer "));
+    if query.get("key") != Some(key) && bearer != Some(key.as_str()) {
+        return Err((StatusCode::UNAUTHORIZED, "Chave do painel ausente ou inválida.".to_string()));
+    }
+
+    Ok(())
+}
+
+async fn dashboard(State(state): State<Arc<ServerState>>, headers: HeaderMap, Query(query): Query<HashMap<String, String>>) -> Html<String> {
+
+    // Os botões só aparecem com a chave, que segue nos formulários para autorizar as ações.
+    let actions = match authorize_dashboard(&state, &headers, &query) {
+        Ok(()) => Ok(query.get("key").map(|key| format!("?key={}", urlencoding::encode(key))).unwrap_or_default()),
+        Err((_, message)) => Err(message),
+    };
+
+    let outcomes = last_outcomes();
+    let session = state.session();
+    let entries = &session.entries;
+
+    let mut companies: Vec<&u32> = entries.keys().collect();
+    companies.sort();
+
+    let mut rows = String::new();
+
+    for company in companies {
+        for dataset in &entries[company] {
+
+            let (outcome, timestamp) = match outcomes.get(&dataset.id) {
+                Some(record) => {
+                    let outcome = record.outcome();
+                    let class = if outcome.is_skipped() { "none" } else if outcome.is_failure(false) { "fail" } else { "ok" };
+                    let outcome = match (outcome, &record.result) {
+                        (Outcome::Denied, _) => format!("<span class=\"fail\">{} ({})</span>", outcome.label(), record.status),
+                        (Outcome::Failed, Some(result)) => format!("<span class=\"fail\">{} ({})</span>", outcome.label(), escape_html(result)),
+                        _ => format!("<span class=\"{}\">{}</span>", class, outcome.label()),
+                    };
+                    (outcome, locale::datetime(record.timestamp))
+                }
+                None => ("<span class=\"none\">-</span>".to_string(), "-".to_string()),
+            };
+
+            // Sem a chave ou no modo somente leitura o painel apenas exibe o status.
+            let action = match &actions {
+                Ok(key) => format!(
+                    "<form method=\"post\" action=\"/refresh/{}/{}{}\"><button type=\"submit\">Atualizar agora</button></form>",
+                    company,
+                    escape_html(&dataset.id),
+                    escape_html(key)
+                ),
+                Err(_) => String::new(),
+            };
+
+            rows.push_str(&format!(
+                "            <tr><td>{company}</td><td>{dataset}</td><td>{outcome}</td><td>{timestamp}</td><td>{action}</td></tr>\n",
+                company = company,
+                dataset = escape_html(&dataset.id),
+                outcome = outcome,
+                timestamp = timestamp,
+                action = action,
+            ));
+        }
+    }
+
+    let next_run = match state.next_run().await {
+        Some(next_run) => locale::datetime(next_run),
+        None => "sem agendamento".to_string(),
+    };
+
+    let refresh_all = match &actions {
+        Ok(key) => format!("<form method=\"post\" action=\"/refresh{}\"><button type=\"submit\">Atualizar todas empresas</button></form>", escape_html(key)),
+        Err(message) => format!("<p>{}</p>", escape_html(message)),
+    };
+
+    Html(DASHBOARD_HTML.replace("{{next_run}}", &next_run).replace("{{refresh_all}}", &refresh_all).replace("{{rows}}", &rows))
+}
+
+/// Volta ao painel mantendo a chave, para que os botões continuem disponíveis.
+fn back_to_dashboard(query: &HashMap<String, String>) -> Redirect {
+    match query.get("key") {
+        Some(key) => Redirect::to(&format!("/?key={}", urlencoding::encode(key))),
+        None => Redirect::to("/"),
+    }
+}
+
+async fn refresh_all(State(state): State<Arc<ServerState>>, headers: HeaderMap, Query(query): Query<HashMap<String, String>>) -> impl IntoResponse {
+
+    if let Err(e) = authorize_dashboard(&state, &headers, &query) {
+        return e.into_response();
+    }
+
+    state.spawn_run(state.session().all_targets());
+    back_to_dashboard(&query).into_response()
+}
+
+async fn refresh_one(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    Path((company, dataset)): Path<(u32, String)>,
+    Query(query): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+
+    if let Err(e) = authorize_dashboard(&state, &headers, &query) {
+        return e.into_response();
+    }
+
+    // Somente datasets presentes no arquivo de configurações podem ser atualizados.
+    match state.session().dataset_target(company, &dataset) {
+        Ok(targets) => {
+            state.spawn_run(targets);
+            back_to_dashboard(&query).into_response()
+        }
+        Err(e) => (StatusCode::NOT_FOUND, e).into_response(),
+    }
+}
+
+/// Histórico central dos agentes com `"history": { "backend": "http" }`. Desativado sem `PBI_HISTORY_KEY`.
+fn authorize_history(headers: &HeaderMap) -> Result<(), StatusCode> {
+
+    let Some(key) = history_key() else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let authorization = headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok());
+    if authorization != Some(format!("Bearer {}", key).as_str()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    Ok(())
+}
+
+/// Com `?dataset=`, devolve apenas os registros do dataset.
+async fn history_records(headers: HeaderMap, Query(query): Query<HashMap<String, String>>) -> impl IntoResponse {
+    match authorize_history(&headers) {
+        Ok(()) => match query.get("dataset") {
+            Some(dataset) => Json(read_dataset_records(dataset)).into_response(),
+            None => Json(read_records()).into_response(),
+        },
+        Err(status) => status.into_response(),
+    }
+}
+
+async fn history_append(headers: HeaderMap, Json(record): Json<RefreshRecord>) -> impl IntoResponse {
+    match authorize_history(&headers) {
+        Ok(()) => {
+            append_record(&record);
+            StatusCode::CREATED.into_response()
+        }
+        Err(status) => status.into_response(),
+    }
+}
+
+/// Liveness: o processo está respondendo.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Readiness: configurações carregadas e token válido (renovado se expirou).
+async fn readyz(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+
+    let session = state.session();
+
+    if session.entries.is_empty() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Nenhuma empresa configurada.".to_string());
+    }
+
+    if let Err(e) = session.current_token().await {
+        return (StatusCode::SERVICE_UNAVAILABLE, format!("Token inválido.\n{}", e));
+    }
+
+    (StatusCode::OK, "ok".to_string())
+}
+
+pub fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,522 @@
+use std::{collections::{HashMap, VecDeque}, future::Future, sync::Arc};
+use futures_util::{stream, StreamExt};
+use tokio::{sync::{broadcast, Mutex, Semaphore}, task::{JoinHandle, JoinSet}};
+
+use crate::config::{check_workspaces, ensure_writable, guid_entries_map, Annotations, AppConfig, DatasetConfig, JobConfig, JobStage, Secrets};
+use crate::history::{append_record, RefreshRecord};
+use crate::notify::{send_annotation, send_callback, send_failure_alert, send_message};
+use crate::powerbi::update_parameters;
+use crate::monitor::{print_result, wait_for_refresh, PollScheduler, WaitOptions};
+use crate::refresh::{max_parallel, refresh_dataset, validate, warm_up};
+use crate::token::{default_resource, renew_saved_token, renew_token, validate_token, TokenResponse};
+
+/// Intercala os datasets das empresas (o primeiro de cada empresa, depois o segundo...), mantendo a ordem dentro de
+/// cada uma. Com vagas limitadas, uma empresa com muitos datasets não ocupa todas enquanto as outras aguardam.
+pub fn round_robin<T>(targets: Vec<(u32, T)>) -> Vec<(u32, T)> {
+
+    let mut queues: Vec<(u32, VecDeque<T>)> = Vec::new();
+
+    for (company, dataset) in targets {
+        match queues.iter_mut().find(|(id, _)| *id == company) {
+            Some((_, queue)) => queue.push_back(dataset),
+            None => queues.push((company, VecDeque::from([dataset]))),
+        }
+    }
+
+    let mut interleaved = Vec::new();
+
+    while !queues.is_empty() {
+        for (company, queue) in queues.iter_mut() {
+            if let Some(dataset) = queue.pop_front() {
+                interleaved.push((*company, dataset));
+            }
+        }
+        queues.retain(|(_, queue)| !queue.is_empty());
+    }
+
+    interleaved
+}
+
+/// Estado compartilhado pelos modos não interativos: segredos, datasets e token renovado sob demanda.
+pub struct Session {
+    secrets: Secrets,
+    pub entries: HashMap<u32, Vec<DatasetConfig>>,
+    /// Empresas marcadas como favoritas.
+    favorites: Vec<u32>,
+    /// Vagas de atualização simultânea por capacidade.
+    capacities: HashMap<String, Arc<Semaphore>>,
+    /// Tokens por audiência (PowerBI, Fabric, XMLA...), gerados sob demanda com as mesmas credenciais.
+    tokens: Mutex<HashMap<String, TokenResponse>>,
+    /// Publica o resultado de cada atualização para os assinantes (ex.: stream gRPC).
+    pub events: broadcast::Sender<RefreshRecord>,
+    /// Agenda compartilhada das consultas de status das atualizações acompanhadas.
+    pub poller: PollScheduler,
+    /// Destino das anotações com a janela e o resultado de cada atualização.
+    annotations: Option<Annotations>,
+    /// Avisos (callbacks, anotações, alertas) ainda em envio, aguardados antes de o programa encerrar. Descartar a
+    /// sessão (ex.: no `reload`) não interrompe os envios.
+    pending: std::sync::Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Session {
+
+    pub fn new(secrets: Secrets, config: &AppConfig, token: TokenResponse) -> Self {
+
+        let (events, _) = broadcast::channel(256);
+
+        let mut entries = guid_entries_map(&config.companies);
+
+        // Cada sessão enxerga apenas as empresas do seu perfil de credenciais.
+        for company in &config.companies {
+            if company.profile != secrets.profile {
+                entries.remove(&company.id);
+            }
+        }
+
+        // Datasets sem capacidade, callback, fuso ou destinatários próprios herdam os da empresa; sem intervalo mínimo,
+        // o geral.
+        for company in &config.companies {
+            if let Some(datasets) = entries.get_mut(&company.id) {
+                for dataset in datasets.iter_mut() {
+                    if dataset.capacity.is_none() {
+                        dataset.capacity = company.capacity.clone();
+                    }
+                    if dataset.callback.is_none() {
+                        dataset.callback = company.callback.clone();
+                    }
+                    if dataset.timezone.is_none() {
+                        dataset.timezone = company.timezone;
+                    }
+                    if dataset.min_interval_minutes.is_none() {
+                        dataset.min_interval_minutes = config.min_interval_minutes;
+                    }
+                    if dataset.notify.is_empty() {
+                        dataset.notify = if company.notify.is_empty() { config.notify.clone() } else { company.notify.clone() };
+                    }
+                }
+            }
+        }
+
+        let capacities = config.capacities
+            .iter()
+            .map(|(name, limit)| (name.clone(), Arc::new(Semaphore::new((*limit).max(1)))))
+            .collect();
+
+        let mut favorites: Vec<u32> = config.companies.iter().filter(|company| company.favorite).map(|company| company.id).collect();
+        favorites.sort();
+
+        let tokens = HashMap::from([(default_resource(&secrets).to_string(), token)]);
+
+        Session {
+            secrets,
+            entries,
+            favorites,
+            capacities,
+            tokens: Mutex::new(tokens),
+            events,
+            poller: PollScheduler::default(),
+            annotations: config.annotations.clone(),
+            pending: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Nova sessão com as configurações recarregadas, mantendo as credenciais, o token e os assinantes dos eventos.
+    pub async fn reloaded(&self, config: &AppConfig) -> Result<Session, String> {
+
+        check_workspaces(config, &self.secrets)?;
+
+        let token = self.current_token().await?;
+        let mut session = Session::new(self.secrets.clone(), config, token);
+        session.events = self.events.clone();
+
+        Ok(session)
+    }
+
+    /// Devolve o token atual da API do PowerBI, gerando um novo caso tenha expirado.
+    pub async fn current_token(&self) -> Result<TokenResponse, String> {
+        self.token_for(default_resource(&self.secrets)).await
+    }
+
+    /// Devolve o token da audiência informada, gerando um novo caso não exista ou tenha expirado.
+    /// Apenas o token do PowerBI é gravado, separado por perfil.
+    pub async fn token_for(&self, resource: &str) -> Result<TokenResponse, String> {
+
+        let mut tokens = self.tokens.lock().await;
+
+        if let Some(token) = tokens.get(resource).filter(|token| validate_token(token)) {
+            return Ok(token.clone());
+        }
+
+        let token = if resource == default_resource(&self.secrets) {
+            renew_saved_token(&self.secrets, tokens.get(resource)).await?
+        } else {
+            renew_token(&self.secrets, resource, tokens.get(resource)).await?
+        };
+
+        tokens.insert(resource.to_string(), token.clone());
+        Ok(token)
+    }
+
+    /// Gera um novo token da API do PowerBI após a API recusar `rejected`. Se outra tarefa já o substituiu,
+    /// devolve o atual em vez de renovar de novo.
+    pub async fn force_renew(&self, rejected: &TokenResponse) -> Result<TokenResponse, String> {
+
+        let resource = default_resource(&self.secrets);
+        let mut tokens = self.tokens.lock().await;
+
+        if let Some(token) = tokens.get(resource).filter(|token| token.access_token != rejected.access_token) {
+            return Ok(token.clone());
+        }
+
+        let token = renew_saved_token(&self.secrets, Some(rejected)).await?;
+
+        tokens.insert(resource.to_string(), token.clone());
+        Ok(token)
+    }
+
+    /// Configuração do dataset, caso esteja presente no arquivo de configurações.
+    pub fn dataset_config(&self, dataset: &str) -> Option<&DatasetConfig> {
+        self.entries.values().flatten().find(|config| config.id == dataset)
+    }
+
+    /// Indica se o dataset está presente no arquivo de configurações da empresa.
+    pub fn is_configured(&self, company: u32, dataset: &str) -> bool {
+        self.entries.get(&company).map(|datasets| datasets.iter().any(|config| config.id == dataset)).unwrap_or(false)
+    }
+
+    /// Empresa em que o dataset está configurado.
+    pub fn find_company(&self, dataset: &str) -> Option<u32> {
+        self.entries.iter().find(|(_, datasets)| datasets.iter().any(|config| config.id == dataset)).map(|(company, _)| *company)
+    }
+
+    /// Publica um registro para os assinantes; não há problema caso ninguém esteja inscrito.
+    pub fn publish(&self, record: &RefreshRecord) {
+        let _ = self.events.send(record.clone());
+    }
+
+    /// Envia a mensagem aos webhooks de notificação do dataset, sem aguardar.
+    pub fn notify(&self, dataset: &str, message: &str) {
+        for url in self.dataset_config(dataset).map(|config| config.notify.clone()).unwrap_or_default() {
+            let (dataset, message) = (dataset.to_string(), message.to_string());
+            self.send_later(async move { send_message(&url, &dataset, &message).await });
+        }
+    }
+
+    /// Envia um aviso em segundo plano, descartando os já entregues para que o modo servidor não os acumule.
+    fn send_later(&self, notice: impl Future<Output = ()> + Send + 'static) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|handle| !handle.is_finished());
+        pending.push(tokio::spawn(notice));
+    }
+
+    /// Aguarda os avisos ainda em envio; sem isso, os últimos se perdem quando o programa encerra logo após as
+    /// atualizações.
+    pub async fn flush(&self) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        for handle in pending {
+            let _ = handle.await;
+        }
+    }
+
+    /// Todos os datasets configurados, ordenados por empresa.
+    pub fn all_targets(&self) -> Vec<(u32, DatasetConfig)> {
+
+        let mut companies: Vec<&u32> = self.entries.keys().collect();
+        companies.sort();
+
+        companies
+            .into_iter()
+            .flat_map(|company| self.entries[company].iter().map(move |dataset| (*company, dataset.clone())))
+            .collect()
+    }
+
+    /// Datasets das empresas favoritas.
+    pub fn favorite_targets(&self) -> Vec<(u32, DatasetConfig)> {
+        self.favorites
+            .iter()
+            .filter_map(|company| self.company_targets(*company).ok())
+            .flatten()
+            .collect()
+    }
+
+    /// Datasets de todas as empresas com o apelido informado.
+    pub fn alias_targets(&self, alias: &str) -> Vec<(u32, DatasetConfig)> {
+        self.all_targets().into_iter().filter(|(_, dataset)| dataset.alias.as_deref() == Some(alias)).collect()
+    }
+
+    /// Datasets de todas as empresas com a etiqueta informada.
+    pub fn tag_targets(&self, tag: &str) -> Vec<(u32, DatasetConfig)> {
+        self.all_targets().into_iter().filter(|(_, dataset)| dataset.tags.iter().any(|known| known == tag)).collect()
+    }
+
+    /// Apelidos configurados, em ordem alfabética.
+    pub fn aliases(&self) -> Vec<&str> {
+        let mut aliases: Vec<&str> = self.entries.values().flatten().filter_map(|dataset| dataset.alias.as_deref()).collect();
+        aliases.sort();
+        aliases.dedup();
+        aliases
+    }
+
+    /// Datasets das empresas do job (todas, quando não informadas) ou de todas as suas etapas,
+    /// com o callback do job nos datasets sem callback próprio.
+    pub fn job_targets(&self, job: &JobConfig) -> Vec<(u32, DatasetConfig)> {
+
+        let mut targets = if !job.stages.is_empty() {
+            job.stages.iter().flat_map(|stage| self.stage_targets(job, stage)).collect()
+        } else if job.companies.is_empty() {
+            self.all_targets()
+        } else {
+            let mut targets = Vec::new();
+            for company in &job.companies {
+                match self.company_targets(*company) {
+                    Ok(company_targets) => targets.extend(company_targets),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            targets
+        };
+
+        for (_, dataset) in targets.iter_mut() {
+            if dataset.callback.is_none() {
+                dataset.callback = job.callback.clone();
+            }
+        }
+
+        targets
+    }
+
+    /// Datasets de uma etapa do job: os das empresas e os informados por GUID.
+    pub fn stage_targets(&self, job: &JobConfig, stage: &JobStage) -> Vec<(u32, DatasetConfig)> {
+
+        let mut targets = Vec::new();
+
+        for company in &stage.companies {
+            match self.company_targets(*company) {
+                Ok(company_targets) => targets.extend(company_targets),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+        for dataset in &stage.datasets {
+            match (self.find_company(dataset), self.dataset_config(dataset)) {
+                (Some(company), Some(config)) => targets.push((company, config.clone())),
+                _ => eprintln!("Dataset {} da etapa \"{}\" não encontrado.", dataset, stage.name),
+            }
+        }
+
+        for (_, dataset) in targets.iter_mut() {
+            if dataset.callback.is_none() {
+                dataset.callback = job.callback.clone();
+            }
+        }
+
+        targets
+    }
+
+    pub fn company_targets(&self, company: u32) -> Result<Vec<(u32, DatasetConfig)>, String> {
+        match self.entries.get(&company) {
+            Some(datasets) => Ok(datasets.iter().map(|dataset| (company, dataset.clone())).collect()),
+            None => Err(format!("Empresa {} não encontrada.", company)),
+        }
+    }
+
+    pub fn dataset_target(&self, company: u32, dataset: &str) -> Result<Vec<(u32, DatasetConfig)>, String> {
+        match self.entries.get(&company).and_then(|datasets| datasets.iter().find(|config| config.id == dataset)) {
+            Some(config) => Ok(vec![(company, config.clone())]),
+            None => Err(format!("Dataset {} não encontrado.", dataset)),
+        }
+    }
+
+    /// Envia a requisição de um dataset, exibe e publica o resultado.
+    async fn submit(&self, company: u32, dataset: &DatasetConfig) -> Result<RefreshRecord, String> {
+
+        ensure_writable("A atualização")?;
+
+        let token = self.current_token().await?;
+        let record = refresh_dataset(company, dataset, dataset.note.as_deref(), &token, || self.force_renew(&token)).await;
+
+        println!("Empresa {} - {}: {}", company, dataset.id, record.describe());
+        if let Some(request_id) = record.request_id.as_ref().filter(|_| record.outcome().is_failure(false)) {
+            eprintln!("\tRequestId: {}", request_id);
+        }
+
+        self.publish(&record);
+        Ok(record)
+    }
+
+    /// Envia o resultado final ao callback do dataset e às anotações e, em caso de falha, avisa os destinatários do
+    /// dataset, sem atrasar as demais atualizações.
+    fn finish(&self, dataset: &DatasetConfig, record: RefreshRecord) -> RefreshRecord {
+
+        if let Some(url) = dataset.callback.clone() {
+            let record = record.clone();
+            self.send_later(async move { send_callback(&url, &record).await });
+        }
+
+        // Datasets não enviados não têm janela de atualização a marcar.
+        if let Some(annotations) = self.annotations.clone().filter(|_| record.skipped.is_none()) {
+            let record = record.clone();
+            self.send_later(async move { send_annotation(&annotations, &record).await });
+        }
+
+        if record.outcome().is_failure(false) {
+            for url in dataset.notify.clone() {
+                let record = record.clone();
+                self.send_later(async move { send_failure_alert(&url, &record).await });
+            }
+        }
+
+        record
+    }
+
+    /// Acompanha uma atualização aceita até o fim; quando concluída, aquece o cache e valida os dados.
+    /// O resultado final é gravado no histórico com os tempos e valores obtidos.
+    async fn follow(&self, dataset: &DatasetConfig, record: RefreshRecord, options: WaitOptions) -> RefreshRecord {
+
+        let mut record = wait_for_refresh(self, &record, options).await;
+
+        if record.result.as_deref() == Some("Completed") && (!dataset.warmup.is_empty() || dataset.validation.is_some()) {
+            match self.current_token().await {
+                Ok(token) => {
+                    if !dataset.warmup.is_empty() {
+                        record = warm_up(dataset, record, &token).await;
+                    }
+                    if let Some(validation) = &dataset.validation {
+                        record = validate(dataset, validation, record, &token).await;
+                    }
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+        // Esgotado o tempo de espera não há resultado final a registrar.
+        if record.completed_at.is_some() {
+            append_record(&record);
+            self.publish(&record);
+        }
+
+        print_result(&record);
+        self.finish(dataset, record)
+    }
+
+    /// Atualiza o dataset uma vez para cada conjunto de parâmetros da matriz, aguardando cada execução.
+    /// A sequência é interrompida na primeira atualização que não for concluída.
+    async fn run_matrix(&self, company: u32, dataset: &DatasetConfig, options: WaitOptions) -> Result<Vec<RefreshRecord>, String> {
+
+        let mut records = Vec::new();
+
+        for parameters in &dataset.matrix {
+
+            let description: Vec<String> = parameters.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            println!("Empresa {} - {}: parâmetros {}", company, dataset.id, description.join(", "));
+
+            let token = self.current_token().await?;
+            if let Err(e) = update_parameters(&dataset.id, parameters, &token).await {
+                eprintln!("Falha ao alterar parâmetros do dataset {}.\n{}", dataset.id, e);
+                break;
+            }
+
+            let record = self.submit(company, dataset).await?;
+            let record = if record.accepted { self.follow(dataset, record, options).await } else { self.finish(dataset, record) };
+            let completed = record.result.as_deref() == Some("Completed");
+
+            records.push(record);
+
+            if !completed {
+                eprintln!("Matriz de parâmetros do dataset {} interrompida.", dataset.id);
+                break;
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Executa as atualizações. Datasets de capacidades com limite aguardam uma vaga e a ocupam
+    /// até o término; os demais são enviados até `max_parallel` por vez. Com `wait`, todas são acompanhadas até o fim.
+    pub async fn run(self: &Arc<Self>, targets: Vec<(u32, DatasetConfig)>, wait: Option<WaitOptions>) -> Result<Vec<RefreshRecord>, String> {
+
+        // Falha cedo quando não há como obter um token.
+        self.current_token().await?;
+
+        let mut records = Vec::new();
+        let mut tasks: JoinSet<Result<Vec<RefreshRecord>, String>> = JoinSet::new();
+        let mut direct = Vec::new();
+
+        // As vagas das capacidades são concedidas na ordem de chegada, então a ordem de envio define a vez de cada empresa.
+        for (company, dataset) in round_robin(targets) {
+
+            let semaphore = dataset.capacity.as_ref().and_then(|capacity| self.capacities.get(capacity)).cloned();
+            let session = self.clone();
+
+            // Cada combinação de parâmetros precisa terminar antes da próxima; a sequência segue em segundo plano.
+            if !dataset.matrix.is_empty() && !dataset.disabled {
+                tasks.spawn(async move {
+                    let _permit = match semaphore {
+                        Some(semaphore) => Some(semaphore.acquire_owned().await.unwrap()),
+                        None => None,
+                    };
+                    session.run_matrix(company, &dataset, wait.unwrap_or_default()).await
+                });
+                continue;
+            }
+
+            match semaphore {
+                Some(semaphore) => {
+                    tasks.spawn(async move {
+                        let _permit = semaphore.acquire_owned().await.unwrap();
+                        let record = session.submit(company, &dataset).await?;
+
+                        if !record.accepted {
+                            return Ok(vec![session.finish(&dataset, record)]);
+                        }
+
+                        // A vaga só é liberada quando a atualização termina.
+                        Ok(vec![session.follow(&dataset, record, wait.unwrap_or_default()).await])
+                    });
+                }
+                None => direct.push((company, dataset)),
+            }
+        }
+
+        // Os envios sem capacidade seguem a ordem do rodízio, com até `max_parallel` aguardando resposta.
+        let mut submissions = stream::iter(direct)
+            .map(|(company, dataset)| async move {
+                let record = self.submit(company, &dataset).await;
+                (dataset, record)
+            })
+            .buffered(max_parallel());
+
+        while let Some((dataset, record)) = submissions.next().await {
+            let record = record?;
+
+            match wait {
+                Some(options) if record.accepted => {
+                    let session = self.clone();
+                    tasks.spawn(async move { Ok(vec![session.follow(&dataset, record, options).await]) });
+                }
+                _ => records.push(self.finish(&dataset, record)),
+            }
+        }
+
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Ok(Ok(mut task_records)) => records.append(&mut task_records),
+                Ok(Err(e)) => eprintln!("{}", e),
+                Err(e) => eprintln!("Falha na tarefa de atualização.\n{}", e),
+            }
+        }
+
+        Ok(records)
+    }
+
+    pub async fn refresh_one(self: &Arc<Self>, company: u32, dataset: &str) -> Result<RefreshRecord, String> {
+        let targets = self.dataset_target(company, dataset)?;
+        let mut records = self.run(targets, None).await?;
+        records.pop().ok_or_else(|| format!("Falha ao atualizar dataset {}.", dataset))
+    }
+
+    pub async fn refresh_company(self: &Arc<Self>, company: u32) -> Result<Vec<RefreshRecord>, String> {
+        let targets = self.company_targets(company)?;
+        self.run(targets, None).await
+    }
+}
@@ -0,0 +1,266 @@
+use std::{fs, path::Path, collections::BTreeMap};
+use colored::Colorize;
+use dialoguer::{Confirm, Input, MultiSelect, Password, Select, theme::ColorfulTheme};
+
+use crate::backup::backup_config_file;
+use crate::config::{read_secrets_file, DatasetConfig, GuidEntry, FILENAME_CONFIG_JSON, FILENAME_SECRETS_TOML};
+use crate::powerbi::{list_datasets, list_groups, set_api_base_url};
+use crate::token::{acquire_new_token, export_token};
+
+
+/// Assistente de primeira execução: cria `secrets.toml` e `dataset.json`.
+pub async fn run_wizard() -> Result<(), String> {
+
+    println!("{}", "Assistente de configuração".green());
+
+    if should_write(FILENAME_SECRETS_TOML)? {
+        write_secrets()?;
+    }
+
+    if should_write(FILENAME_CONFIG_JSON)? {
+        write_datasets().await?;
+    }
+
+    println!("{}", "Configuração concluída.".green());
+    Ok(())
+}
+
+/// Pergunta antes de sobrescrever um arquivo existente.
+fn should_write(filename: &str) -> Result<bool, String> {
+
+    if !Path::new(filename).exists() {
+        return Ok(true);
+    }
+
+    Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("O arquivo {} já existe. Deseja sobrescrever?", filename))
+        .default(false)
+        .interact()
+        .map_err(|e| e.to_string())
+}
+
+fn write_secrets() -> Result<(), String> {
+
+    let theme = ColorfulTheme::default();
+
+    let flows = vec![
+        "Usuário e senha (password)",
+        "Service principal (client_credentials)",
+        "Código de dispositivo (device_code)",
+        "Service principal com certificado (certificate)",
+        "Login da Azure CLI (azure_cli)",
+        "Identidade gerenciada do Azure (managed_identity)",
+    ];
+    let flow = Select::with_theme(&theme)
+        .with_prompt("Forma de autenticação")
+        .default(0)
+        .items(&flows)
+        .interact()
+        .map_err(|e| e.to_string())?;
+
+    // Valores aceitos em `cloud`, na ordem das opções.
+    let clouds = [("public", "Pública"), ("usgov", "Governo dos EUA (GCC)"), ("usgovhigh", "Governo dos EUA (GCC High)"), ("usgovdod", "Governo dos EUA (DoD)"), ("china", "China (21Vianet)")];
+    let cloud = Select::with_theme(&theme)
+        .with_prompt("Nuvem do Azure")
+        .default(0)
+        .items(&clouds.iter().map(|(_, label)| *label).collect::<Vec<_>>())
+        .interact()
+        .map_err(|e| e.to_string())?;
+
+    let mut secrets: Vec<(&str, String)> = Vec::new();
+
+    if cloud > 0 {
+        secrets.push(("cloud", clouds[cloud].0.to_string()));
+    }
+
+    // A Azure CLI usa o próprio aplicativo registrado e a identidade gerenciada dispensa o client_id.
+    if flow < 4 {
+        let client_id: String = Input::with_theme(&theme)
+            .with_prompt("client_id")
+            .interact_text()
+            .map_err(|e| e.to_string())?;
+        secrets.push(("client_id", client_id));
+    }
+
+    if flow == 4 {
+        secrets.push(("grant_type", "azure_cli".to_string()));
+    } else if flow == 5 {
+        let client_id: String = Input::with_theme(&theme)
+            .with_prompt("client_id da identidade atribuída pelo usuário (ENTER para a identidade do sistema)")
+            .allow_empty(true)
+            .interact_text()
+            .map_err(|e| e.to_string())?;
+
+        secrets.push(("grant_type", "managed_identity".to_string()));
+        if !client_id.trim().is_empty() {
+            secrets.push(("client_id", client_id.trim().to_string()));
+        }
+    } else if flow == 2 {
+        secrets.push(("grant_type", "device_code".to_string()));
+    } else if flow == 3 {
+        let tenant: String = Input::with_theme(&theme)
+            .with_prompt("Tenant (ID ou domínio)")
+            .interact_text()
+            .map_err(|e| e.to_string())?;
+        let certificate: String = Input::with_theme(&theme)
+            .with_prompt("Arquivo PEM com certificado e chave privada")
+            .interact_text()
+            .map_err(|e| e.to_string())?;
+
+        secrets.push(("grant_type", "certificate".to_string()));
+        secrets.push(("tenant", tenant));
+        secrets.push(("certificate", certificate));
+    } else if flow == 0 {
+        let username: String = Input::with_theme(&theme)
+            .with_prompt("Usuário")
+            .interact_text()
+            .map_err(|e| e.to_string())?;
+        let password = Password::with_theme(&theme)
+            .with_prompt("Senha (ENTER para não gravar e digitá-la a cada login)")
+            .allow_empty_password(true)
+            .interact()
+            .map_err(|e| e.to_string())?;
+
+        secrets.push(("grant_type", "password".to_string()));
+        secrets.push(("username", username));
+        if !password.is_empty() {
+            secrets.push(("password", password));
+        }
+    } else if flow == 1 {
+        let tenant: String = Input::with_theme(&theme)
+            .with_prompt("Tenant (ID ou domínio)")
+            .interact_text()
+            .map_err(|e| e.to_string())?;
+        let client_secret = Password::with_theme(&theme)
+            .with_prompt("client_secret")
+            .interact()
+            .map_err(|e| e.to_string())?;
+
+        secrets.push(("grant_type", "client_credentials".to_string()));
+        secrets.push(("tenant", tenant));
+        secrets.push(("client_secret", client_secret));
+    }
+
+    let content: String = secrets
+        .iter()
+        .map(|(key, value)| format!("{} = \"{}\"\n", key, value.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+
+    fs::write(FILENAME_SECRETS_TOML, content).map_err(|e| format!("Falha ao gravar {}.\nErro: {}", FILENAME_SECRETS_TOML, e))?;
+    println!("Arquivo {} criado.", FILENAME_SECRETS_TOML);
+
+    Ok(())
+}
+
+async fn write_datasets() -> Result<(), String> {
+
+    let import = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("Importar datasets a partir da API do PowerBI?")
+        .default(true)
+        .interact()
+        .map_err(|e| e.to_string())?;
+
+    let entries = if import { import_datasets().await? } else { manual_datasets()? };
+
+    let content = serde_json::to_string_pretty(&entries).unwrap();
+    backup_config_file()?;
+    fs::write(FILENAME_CONFIG_JSON, content).map_err(|e| format!("Falha ao gravar {}.\nErro: {}", FILENAME_CONFIG_JSON, e))?;
+    println!("Arquivo {} criado com {} empresa(s).", FILENAME_CONFIG_JSON, entries.len());
+
+    Ok(())
+}
+
+/// Lista os datasets atualizáveis dos workspaces e agrupa os escolhidos por empresa.
+async fn import_datasets() -> Result<Vec<GuidEntry>, String> {
+
+    let theme = ColorfulTheme::default();
+
+    let secrets = read_secrets_file(None);
+    set_api_base_url(secrets.api_base_url());
+    let token = acquire_new_token(&secrets).await.map_err(|e| format!("Erro ao gerar novo token.\n{}", e))?;
+    export_token(&secrets, &token);
+
+    let mut candidates = Vec::new();
+
+    for group in list_groups(&token).await? {
+        for dataset in list_datasets(&group.id, &token).await? {
+            if dataset.is_refreshable {
+                candidates.push((group.clone(), dataset));
+            }
+        }
+    }
+
+    if candidates.is_empty() {
+        return Err("Nenhum dataset atualizável encontrado para esta conta.".to_string());
+    }
+
+    let labels: Vec<String> = candidates.iter().map(|(group, dataset)| format!("{} / {}", group.name, dataset.name)).collect();
+    let selected = MultiSelect::with_theme(&theme)
+        .with_prompt("Datasets (espaço para marcar, ENTER para confirmar)")
+        .items(&labels)
+        .interact()
+        .map_err(|e| e.to_string())?;
+
+    // Cada workspace escolhido vira uma empresa.
+    let mut by_group: BTreeMap<String, (String, Vec<DatasetConfig>)> = BTreeMap::new();
+    for index in selected {
+        let (group, dataset) = &candidates[index];
+        by_group
+            .entry(group.id.clone())
+            .or_insert_with(|| (group.name.clone(), Vec::new()))
+            .1
+            .push(DatasetConfig { name: Some(dataset.name.clone()), ..DatasetConfig::new(&dataset.id) });
+    }
+
+    let mut entries = Vec::new();
+
+    for (next_id, (workspace, (name, guid))) in (1u32..).zip(by_group) {
+        let id: u32 = Input::with_theme(&theme)
+            .with_prompt(format!("ID da empresa para o workspace \"{}\"", name))
+            .default(next_id)
+            .interact_text()
+            .map_err(|e| e.to_string())?;
+
+        entries.push(GuidEntry { id, guid, workspace: Some(workspace), ..Default::default() });
+    }
+
+    Ok(entries)
+}
+
+fn manual_datasets() -> Result<Vec<GuidEntry>, String> {
+
+    let theme = ColorfulTheme::default();
+    let mut entries = Vec::new();
+
+    loop {
+        let id: u32 = Input::with_theme(&theme)
+            .with_prompt("ID Empresa")
+            .interact_text()
+            .map_err(|e| e.to_string())?;
+
+        let guids: String = Input::with_theme(&theme)
+            .with_prompt("GUID dos datasets (separados por vírgula)")
+            .interact_text()
+            .map_err(|e| e.to_string())?;
+
+        let guid = guids.split(',').map(str::trim).filter(|guid| !guid.is_empty()).map(DatasetConfig::new).collect();
+        entries.push(GuidEntry { id, guid, ..Default::default() });
+
+        let more = Confirm::with_theme(&theme)
+            .with_prompt("Adicionar outra empresa?")
+            .default(false)
+            .interact()
+            .map_err(|e| e.to_string())?;
+
+        if !more {
+            return Ok(entries);
+        }
+    }
+}
+
+/// Indica se falta algum arquivo de configuração (sem as variáveis de ambiente equivalentes).
+pub fn missing_config_files() -> bool {
+    let secrets_missing = !Path::new(FILENAME_SECRETS_TOML).exists() && std::env::var("PBI_CLIENT_ID").is_err() && std::env::var("PBI_CLIENT_ID_FILE").is_err();
+    let datasets_missing = !Path::new(FILENAME_CONFIG_JSON).exists() && std::env::var("PBI_DATASETS").is_err();
+    secrets_missing || datasets_missing
+}
@@ -0,0 +1,304 @@
+use std::{collections::HashMap, net::TcpListener, sync::{atomic::{AtomicU32, Ordering}, Arc, Mutex}};
+use axum::{Router, routing::{get, post}, extract::{Form, Path, Query, State}, response::{IntoResponse, Json, Response}, http::{header::{AUTHORIZATION, RETRY_AFTER}, HeaderMap, StatusCode}};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+use colored::Colorize;
+use serde_json::{json, Value};
+
+use crate::config::{Cloud, GrantType, Secrets, TokenStorage};
+use crate::history;
+use crate::history_store::HistoryBackend;
+use crate::powerbi::new_request_id;
+use crate::token::{acquire_new_token, export_token};
+
+/// Perfil dos segredos simulados, para que o token da simulação não substitua o do perfil real.
+const SIMULATION_PROFILE: &str = "simulacao";
+/// Histórico separado, para que as atualizações simuladas não entrem em relatórios e prazos.
+const FILENAME_SIMULATION_HISTORY: &str = "history-simulacao.jsonl";
+/// Duração da tempestade de limites, a partir do primeiro envio.
+const STORM_SECONDS: i64 = 30;
+const STORM_RETRY_AFTER_SECONDS: u64 = 5;
+/// Tempo de cada atualização simulada na fila da capacidade e em processamento.
+const QUEUED_SECONDS: i64 = 5;
+const RUNNING_SECONDS: i64 = 20;
+/// Com o gateway fora do ar, a atualização falha logo ao tentar a conexão.
+const GATEWAY_TIMEOUT_SECONDS: i64 = 5;
+const GATEWAY_ERROR_CODE: &str = "DM_GWPipeline_Client_GatewayUnreachable";
+
+/// Cenário de falha simulado (`--scenario`).
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Scenario {
+    /// O token é revogado depois do primeiro envio e a API passa a recusá-lo (401) até ser renovado.
+    TokenExpiry,
+    /// A API limita as requisições (429) por 30 segundos a partir do primeiro envio.
+    ThrottlingStorm,
+    /// O gateway está fora do ar: as atualizações são aceitas e falham com `DM_GWPipeline_Client_GatewayUnreachable`.
+    GatewayOutage,
+}
+
+impl Scenario {
+    fn label(&self) -> &'static str {
+        match self {
+            Scenario::TokenExpiry => "token expirado no meio da execução",
+            Scenario::ThrottlingStorm => "tempestade de limites da API",
+            Scenario::GatewayOutage => "gateway fora do ar",
+        }
+    }
+}
+
+/// Atualização aceita pela API simulada; o status avança com o tempo desde o envio.
+struct SimulatedRefresh {
+    request_id: String,
+    accepted_at: DateTime<Utc>,
+}
+
+struct Simulation {
+    scenario: Scenario,
+    /// Prefixo dos tokens emitidos nesta execução; tokens guardados de simulações anteriores são recusados.
+    nonce: String,
+    issued: AtomicU32,
+    /// Tokens com número até este foram revogados.
+    revoked_up_to: AtomicU32,
+    storm_started: Mutex<Option<DateTime<Utc>>>,
+    refreshes: Mutex<HashMap<String, Vec<SimulatedRefresh>>>,
+}
+
+impl Simulation {
+
+    /// Aceita apenas os tokens emitidos nesta execução e ainda não revogados. A "assinatura" do token é o
+    /// prefixo da execução seguido do número do token.
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+
+        let number = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| token.rsplit('.').next())
+            .and_then(|signature| signature.strip_prefix(&format!("{}-", self.nonce)))
+            .and_then(|number| number.parse::<u32>().ok());
+
+        number.is_some_and(|number| number > self.revoked_up_to.load(Ordering::Relaxed))
+    }
+
+    fn refresh_json(&self, refresh: &SimulatedRefresh, now: DateTime<Utc>) -> Value {
+
+        let started_at = refresh.accepted_at + Duration::seconds(QUEUED_SECONDS);
+        let running = match self.scenario {
+            Scenario::GatewayOutage => GATEWAY_TIMEOUT_SECONDS,
+            _ => RUNNING_SECONDS,
+        };
+        let ended_at = started_at + Duration::seconds(running);
+
+        let mut value = json!({
+            "requestId": refresh.request_id,
+            "refreshType": "ViaApi",
+            "status": "Unknown",
+        });
+
+        if now < started_at {
+            value["extendedStatus"] = json!("NotStarted");
+            return value;
+        }
+
+        value["startTime"] = json!(started_at);
+
+        if now < ended_at {
+            value["extendedStatus"] = json!("InProgress");
+            return value;
+        }
+
+        value["endTime"] = json!(ended_at);
+
+        if self.scenario == Scenario::GatewayOutage {
+            let exception = json!({ "errorCode": GATEWAY_ERROR_CODE, "errorDescription": "The gateway is either offline or could not be reached." });
+            value["status"] = json!("Failed");
+            value["serviceExceptionJson"] = json!(exception.to_string());
+        } else {
+            value["status"] = json!("Completed");
+        }
+
+        value
+    }
+}
+
+/// Inicia a API simulada (login e PowerBI) em uma porta local e devolve os segredos que apontam para ela.
+/// O histórico passa a ser gravado em `history-simulacao.jsonl`; os avisos seguem o `dataset.json`, para
+/// testar o encaminhamento das notificações de ponta a ponta.
+pub async fn start(scenario: Scenario) -> Result<Secrets, String> {
+
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(|e| format!("Falha ao iniciar a API simulada.\nErro: {}", e))?;
+    let address = listener.local_addr().map_err(|e| e.to_string())?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+    let simulation = Arc::new(Simulation {
+        scenario,
+        nonce: format!("{}-{}", SIMULATION_PROFILE, new_request_id()),
+        issued: AtomicU32::new(0),
+        revoked_up_to: AtomicU32::new(0),
+        storm_started: Mutex::new(None),
+        refreshes: Mutex::new(HashMap::new()),
+    });
+
+    let app = Router::new()
+        .route("/:tenant/oauth2/token", post(issue_token))
+        .route("/v1.0/myorg/datasets/:dataset", get(dataset))
+        .route("/v1.0/myorg/groups/:group/datasets/:dataset", get(dataset))
+        .route("/v1.0/myorg/datasets/:dataset/refreshes", get(list_refreshes).post(submit_refresh))
+        .route("/v1.0/myorg/groups/:group/datasets/:dataset/refreshes", get(list_refreshes).post(submit_refresh))
+        .with_state(simulation);
+
+    let server = axum::Server::from_tcp(listener).map_err(|e| format!("Falha ao iniciar a API simulada.\nErro: {}", e))?;
+    tokio::spawn(async move {
+        if let Err(e) = server.serve(app.into_make_service()).await {
+            eprintln!("Falha na API simulada.\nErro: {}", e);
+        }
+    });
+
+    history::set_backend(&HistoryBackend::File { path: Some(FILENAME_SIMULATION_HISTORY.to_string()) })?;
+
+    let secrets = Secrets {
+        client_id: SIMULATION_PROFILE.to_string(),
+        grant_type: GrantType::Password,
+        resource: None,
+        tenant: Some(SIMULATION_PROFILE.to_string()),
+        client_secret: None,
+        username: Some(format!("operador@{}", SIMULATION_PROFILE)),
+        password: Some(SIMULATION_PROFILE.to_string()),
+        certificate: None,
+        access_token: None,
+        token_storage: TokenStorage::File,
+        cloud: Cloud::Public,
+        authority_url: Some(format!("http://{}", address)),
+        api_base_url: Some(format!("http://{}/v1.0/myorg", address)),
+        profile: Some(SIMULATION_PROFILE.to_string()),
+    };
+
+    // O token da simulação anterior fica guardado, mas não vale para esta API.
+    let token = acquire_new_token(&secrets).await?;
+    export_token(&secrets, &token);
+
+    println!(
+        "{} cenário \"{}\". Nenhuma chamada chega ao PowerBI; histórico em {}.",
+        "Simulação:".yellow(),
+        scenario.label(),
+        FILENAME_SIMULATION_HISTORY
+    );
+
+    Ok(secrets)
+}
+
+/// Resposta da API a um token expirado ou revogado.
+fn token_expired() -> Response {
+    let error = json!({ "error": { "code": "TokenExpired", "message": "Access token has expired, resubmit with a new access token" } });
+    (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+}
+
+/// Emite um token para qualquer fluxo (senha ou `refresh_token`): um JWT sem assinatura válida, com a audiência
+/// pedida e a permissão de atualização, para que a conferência das declarações não acuse problemas.
+async fn issue_token(State(simulation): State<Arc<Simulation>>, Form(form): Form<HashMap<String, String>>) -> impl IntoResponse {
+
+    let number = simulation.issued.fetch_add(1, Ordering::Relaxed) + 1;
+    let expires = Utc::now() + Duration::hours(1);
+
+    let claims = json!({
+        "aud": form.get("resource"),
+        "scp": "Dataset.ReadWrite.All",
+        "exp": expires.timestamp(),
+        "upn": format!("operador@{}", SIMULATION_PROFILE),
+        "tid": SIMULATION_PROFILE,
+    });
+    let access_token = format!(
+        "{}.{}.{}-{}",
+        URL_SAFE_NO_PAD.encode(json!({ "alg": "none", "typ": "JWT" }).to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string()),
+        simulation.nonce,
+        number
+    );
+
+    Json(json!({
+        "token_type": "Bearer",
+        "expires_on": expires.timestamp().to_string(),
+        "access_token": access_token,
+        "refresh_token": format!("{}-refresh", simulation.nonce),
+    }))
+}
+
+/// Dataset do "Meu workspace", para que a atualização simples seja a escolhida.
+async fn dataset(State(simulation): State<Arc<Simulation>>, headers: HeaderMap, Path(params): Path<HashMap<String, String>>) -> Response {
+
+    if !simulation.authorized(&headers) {
+        return token_expired();
+    }
+
+    let id = params.get("dataset").cloned().unwrap_or_default();
+    Json(json!({
+        "id": id,
+        "name": format!("Dataset simulado {}", id),
+        "isRefreshable": true,
+        "webUrl": format!("https://app.powerbi.com/groups/me/datasets/{}", id),
+    })).into_response()
+}
+
+/// Histórico de atualizações do dataset, da mais recente para a mais antiga, limitado por `$top`.
+async fn list_refreshes(
+    State(simulation): State<Arc<Simulation>>,
+    headers: HeaderMap,
+    Path(params): Path<HashMap<String, String>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+
+    if !simulation.authorized(&headers) {
+        return token_expired();
+    }
+
+    let top = query.get("$top").and_then(|top| top.parse::<usize>().ok()).unwrap_or(usize::MAX);
+    let now = Utc::now();
+    let refreshes = simulation.refreshes.lock().unwrap();
+
+    let value: Vec<Value> = refreshes
+        .get(params.get("dataset").map(String::as_str).unwrap_or_default())
+        .map(|list| list.iter().rev().take(top).map(|refresh| simulation.refresh_json(refresh, now)).collect())
+        .unwrap_or_default();
+
+    Json(json!({ "value": value })).into_response()
+}
+
+/// Aceita a atualização (202), salvo durante a tempestade de limites. No cenário de token expirado, o primeiro
+/// envio revoga os tokens emitidos até então.
+async fn submit_refresh(State(simulation): State<Arc<Simulation>>, headers: HeaderMap, Path(params): Path<HashMap<String, String>>) -> Response {
+
+    if !simulation.authorized(&headers) {
+        return token_expired();
+    }
+
+    let now = Utc::now();
+
+    if simulation.scenario == Scenario::ThrottlingStorm {
+        let started = *simulation.storm_started.lock().unwrap().get_or_insert(now);
+        if now - started < Duration::seconds(STORM_SECONDS) {
+            return (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, STORM_RETRY_AFTER_SECONDS.to_string())]).into_response();
+        }
+    }
+
+    let request_id = headers
+        .get("x-ms-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(new_request_id);
+
+    simulation
+        .refreshes
+        .lock()
+        .unwrap()
+        .entry(params.get("dataset").cloned().unwrap_or_default())
+        .or_default()
+        .push(SimulatedRefresh { request_id: request_id.clone(), accepted_at: now });
+
+    if simulation.scenario == Scenario::TokenExpiry {
+        let issued = simulation.issued.load(Ordering::Relaxed);
+        let _ = simulation.revoked_up_to.compare_exchange(0, issued, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    (StatusCode::ACCEPTED, [("RequestId", request_id)]).into_response()
+}
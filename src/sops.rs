@@ -0,0 +1,26 @@
+use std::{path::Path, process::Command};
+use serde_json::Value;
+
+/// Indica se o conteúdo é um arquivo cifrado pelo SOPS. Extensões que o SOPS não conhece, como `.toml`, são cifradas
+/// no formato binário: um JSON com o conteúdo em `data` e os metadados das chaves em `sops`.
+pub fn is_encrypted(content: &str) -> bool {
+    serde_json::from_str::<Value>(content).is_ok_and(|document| document["sops"].is_object() && document["data"].is_string())
+}
+
+/// Decifra o arquivo com a CLI do SOPS, que encontra as chaves (age, PGP, KMS, Key Vault) pela própria configuração
+/// (ex.: `SOPS_AGE_KEY_FILE`). O conteúdo decifrado fica apenas em memória.
+pub fn decrypt(path: &Path) -> Result<String, String> {
+
+    let output = Command::new("sops")
+        .args(["--decrypt", "--input-type", "binary", "--output-type", "binary"])
+        .arg(path)
+        .output()
+        .map_err(|e| format!("SOPS não encontrado; instale-o para ler o {} cifrado.\nErro: {}", path.display(), e))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Falha ao decifrar {} com o SOPS.\n{}", path.display(), error.trim()));
+    }
+
+    String::from_utf8(output.stdout).map_err(|e| format!("Conteúdo decifrado de {} inválido.\nErro: {}", path.display(), e))
+}
@@ -0,0 +1,251 @@
+use std::{env, fs::{self, File}, io::{Read, Write}, path::{Path, PathBuf}};
+use aes_gcm::{aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng}, Aes256Gcm, Nonce};
+use dialoguer::{Password, theme::ColorfulTheme};
+use pbkdf2::pbkdf2_hmac;
+use sha2::Sha256;
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::backup::{backup_config_file, BACKUP_DIR};
+use crate::config::{load_config_file, profile_names, FILENAME_CONFIG_JSON, FILENAME_SECRETS_TOML};
+use crate::explain::FILENAME_ERRORS_YAML;
+use crate::history_store::local_path;
+use crate::token::{is_token_file, keyring_tokens};
+use crate::token_crypt;
+use crate::ui::is_interactive;
+
+/// Senha que cifra os segredos e tokens do pacote, para execuções sem terminal.
+pub const ENV_STATE_PASSPHRASE: &str = "PBI_STATE_PASSPHRASE";
+/// Pasta, dentro do pacote, dos arquivos cifrados com a senha.
+const ENCRYPTED_DIR: &str = "encrypted";
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+/// Iterações do PBKDF2 na derivação da chave a partir da senha.
+const PBKDF2_ROUNDS: u32 = 600_000;
+
+fn passphrase(confirm: bool) -> Result<String, String> {
+
+    if let Some(passphrase) = env::var(ENV_STATE_PASSPHRASE).ok().filter(|passphrase| !passphrase.is_empty()) {
+        return Ok(passphrase);
+    }
+
+    if !is_interactive() {
+        return Err(format!("Defina {} com a senha dos segredos e tokens do pacote.", ENV_STATE_PASSPHRASE));
+    }
+
+    let theme = ColorfulTheme::default();
+    let prompt = Password::with_theme(&theme).with_prompt("Senha do pacote");
+    let prompt = if confirm { prompt.with_confirmation("Confirme a senha", "As senhas não conferem.") } else { prompt };
+
+    prompt.interact().map_err(|e| e.to_string())
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Aes256Gcm {
+    let mut key = [0u8; 32];
+    pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    Aes256Gcm::new(&key.into())
+}
+
+/// Cifra com uma chave derivada da senha; o sal e o nonce são gravados antes do conteúdo.
+fn seal(data: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+
+    let mut salt = [0u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let encrypted = cipher(passphrase, &salt).encrypt(&nonce, data).map_err(|e| format!("Falha ao cifrar.\nErro: {}", e))?;
+
+    Ok([salt.as_slice(), nonce.as_slice(), &encrypted].concat())
+}
+
+fn open(data: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+
+    if data.len() < SALT_SIZE + NONCE_SIZE {
+        return Err("Arquivo cifrado corrompido.".to_string());
+    }
+
+    let (salt, data) = data.split_at(SALT_SIZE);
+    let (nonce, encrypted) = data.split_at(NONCE_SIZE);
+
+    cipher(passphrase, salt)
+        .decrypt(Nonce::from_slice(nonce), encrypted)
+        .map_err(|_| "Senha incorreta ou pacote corrompido.".to_string())
+}
+
+/// Arquivos de estado existentes: configurações, mensagens de erro, histórico local e cópias do `dataset.json`.
+fn state_files() -> Vec<PathBuf> {
+
+    let history = load_config_file().ok().and_then(|config| config.history);
+
+    let mut files: Vec<PathBuf> = [Some(FILENAME_CONFIG_JSON), Some(FILENAME_ERRORS_YAML), local_path(history.as_ref())]
+        .into_iter()
+        .flatten()
+        .map(PathBuf::from)
+        .collect();
+
+    if let Ok(entries) = fs::read_dir(BACKUP_DIR) {
+        files.extend(entries.map_while(Result::ok).map(|entry| entry.path()));
+    }
+
+    files.retain(|path| path.is_file());
+    files
+}
+
+fn token_files() -> Vec<PathBuf> {
+    match fs::read_dir(".") {
+        Ok(entries) => entries
+            .map_while(Result::ok)
+            .filter(|entry| entry.file_name().to_str().is_some_and(is_token_file))
+            .map(|entry| PathBuf::from(entry.file_name()))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Cache de tokens de todos os perfis, pelo nome do arquivo de token: os guardados no cofre de credenciais (o padrão)
+/// e os arquivos `.token*`, já decifrados. Quando o perfil tem os dois, vale o do cofre, como na leitura do token.
+fn saved_tokens() -> Result<Vec<(String, String)>, String> {
+
+    let profiles: Vec<Option<String>> = std::iter::once(None).chain(profile_names().into_iter().map(Some)).collect();
+    let mut tokens = keyring_tokens(&profiles);
+
+    for path in token_files() {
+
+        let name = entry_name(&path);
+        if tokens.iter().any(|(existing, _)| *existing == name) {
+            continue;
+        }
+
+        // O arquivo é cifrado para a máquina atual; no pacote ele vai com a senha, para valer no destino.
+        let mut content = fs::read_to_string(&path).map_err(|e| format!("Falha ao ler {}.\nErro: {}", path.display(), e))?;
+        if token_crypt::is_encrypted(&content) {
+            content = token_crypt::decrypt(&content)?;
+        }
+
+        tokens.push((name, content));
+    }
+
+    Ok(tokens)
+}
+
+/// Nome do arquivo dentro do pacote: o caminho relativo à pasta do programa ou, se estiver fora dela, só o nome.
+fn entry_name(path: &Path) -> String {
+    let path = if path.is_absolute() { Path::new(path.file_name().unwrap_or_default()) } else { path };
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Empacota o estado da instalação em um zip: `dataset.json`, `errors.yaml`, histórico local, cópias do
+/// `dataset.json` e os runbooks informados. Com `secrets` e `tokens`, inclui o `secrets.toml` e o cache de tokens
+/// (do cofre de credenciais ou dos arquivos), cifrados com uma senha. Devolve a quantidade de arquivos.
+pub fn export(path: &str, runbooks: &[String], secrets: bool, tokens: bool) -> Result<usize, String> {
+
+    let mut plain = state_files();
+    plain.extend(runbooks.iter().map(PathBuf::from));
+
+    // Conteúdo cifrado com a senha, pelo nome no pacote.
+    let mut sealed = Vec::new();
+    if secrets && Path::new(FILENAME_SECRETS_TOML).exists() {
+        let content = fs::read_to_string(FILENAME_SECRETS_TOML).map_err(|e| format!("Falha ao ler {}.\nErro: {}", FILENAME_SECRETS_TOML, e))?;
+        sealed.push((FILENAME_SECRETS_TOML.to_string(), content));
+    }
+    if tokens {
+        let saved = saved_tokens()?;
+        if saved.is_empty() {
+            return Err("Nenhum token guardado (cofre de credenciais ou arquivo .token) para incluir com --tokens.".to_string());
+        }
+        sealed.extend(saved);
+    }
+
+    let passphrase = if sealed.is_empty() { None } else { Some(passphrase(true)?) };
+
+    let file = File::create(path).map_err(|e| format!("Falha ao criar {}.\nErro: {}", path, e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for source in &plain {
+        let content = fs::read(source).map_err(|e| format!("Falha ao ler {}.\nErro: {}", source.display(), e))?;
+        zip.start_file(entry_name(source), options).map_err(|e| e.to_string())?;
+        zip.write_all(&content).map_err(|e| e.to_string())?;
+    }
+
+    for (name, content) in &sealed {
+        let content = seal(content.as_bytes(), passphrase.as_deref().unwrap_or_default())?;
+        zip.start_file(format!("{}/{}", ENCRYPTED_DIR, name), options).map_err(|e| e.to_string())?;
+        zip.write_all(&content).map_err(|e| e.to_string())?;
+    }
+
+    zip.finish().map_err(|e| format!("Falha ao gravar {}.\nErro: {}", path, e))?;
+    Ok(plain.len() + sealed.len())
+}
+
+/// Restaura um pacote do `state export` na pasta do programa. Arquivos existentes só são substituídos com `force`; o
+/// `dataset.json` atual ganha uma cópia em `backups` antes. Devolve a quantidade de arquivos restaurados.
+pub fn import(path: &str, force: bool) -> Result<usize, String> {
+
+    let file = File::open(path).map_err(|e| format!("Falha ao abrir {}.\nErro: {}", path, e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("{} não é um pacote válido.\nErro: {}", path, e))?;
+
+    let mut entries = Vec::new();
+
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(|e| e.to_string())?;
+        if entry.is_dir() {
+            continue;
+        }
+
+        // Caminhos absolutos ou com `..` poderiam gravar fora da pasta do programa.
+        let name = entry.enclosed_name().map(Path::to_path_buf).ok_or_else(|| format!("Caminho inválido no pacote: {}", entry.name()))?;
+
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).map_err(|e| e.to_string())?;
+
+        match name.strip_prefix(ENCRYPTED_DIR) {
+            Ok(target) => entries.push((target.to_path_buf(), content, true)),
+            Err(_) => entries.push((name, content, false)),
+        }
+    }
+
+    let existing: Vec<String> = entries.iter().filter(|(target, _, _)| target.exists()).map(|(target, _, _)| target.display().to_string()).collect();
+    if !force && !existing.is_empty() {
+        return Err(format!("Arquivos já existentes (use --force para substituí-los): {}", existing.join(", ")));
+    }
+
+    let passphrase = if entries.iter().any(|(_, _, encrypted)| *encrypted) { Some(passphrase(false)?) } else { None };
+
+    // Tudo é decifrado antes da gravação, para que uma senha errada não deixe a restauração pela metade.
+    let mut files = Vec::new();
+
+    for (target, content, encrypted) in entries {
+
+        let content = match encrypted {
+            false => content,
+            true => {
+                let content = open(&content, passphrase.as_deref().unwrap_or_default())?;
+
+                // O token volta a ser cifrado para a nova máquina, como os gravados pelo programa.
+                if target.to_str().is_some_and(is_token_file) {
+                    token_crypt::encrypt(&String::from_utf8(content).map_err(|e| e.to_string())?)?.into_bytes()
+                } else {
+                    content
+                }
+            }
+        };
+
+        files.push((target, content));
+    }
+
+    if files.iter().any(|(target, _)| target == Path::new(FILENAME_CONFIG_JSON)) {
+        backup_config_file()?;
+    }
+
+    for (target, content) in &files {
+
+        if let Some(parent) = target.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| format!("Falha ao criar pasta {}.\nErro: {}", parent.display(), e))?;
+        }
+
+        fs::write(target, content).map_err(|e| format!("Falha ao gravar {}.\nErro: {}", target.display(), e))?;
+        println!("\t- {}", target.display());
+    }
+
+    Ok(files.len())
+}
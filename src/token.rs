@@ -0,0 +1,292 @@
+use std::{fs::{self, File, OpenOptions, TryLockError}, io::{Write, Read}, env, sync::atomic::{AtomicI64, Ordering}, time::Instant};
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::auth::provider;
+use crate::config::{Secrets, TokenStorage};
+use crate::keystore;
+use crate::token_crypt;
+
+const FILENAME_TOKEN_JSON: &str = ".token";
+/// Arquivo temporário renomeado sobre o `.token`, para que uma leitura nunca encontre a gravação pela metade.
+const SUFFIX_TOKEN_TMP: &str = ".tmp";
+/// Trava compartilhada entre processos (ex.: o `serve` e uma execução avulsa) durante a renovação do token.
+const SUFFIX_TOKEN_LOCK: &str = ".lock";
+/// Tempo máximo de espera pela trava; esgotado, a renovação segue sem ela.
+const LOCK_TIMEOUT_SECONDS: u64 = 120;
+/// Antecedência padrão da renovação, para que nenhuma requisição saia com um token prestes a expirar.
+const DEFAULT_SKEW_MINUTES: i64 = 5;
+
+static TOKEN_SKEW_MINUTES: AtomicI64 = AtomicI64::new(DEFAULT_SKEW_MINUTES);
+
+/// Define a antecedência com que os tokens são renovados (`token_skew_minutes`).
+pub fn set_token_skew(minutes: i64) {
+    TOKEN_SKEW_MINUTES.store(minutes.max(0), Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub token_type: String,
+    pub expires_on: String,
+    pub access_token: String,
+    /// Devolvido nos fluxos de usuário; permite renovar o acesso sem nova autenticação.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+/// Audiência configurada para as chamadas à API do PowerBI; sem o segredo `resource`, vale a da nuvem.
+pub fn default_resource(secrets: &Secrets) -> &str {
+    secrets.resource.as_deref().unwrap_or(secrets.cloud.resource())
+}
+
+pub async fn acquire_new_token(secrets: &Secrets) -> Result<TokenResponse, String> {
+    acquire_token_for(secrets, default_resource(secrets)).await
+}
+
+/// Gera um token para a audiência informada (PowerBI, Fabric, XMLA...), com as mesmas credenciais.
+pub async fn acquire_token_for(secrets: &Secrets, resource: &str) -> Result<TokenResponse, String> {
+    provider(secrets).token(resource).await
+}
+
+/// Renova o token com o `refresh_token` da sessão anterior, mantendo-o quando o servidor não envia um novo.
+pub async fn redeem_refresh_token(secrets: &Secrets, resource: &str, refresh_token: &str) -> Result<TokenResponse, String> {
+
+    let tenant = secrets.tenant.as_deref().unwrap_or("common");
+    let url = format!("{}/{}/oauth2/token", secrets.authority(), tenant);
+    let params = [
+        ("client_id", Some(secrets.client_id.as_str())),
+        ("client_secret", secrets.client_secret.as_deref()),
+        ("grant_type", Some("refresh_token")),
+        ("refresh_token", Some(refresh_token)),
+        ("resource", Some(resource)),
+    ];
+
+    let res = reqwest::Client::new()
+        .post(url)
+        .form(&params)
+        .send()
+        .await
+        .map_err(|e| format!("Falha ao renovar token.\nErro: {}", e))?;
+
+    if !res.status().is_success() {
+        return Err(res.text().await.unwrap_or_default());
+    }
+
+    let token: TokenResponse = res.json().await.map_err(|e| format!("Falha ao converter JSON.\nErro: {}", e))?;
+    Ok(TokenResponse { refresh_token: token.refresh_token.or_else(|| Some(refresh_token.to_string())), ..token })
+}
+
+/// Gera um novo token para a audiência, tentando antes o `refresh_token` do token expirado;
+/// se ele também tiver perdido a validade, refaz a autenticação completa.
+pub async fn renew_token(secrets: &Secrets, resource: &str, expired: Option<&TokenResponse>) -> Result<TokenResponse, String> {
+
+    if let Some(refresh_token) = expired.and_then(|token| token.refresh_token.as_deref()) {
+        match redeem_refresh_token(secrets, resource, refresh_token).await {
+            Ok(token) => return Ok(token),
+            Err(_) => println!("Não foi possível renovar o token salvo; autenticando novamente."),
+        }
+    }
+
+    acquire_token_for(secrets, resource).await
+}
+
+/// Indica se o token ainda vale por mais que a antecedência de renovação configurada.
+pub fn validate_token(token: &TokenResponse) -> bool {
+
+    let now: DateTime<Utc> = Utc::now() + Duration::minutes(TOKEN_SKEW_MINUTES.load(Ordering::Relaxed));
+
+    let expire_token: i64 = token.expires_on.trim().parse::<i64>().unwrap_or_default();
+
+    let expire_token_date: DateTime<Utc> = DateTime::from_timestamp(expire_token, 0).unwrap();
+
+    now < expire_token_date
+}
+
+/// Arquivo do token do perfil: `.token` no perfil padrão e `.token-<perfil>` nos nomeados.
+fn token_filename(profile: Option<&str>) -> String {
+    match profile {
+        Some(profile) => format!("{}-{}", FILENAME_TOKEN_JSON, profile),
+        None => FILENAME_TOKEN_JSON.to_string(),
+    }
+}
+
+/// Indica se o arquivo é o cache de token de algum perfil (`.token`, `.token-<perfil>`), sem temporários e travas.
+pub fn is_token_file(name: &str) -> bool {
+    (name == FILENAME_TOKEN_JSON || name.starts_with(&format!("{}-", FILENAME_TOKEN_JSON)))
+        && !name.ends_with(SUFFIX_TOKEN_TMP)
+        && !name.ends_with(SUFFIX_TOKEN_LOCK)
+}
+
+/// Conta do token do perfil no cofre de credenciais.
+fn keyring_account(profile: Option<&str>) -> String {
+    match profile {
+        Some(profile) => format!("{}@{}", keystore::KEYRING_ACCOUNT, profile),
+        None => keystore::KEYRING_ACCOUNT.to_string(),
+    }
+}
+
+/// Tokens guardados no cofre de credenciais pelos perfis informados (`None` é o perfil padrão), com o nome do
+/// arquivo de token equivalente. No destino de um `state export`, o perfil que usa o cofre migra o arquivo para ele
+/// na primeira leitura.
+pub fn keyring_tokens(profiles: &[Option<String>]) -> Vec<(String, String)> {
+    profiles
+        .iter()
+        .filter_map(|profile| keystore::load(&keyring_account(profile.as_deref())).map(|content| (token_filename(profile.as_deref()), content)))
+        .collect()
+}
+
+fn read_token_file(secrets: &Secrets) -> Option<TokenResponse> {
+
+    let filename = token_filename(secrets.profile.as_deref());
+    let current_dir = env::current_dir().expect("Erro ao obter diretório de execução");
+    let full_current_dir = current_dir.join(&filename);
+
+    let mut file = match File::open(full_current_dir) {
+        Ok(file) => file,
+        Err(_) => return None,
+    };
+
+    let mut content: String = String::new();
+    if file.read_to_string(&mut content).is_err() {
+        return None;
+    }
+
+    if token_crypt::is_encrypted(&content) {
+        return match token_crypt::decrypt(&content) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+            Err(e) => {
+                eprintln!("{}", e);
+                None
+            }
+        };
+    }
+
+    // Arquivo em texto puro de versões anteriores: regravado já cifrado.
+    let token: TokenResponse = serde_json::from_str(&content).ok()?;
+    if secrets.token_storage == TokenStorage::File {
+        write_token_file(secrets, &content);
+        println!("Arquivo {} convertido para o formato cifrado.", filename);
+    }
+
+    Some(token)
+}
+
+/// Grava o token cifrado; sem como cifrar, o token não é gravado.
+fn write_token_file(secrets: &Secrets, content: &str) {
+    let filename = token_filename(secrets.profile.as_deref());
+    let temporary = format!("{}{}", filename, SUFFIX_TOKEN_TMP);
+
+    let content = match token_crypt::encrypt(content) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("{}\nO token não será reaproveitado na próxima execução.", e);
+            return;
+        }
+    };
+
+    let result = File::create(&temporary)
+        .and_then(|mut file| file.write_all(content.as_bytes()).and_then(|_| file.sync_all()))
+        .and_then(|_| fs::rename(&temporary, &filename));
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temporary);
+        eprintln!("Falha ao gravar arquivo {}.\nErro: {}", filename, e);
+    }
+}
+
+/// Trava o cache do token do perfil entre processos até o valor devolvido ser descartado. Sem como travar
+/// (ou esgotada a espera), devolve `None` e a renovação segue sem a trava.
+async fn lock_token_cache(secrets: &Secrets) -> Option<File> {
+
+    let filename = format!("{}{}", token_filename(secrets.profile.as_deref()), SUFFIX_TOKEN_LOCK);
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(filename).ok()?;
+    let started = Instant::now();
+    let mut warned = false;
+
+    loop {
+        match file.try_lock() {
+            Ok(_) => return Some(file),
+            Err(TryLockError::WouldBlock) if started.elapsed().as_secs() < LOCK_TIMEOUT_SECONDS => {
+                if !warned {
+                    println!("Aguardando outra execução terminar de renovar o token...");
+                    warned = true;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+            }
+            Err(_) => return None,
+        }
+    }
+}
+
+/// Renova o token salvo do perfil com o cache travado. Se outra execução já o renovou enquanto esta aguardava
+/// a trava, o token salvo é reaproveitado em vez de gerar mais um.
+pub async fn renew_saved_token(secrets: &Secrets, expired: Option<&TokenResponse>) -> Result<TokenResponse, String> {
+
+    let _lock = lock_token_cache(secrets).await;
+
+    let renewed_elsewhere = read_saved_token(secrets)
+        .filter(validate_token)
+        .filter(|saved| expired.is_none_or(|expired| saved.access_token != expired.access_token));
+    if let Some(saved) = renewed_elsewhere {
+        return Ok(saved);
+    }
+
+    let token = renew_token(secrets, default_resource(secrets), expired).await?;
+    export_token(secrets, &token);
+    Ok(token)
+}
+
+/// Token do perfil salvo pela execução anterior. No cofre, um arquivo de token de versões anteriores é migrado
+/// e apagado.
+pub fn read_saved_token(secrets: &Secrets) -> Option<TokenResponse> {
+
+    if secrets.token_storage == TokenStorage::File {
+        return read_token_file(secrets);
+    }
+
+    let account = keyring_account(secrets.profile.as_deref());
+    if let Some(content) = keystore::load(&account) {
+        return serde_json::from_str(&content).ok();
+    }
+
+    let token = read_token_file(secrets)?;
+    if keystore::save(&account, &serde_json::to_string(&token).unwrap()).is_ok() {
+        let filename = token_filename(secrets.profile.as_deref());
+        let _ = fs::remove_file(&filename);
+        println!("Token migrado do arquivo {} para o cofre de credenciais do sistema.", filename);
+    }
+
+    Some(token)
+}
+
+/// Guarda o token do perfil conforme o `token_storage` dele.
+pub fn export_token(secrets: &Secrets, token: &TokenResponse) {
+    let content = serde_json::to_string(&token).unwrap();
+
+    match secrets.token_storage {
+        TokenStorage::File => write_token_file(secrets, &content),
+        TokenStorage::Keyring => {
+            if let Err(e) = keystore::save(&keyring_account(secrets.profile.as_deref()), &content) {
+                eprintln!("{}\nO token não será reaproveitado na próxima execução. Para gravá-lo no arquivo {}, use token_storage = \"file\" no secrets.toml.", e, token_filename(secrets.profile.as_deref()));
+            }
+        }
+    }
+}
+
+/// Obtém um token válido: reaproveita o token salvo ou gera um novo.
+pub async fn load_token(secrets: &Secrets) -> Result<TokenResponse, String> {
+
+    // Realiza leitura do token salvo (caso houver)
+    let token_loaded = read_saved_token(secrets);
+
+    // Verifica se o token já perdeu a validade
+    if let Some(token_loaded) = token_loaded.as_ref().filter(|token| validate_token(token)) {
+        return Ok(token_loaded.clone());
+    }
+
+    // Será feito uma tentativa de obtenção de um novo token, pelo refresh_token quando houver.
+    let token = renew_saved_token(secrets, token_loaded.as_ref()).await?;
+    println!("Novo token gerado !");
+
+    Ok(token)
+}
@@ -0,0 +1,129 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Prefixo do conteúdo cifrado; arquivos sem ele são do formato antigo, em texto puro.
+const PREFIX: &str = "enc:v1:";
+
+/// Indica se o conteúdo do arquivo de token já está cifrado.
+pub fn is_encrypted(content: &str) -> bool {
+    content.starts_with(PREFIX)
+}
+
+/// Cifra o token para gravação: com DPAPI no Windows e, nos demais sistemas, com uma chave derivada do
+/// identificador da máquina. Uma cópia do arquivo não pode ser usada em outra máquina.
+pub fn encrypt(plain: &str) -> Result<String, String> {
+    Ok(format!("{}{}", PREFIX, STANDARD.encode(platform::protect(plain.as_bytes())?)))
+}
+
+pub fn decrypt(content: &str) -> Result<String, String> {
+
+    let data = STANDARD
+        .decode(content.trim().trim_start_matches(PREFIX))
+        .map_err(|e| format!("Arquivo de token corrompido.\nErro: {}", e))?;
+
+    String::from_utf8(platform::unprotect(&data)?).map_err(|e| e.to_string())
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::{io, ptr, slice};
+    use windows_sys::Win32::Foundation::LocalFree;
+    use windows_sys::Win32::Security::Cryptography::{CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB};
+
+    fn blob(data: &[u8]) -> CRYPT_INTEGER_BLOB {
+        CRYPT_INTEGER_BLOB { cbData: data.len() as u32, pbData: data.as_ptr() as *mut u8 }
+    }
+
+    /// Copia o resultado alocado pelo DPAPI e libera a memória.
+    fn take(output: CRYPT_INTEGER_BLOB) -> Vec<u8> {
+        unsafe {
+            let data = slice::from_raw_parts(output.pbData, output.cbData as usize).to_vec();
+            LocalFree(output.pbData as _);
+            data
+        }
+    }
+
+    /// DPAPI: apenas o mesmo usuário, na mesma máquina, consegue decifrar.
+    pub fn protect(data: &[u8]) -> Result<Vec<u8>, String> {
+
+        let input = blob(data);
+        let mut output = CRYPT_INTEGER_BLOB { cbData: 0, pbData: ptr::null_mut() };
+
+        let ok = unsafe { CryptProtectData(&input, ptr::null(), ptr::null(), ptr::null(), ptr::null(), CRYPTPROTECT_UI_FORBIDDEN, &mut output) };
+        if ok == 0 {
+            return Err(format!("Falha ao cifrar token com DPAPI.\nErro: {}", io::Error::last_os_error()));
+        }
+
+        Ok(take(output))
+    }
+
+    pub fn unprotect(data: &[u8]) -> Result<Vec<u8>, String> {
+
+        let input = blob(data);
+        let mut output = CRYPT_INTEGER_BLOB { cbData: 0, pbData: ptr::null_mut() };
+
+        let ok = unsafe { CryptUnprotectData(&input, ptr::null_mut(), ptr::null(), ptr::null(), ptr::null(), CRYPTPROTECT_UI_FORBIDDEN, &mut output) };
+        if ok == 0 {
+            return Err(format!("Falha ao decifrar token com DPAPI (arquivo de outro usuário ou máquina?).\nErro: {}", io::Error::last_os_error()));
+        }
+
+        Ok(take(output))
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use std::{fs, process::Command};
+    use aes_gcm::{aead::{Aead, AeadCore, KeyInit, OsRng}, Aes256Gcm, Nonce};
+    use sha2::{Digest, Sha256};
+
+    /// Tamanho do nonce do AES-GCM, gravado antes do conteúdo cifrado.
+    const NONCE_SIZE: usize = 12;
+
+    /// Identificador estável da máquina: `machine-id` no Linux e `IOPlatformUUID` no macOS. Imagens musl (ex.: Alpine)
+    /// não têm `machine-id`; nelas vale o `boot_id`, e o token volta a ser gerado após reiniciar o host.
+    fn machine_id() -> Result<String, String> {
+
+        for path in ["/etc/machine-id", "/var/lib/dbus/machine-id", "/proc/sys/kernel/random/boot_id"] {
+            if let Some(id) = fs::read_to_string(path).ok().map(|id| id.trim().to_string()).filter(|id| !id.is_empty()) {
+                return Ok(id);
+            }
+        }
+
+        Command::new("ioreg")
+            .args(["-rd1", "-c", "IOPlatformExpertDevice"])
+            .output()
+            .ok()
+            .and_then(|output| {
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .find(|line| line.contains("IOPlatformUUID"))
+                    .and_then(|line| line.split('"').nth(3).map(str::to_string))
+            })
+            .ok_or_else(|| "Não foi possível obter o identificador da máquina para cifrar o token.".to_string())
+    }
+
+    fn cipher() -> Result<Aes256Gcm, String> {
+        let key = Sha256::digest(format!("power_bi_updater:{}", machine_id()?).as_bytes());
+        Aes256Gcm::new_from_slice(&key).map_err(|e| e.to_string())
+    }
+
+    pub fn protect(data: &[u8]) -> Result<Vec<u8>, String> {
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let encrypted = cipher()?.encrypt(&nonce, data).map_err(|e| format!("Falha ao cifrar token.\nErro: {}", e))?;
+
+        Ok([nonce.as_slice(), &encrypted].concat())
+    }
+
+    pub fn unprotect(data: &[u8]) -> Result<Vec<u8>, String> {
+
+        if data.len() < NONCE_SIZE {
+            return Err("Arquivo de token corrompido.".to_string());
+        }
+
+        let (nonce, encrypted) = data.split_at(NONCE_SIZE);
+        cipher()?
+            .decrypt(Nonce::from_slice(nonce), encrypted)
+            .map_err(|_| "Falha ao decifrar token (arquivo copiado de outra máquina?).".to_string())
+    }
+}
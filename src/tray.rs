@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use crate::monitor::WaitOptions;
+use crate::session::Session;
+
+/// Executa o ícone da bandeja até o usuário escolher "Sair". Bloqueia a thread atual.
+#[cfg(windows)]
+pub fn run(session: Arc<Session>, dashboard_url: String, wait: Option<WaitOptions>) -> Result<(), String> {
+    platform::run(session, dashboard_url, wait)
+}
+
+#[cfg(not(windows))]
+pub fn run(_session: Arc<Session>, _dashboard_url: String, _wait: Option<WaitOptions>) -> Result<(), String> {
+    Err("O modo bandeja está disponível apenas no Windows.".to_string())
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::{sync::Arc, thread, time::Duration};
+    use notify_rust::Notification;
+    use tokio::sync::broadcast::error::TryRecvError;
+    use tray_icon::{Icon, TrayIconBuilder, menu::{Menu, MenuEvent, MenuItem}};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{DispatchMessageW, PeekMessageW, TranslateMessage, MSG, PM_REMOVE};
+
+    use crate::history::RefreshRecord;
+    use crate::monitor::WaitOptions;
+    use crate::session::Session;
+
+    const MENU_REFRESH_ALL: &str = "refresh_all";
+    const MENU_REFRESH_FAVORITES: &str = "refresh_favorites";
+    const MENU_OPEN_DASHBOARD: &str = "open_dashboard";
+    const MENU_QUIT: &str = "quit";
+
+    /// Status exibido pela cor do ícone.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum TrayStatus {
+        Idle,
+        Running,
+        Ok,
+        Failed,
+    }
+
+    impl TrayStatus {
+
+        fn color(&self) -> [u8; 3] {
+            match self {
+                TrayStatus::Idle => [128, 128, 128],
+                TrayStatus::Running => [230, 180, 0],
+                TrayStatus::Ok => [0, 170, 60],
+                TrayStatus::Failed => [210, 30, 30],
+            }
+        }
+
+        fn tooltip(&self) -> &'static str {
+            match self {
+                TrayStatus::Idle => "PowerBi Updater - sem execuções",
+                TrayStatus::Running => "PowerBi Updater - atualizando",
+                TrayStatus::Ok => "PowerBi Updater - última execução sem falhas",
+                TrayStatus::Failed => "PowerBi Updater - última execução com falhas",
+            }
+        }
+
+        /// Ícone 16x16 com um círculo na cor do status.
+        fn icon(&self) -> Icon {
+
+            const SIZE: u32 = 16;
+            let [r, g, b] = self.color();
+            let mut rgba = Vec::with_capacity((SIZE * SIZE * 4) as usize);
+
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    let (dx, dy) = (x as f32 - 7.5, y as f32 - 7.5);
+                    let alpha = if dx * dx + dy * dy <= 56.0 { 255 } else { 0 };
+                    rgba.extend_from_slice(&[r, g, b, alpha]);
+                }
+            }
+
+            Icon::from_rgba(rgba, SIZE, SIZE).unwrap()
+        }
+    }
+
+    fn record_status(record: &RefreshRecord) -> TrayStatus {
+        if record.outcome().is_failure(false) { TrayStatus::Failed } else { TrayStatus::Ok }
+    }
+
+    fn toast(message: &str) {
+        if let Err(e) = Notification::new().summary("PowerBi Updater").body(message).show() {
+            eprintln!("Falha ao exibir notificação.\nErro: {}", e);
+        }
+    }
+
+    pub fn run(session: Arc<Session>, dashboard_url: String, wait: Option<WaitOptions>) -> Result<(), String> {
+
+        let menu = Menu::new();
+        menu.append_items(&[
+            &MenuItem::with_id(MENU_REFRESH_ALL, "Atualizar todas", true, None),
+            &MenuItem::with_id(MENU_REFRESH_FAVORITES, "Atualizar favoritas", true, None),
+            &MenuItem::with_id(MENU_OPEN_DASHBOARD, "Abrir painel", true, None),
+            &MenuItem::with_id(MENU_QUIT, "Sair", true, None),
+        ])
+        .map_err(|e| e.to_string())?;
+
+        let mut status = TrayStatus::Idle;
+
+        let tray = TrayIconBuilder::new()
+            .with_menu(Box::new(menu))
+            .with_tooltip(status.tooltip())
+            .with_icon(status.icon())
+            .build()
+            .map_err(|e| format!("Falha ao criar ícone da bandeja.\nErro: {}", e))?;
+
+        let runtime = tokio::runtime::Handle::current();
+        let mut events = session.events.subscribe();
+
+        loop {
+            // O ícone e o menu dependem das mensagens do Windows entregues a esta thread.
+            unsafe {
+                let mut message: MSG = std::mem::zeroed();
+                while PeekMessageW(&mut message, std::ptr::null_mut(), 0, 0, PM_REMOVE) != 0 {
+                    TranslateMessage(&message);
+                    DispatchMessageW(&message);
+                }
+            }
+
+            let previous = status;
+
+            while let Ok(event) = MenuEvent::receiver().try_recv() {
+                let targets = match event.id.as_ref() {
+                    MENU_REFRESH_ALL => session.all_targets(),
+                    MENU_REFRESH_FAVORITES => session.favorite_targets(),
+                    MENU_OPEN_DASHBOARD => {
+                        if open::that(&dashboard_url).is_err() {
+                            toast("Falha ao abrir o painel.");
+                        }
+                        continue;
+                    }
+                    MENU_QUIT => return Ok(()),
+                    _ => continue,
+                };
+
+                status = TrayStatus::Running;
+
+                let session = session.clone();
+                runtime.spawn(async move {
+                    if let Err(e) = session.run(targets, wait).await {
+                        toast(&format!("Falha ao executar atualizações.\n{}", e));
+                    }
+                });
+            }
+
+            // Uma falha mantém o ícone vermelho até a próxima execução disparada pela bandeja.
+            loop {
+                match events.try_recv() {
+                    Ok(record) => {
+                        let record_status = record_status(&record);
+
+                        if record_status == TrayStatus::Failed {
+                            toast(&format!("Empresa {} - {}: falha na atualização.", record.company, record.dataset));
+                        }
+
+                        if status != TrayStatus::Failed {
+                            status = record_status;
+                        }
+                    }
+                    Err(TryRecvError::Lagged(_)) => continue,
+                    Err(_) => break,
+                }
+            }
+
+            if status != previous {
+                let _ = tray.set_icon(Some(status.icon()));
+                let _ = tray.set_tooltip(Some(status.tooltip()));
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+use colored::Colorize;
+use dialoguer::{Select, theme::ColorfulTheme};
+use serde::Deserialize;
+
+use crate::config::{GrantType, Secrets};
+use crate::token::{acquire_new_token, export_token, TokenResponse};
+use crate::ui::is_interactive;
+
+/// Erro devolvido pelo Azure AD no endpoint de token.
+#[derive(Debug, Default, Deserialize)]
+struct AadError {
+    #[serde(default)]
+    error: String,
+    #[serde(default)]
+    error_description: String,
+    #[serde(default)]
+    error_codes: Vec<u64>,
+    #[serde(default)]
+    timestamp: Option<String>,
+    #[serde(default)]
+    trace_id: Option<String>,
+    #[serde(default)]
+    correlation_id: Option<String>,
+}
+
+/// Exibe o erro do Azure AD campo a campo; respostas fora do formato são exibidas como vieram.
+fn print_error(response: &str) {
+
+    let Ok(error) = serde_json::from_str::<AadError>(response) else {
+        eprintln!("{}", response.red());
+        return;
+    };
+
+    eprintln!("{} {}", "Erro:".red(), error.error);
+    if !error.error_codes.is_empty() {
+        // Os códigos AADSTS podem ser pesquisados em https://login.microsoftonline.com/error.
+        let codes: Vec<String> = error.error_codes.iter().map(|code| format!("AADSTS{}", code)).collect();
+        eprintln!("Códigos: {}", codes.join(", "));
+    }
+    for line in error.error_description.lines().filter(|line| !line.trim().is_empty()) {
+        eprintln!("\t{}", line.trim());
+    }
+    for (label, value) in [("Horário", &error.timestamp), ("Trace ID", &error.trace_id), ("Correlation ID", &error.correlation_id)] {
+        if let Some(value) = value {
+            eprintln!("{}: {}", label, value);
+        }
+    }
+}
+
+/// Testa o acesso ao endpoint de descoberta do tenant (DNS, proxy, TLS).
+async fn test_authority(secrets: &Secrets) {
+
+    let tenant = secrets.tenant.as_deref().unwrap_or("common");
+    let url = format!("{}/{}/v2.0/.well-known/openid-configuration", secrets.authority(), tenant);
+    println!("Consultando {}...", url);
+
+    let started = Instant::now();
+    let client = reqwest::Client::builder().timeout(Duration::from_secs(15)).build().unwrap_or_default();
+
+    match client.get(&url).send().await {
+        Ok(res) if res.status().is_success() => {
+            println!("{}", format!("Autoridade acessível ({} ms).", started.elapsed().as_millis()).green());
+        }
+        Ok(res) => {
+            // A autoridade respondeu: a rede está ok, mas o tenant pode estar errado.
+            println!("{}", format!("Autoridade respondeu {} em {} ms; confira o tenant informado.", res.status(), started.elapsed().as_millis()).yellow());
+            print_error(&res.text().await.unwrap_or_default());
+        }
+        Err(e) if e.is_timeout() => eprintln!("{}", "Sem resposta da autoridade em 15 segundos; verifique proxy e firewall.".red()),
+        Err(e) => eprintln!("{}", format!("Falha de conexão com a autoridade; verifique DNS, proxy e certificados.\nErro: {}", e).red()),
+    }
+}
+
+/// Oferece caminhos para recuperar a autenticação após uma falha ao gerar o token: tentar de novo com o erro
+/// detalhado, entrar com código de dispositivo ou testar a conexão com a autoridade. Sem terminal, apenas
+/// exibe o erro. Devolve o token obtido (já gravado) ou `None` quando o usuário desiste.
+pub async fn troubleshoot(secrets: &Secrets, error: &str) -> Option<TokenResponse> {
+
+    eprintln!("{}", "Erro ao gerar novo token.".red());
+    print_error(error);
+
+    if !is_interactive() {
+        eprintln!("Considere validar o arquivo de segredos.");
+        return None;
+    }
+
+    let options = ["Tentar novamente", "Entrar com código de dispositivo", "Testar conexão com a autoridade", "Sair"];
+
+    loop {
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Como deseja prosseguir?")
+            .default(0)
+            .items(&options)
+            .interact_opt()
+            .ok()
+            .flatten();
+
+        let result = match selection {
+            Some(0) => acquire_new_token(secrets).await,
+            Some(1) => {
+                let device = Secrets { grant_type: GrantType::DeviceCode, ..secrets.clone() };
+                acquire_new_token(&device).await
+            }
+            Some(2) => {
+                test_authority(secrets).await;
+                continue;
+            }
+            _ => return None,
+        };
+
+        match result {
+            Ok(token) => {
+                println!("Novo token gerado !");
+                export_token(secrets, &token);
+                return Some(token);
+            }
+            Err(e) => print_error(&e),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::{env, io::{self, IsTerminal}, process::{exit, Command}, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, mpsc}, thread, time::Duration};
+use colored::Colorize;
+use figlet_rs::FIGfont;
+
+const FONT: &str = include_str!("doom.flf");
+
+/// Execução sem banner nem "Pressione ENTER" (`--quiet`).
+static QUIET: AtomicBool = AtomicBool::new(false);
+/// Segundos até o "Pressione ENTER" continuar sozinho; 0 aguarda indefinidamente.
+static PAUSE_TIMEOUT_SECONDS: AtomicU64 = AtomicU64::new(PAUSE_TIMEOUT_UNSET);
+const PAUSE_TIMEOUT_UNSET: u64 = u64::MAX;
+
+pub fn set_quiet() {
+    QUIET.store(true, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+pub fn set_pause_timeout(seconds: u64) {
+    PAUSE_TIMEOUT_SECONDS.store(seconds, Ordering::Relaxed);
+}
+
+/// Prazo do `dataset.json`, que não substitui o informado em `--pause-timeout`.
+pub fn set_pause_timeout_default(seconds: u64) {
+    let _ = PAUSE_TIMEOUT_SECONDS.compare_exchange(PAUSE_TIMEOUT_UNSET, seconds, Ordering::Relaxed, Ordering::Relaxed);
+}
+
+/// Aguarda o ENTER para que a janela aberta pelo duplo clique não feche antes da leitura. Em execuções agendadas ou
+/// redirecionadas e com `--quiet` não há quem pressione a tecla, então não aguarda; com `pause_timeout_seconds`, continua
+/// sozinho após o prazo.
+pub fn pause() {
+
+    if is_quiet() || !is_interactive() {
+        return;
+    }
+
+    let timeout = PAUSE_TIMEOUT_SECONDS.load(Ordering::Relaxed);
+
+    if timeout == 0 || timeout == PAUSE_TIMEOUT_UNSET {
+        println!("{}", "\nPressione ENTER para finalizar\n".yellow());
+        let mut buffer = String::new();
+        io::stdin().read_line(&mut buffer).expect("Falha ao ler entrada do usuário.");
+        return;
+    }
+
+    println!("{}", format!("\nPressione ENTER para finalizar (continua em {} segundos)\n", timeout).yellow());
+
+    // A leitura fica em outra thread; ao fim do prazo ela é abandonada e termina com o processo.
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = String::new();
+        let _ = io::stdin().read_line(&mut buffer);
+        let _ = sender.send(());
+    });
+
+    let _ = receiver.recv_timeout(Duration::from_secs(timeout));
+}
+
+/// Indica se há um terminal para exibir menus e perguntas.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// Com a saída redirecionada (agendador, pipe, arquivo de log), desativa as cores.
+pub fn configure_output() {
+    if !io::stdout().is_terminal() {
+        colored::control::set_override(false);
+    }
+}
+
+/// Encerra com uma mensagem instrutiva quando a ação depende de um terminal interativo.
+pub fn require_interactive(action: &str) {
+    if !is_interactive() {
+        eprintln!("{} requer um terminal interativo. Em execuções agendadas ou redirecionadas, use os subcomandos (ex.: `refresh`, `refresh --company 12`).", action);
+        exit(2);
+    }
+}
+
+pub fn welcome_message() {
+
+    // O banner só faz sentido no terminal.
+    if is_quiet() || !io::stdout().is_terminal() {
+        return;
+    }
+
+    let standard_font = FIGfont::from_content(FONT).unwrap();
+    let figure = standard_font.convert("PowerBI    Updater");
+    println!("{}", figure.unwrap());
+}
+
+/// Destaca um alerta operacional na saída de erro.
+pub fn alert(message: &str) {
+    eprintln!("{} {}", " ALERTA ".on_red().bold(), message.red());
+}
+
+/// Há interface gráfica para abrir arquivos no aplicativo padrão (no Linux, uma sessão X11 ou Wayland).
+fn has_desktop() -> bool {
+    !cfg!(target_os = "linux") || env::var_os("DISPLAY").is_some() || env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+/// Abre o arquivo para edição: no aplicativo padrão quando há interface gráfica; senão, no editor de `VISUAL` ou
+/// `EDITOR`, no próprio terminal. Sem nenhum dos dois, o erro traz o caminho completo e como editar.
+pub fn edit_file(path: &str) -> Result<(), String> {
+
+    if has_desktop() && open::that(path).is_ok() {
+        return Ok(());
+    }
+
+    let editor = env::var("VISUAL").ok().or_else(|| env::var("EDITOR").ok()).filter(|editor| !editor.trim().is_empty());
+
+    if let Some(editor) = editor {
+        // O editor pode vir com argumentos (ex.: `code --wait`).
+        let mut parts = editor.split_whitespace();
+        let program = parts.next().unwrap_or_default();
+
+        let status = Command::new(program)
+            .args(parts)
+            .arg(path)
+            .status()
+            .map_err(|e| format!("Falha ao executar o editor \"{}\".\nErro: {}", editor, e))?;
+
+        return match status.success() {
+            true => Ok(()),
+            false => Err(format!("O editor \"{}\" terminou com erro ({}).", editor, status)),
+        };
+    }
+
+    let full_path = env::current_dir().map(|dir| dir.join(path).display().to_string()).unwrap_or_else(|_| path.to_string());
+    Err(format!(
+        "Nenhuma interface gráfica ou editor disponível para abrir o arquivo.\nEdite {} em outro terminal ou defina a variável EDITOR (ex.: EDITOR=nano).",
+        full_path
+    ))
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, env, time::Duration};
+use serde_json::{json, Value};
+
+/// Chaves do `secrets.toml` que apontam o segredo no Vault; não fazem parte das credenciais em si.
+pub const VAULT_KEYS: [&str; 6] = ["vault_addr", "vault_path", "vault_token", "vault_role_id", "vault_secret_id", "vault_namespace"];
+
+/// Cabeçalho do namespace no Vault Enterprise.
+const NAMESPACE_HEADER: &str = "X-Vault-Namespace";
+
+/// Conexão com o Vault descrita no `secrets.toml`. O endereço e o token também podem vir de `VAULT_ADDR` e
+/// `VAULT_TOKEN`, as variáveis da CLI do Vault.
+struct VaultSource {
+    addr: String,
+    path: String,
+    token: Option<String>,
+    role_id: Option<String>,
+    secret_id: Option<String>,
+    namespace: Option<String>,
+}
+
+impl VaultSource {
+
+    fn from_values(values: &mut HashMap<String, String>) -> Result<Option<Self>, String> {
+
+        let Some(path) = values.remove("vault_path") else {
+            if let Some(key) = VAULT_KEYS.iter().find(|key| values.contains_key(**key)) {
+                return Err(format!("A chave {} exige vault_path.", key));
+            }
+            return Ok(None);
+        };
+
+        let addr = values
+            .remove("vault_addr")
+            .or_else(|| env::var("VAULT_ADDR").ok())
+            .ok_or("Informe vault_addr (ou VAULT_ADDR) para ler os segredos do Vault.")?;
+
+        Ok(Some(VaultSource {
+            addr: addr.trim_end_matches('/').to_string(),
+            path: path.trim_matches('/').to_string(),
+            token: values.remove("vault_token").or_else(|| env::var("VAULT_TOKEN").ok()),
+            role_id: values.remove("vault_role_id"),
+            secret_id: values.remove("vault_secret_id"),
+            namespace: values.remove("vault_namespace"),
+        }))
+    }
+
+    fn request(&self, request: reqwest::blocking::RequestBuilder) -> reqwest::blocking::RequestBuilder {
+        match &self.namespace {
+            Some(namespace) => request.header(NAMESPACE_HEADER, namespace),
+            None => request,
+        }
+    }
+
+    /// Token do Vault: o informado ou, com `vault_role_id` e `vault_secret_id`, o obtido pelo login AppRole.
+    fn token(&self, client: &reqwest::blocking::Client) -> Result<String, String> {
+
+        if let Some(token) = &self.token {
+            return Ok(token.clone());
+        }
+
+        let (Some(role_id), Some(secret_id)) = (&self.role_id, &self.secret_id) else {
+            return Err("Informe vault_token (ou VAULT_TOKEN) ou vault_role_id e vault_secret_id para acessar o Vault.".to_string());
+        };
+
+        let res = self
+            .request(client.post(format!("{}/v1/auth/approle/login", self.addr)))
+            .json(&json!({ "role_id": role_id, "secret_id": secret_id }))
+            .send()
+            .map_err(|e| format!("Falha ao autenticar no Vault.\nErro: {}", e))?;
+
+        if !res.status().is_success() {
+            return Err(format!("O Vault recusou o login AppRole ({}).", res.status()));
+        }
+
+        let body: Value = res.json().map_err(|e| format!("Resposta inválida do Vault.\nErro: {}", e))?;
+        body["auth"]["client_token"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| "O Vault não devolveu o token do login AppRole.".to_string())
+    }
+
+    /// Chaves do segredo. No KV v2 os valores ficam em `data.data`; no KV v1, direto em `data`.
+    fn read(&self) -> Result<HashMap<String, String>, String> {
+
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let token = self.token(&client)?;
+
+        let res = self
+            .request(client.get(format!("{}/v1/{}", self.addr, self.path)))
+            .header("X-Vault-Token", token)
+            .send()
+            .map_err(|e| format!("Falha ao ler {} no Vault.\nErro: {}", self.path, e))?;
+
+        if !res.status().is_success() {
+            return Err(format!("O Vault respondeu com status {} ao ler {}.", res.status(), self.path));
+        }
+
+        let body: Value = res.json().map_err(|e| format!("Resposta inválida do Vault.\nErro: {}", e))?;
+        let data = match &body["data"]["data"] {
+            Value::Object(data) => data,
+            _ => body["data"].as_object().ok_or_else(|| format!("O caminho {} do Vault não contém um segredo.", self.path))?,
+        };
+
+        Ok(data
+            .iter()
+            .map(|(key, value)| (key.clone(), value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string())))
+            .collect())
+    }
+}
+
+/// Completa os segredos com as chaves guardadas no caminho `vault_path` do Vault. Os valores do Vault prevalecem sobre
+/// os do arquivo; chaves do segredo que não são reconhecidas (ex.: de outros sistemas) são ignoradas.
+pub fn resolve_vault(values: &mut HashMap<String, String>, known: &[&str]) -> Result<(), String> {
+
+    let Some(source) = VaultSource::from_values(values)? else {
+        return Ok(());
+    };
+
+    // O cliente bloqueante não pode rodar diretamente nas tarefas do tokio; `block_in_place` libera a thread.
+    let secret = tokio::task::block_in_place(|| source.read())?;
+
+    values.extend(secret.into_iter().filter(|(key, _)| known.contains(&key.as_str())));
+    Ok(())
+}
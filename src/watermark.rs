@@ -0,0 +1,82 @@
+use std::time::Duration;
+use serde_json::Value;
+use tokio::process::Command;
+
+use crate::config::Watermark;
+
+/// Tempo máximo de espera pela consulta da marca d'água.
+const WATERMARK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Comando executado pelo interpretador do sistema, para aceitar pipes e redirecionamentos (ex.: `sqlcmd ... | ...`).
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
+
+async fn run_command(command: &str) -> Result<String, String> {
+
+    let output = tokio::time::timeout(WATERMARK_TIMEOUT, shell(command).output())
+        .await
+        .map_err(|_| format!("O comando não terminou em {} segundos.", WATERMARK_TIMEOUT.as_secs()))?
+        .map_err(|e| format!("Falha ao executar o comando.\nErro: {}", e))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("O comando terminou com {}.\n{}", output.status, error.trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+async fn fetch_url(url: &str) -> Result<String, String> {
+
+    let res = reqwest::Client::new()
+        .get(url)
+        .timeout(WATERMARK_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("Falha ao consultar {}.\nErro: {}", url, e))?;
+
+    if !res.status().is_success() {
+        return Err(format!("{} respondeu com status {}.", url, res.status()));
+    }
+
+    res.text().await.map_err(|e| e.to_string())
+}
+
+/// Valor atual da marca d'água: a saída do comando ou a resposta da URL, sem espaços nas pontas. Com `pointer`, a
+/// saída é lida como JSON e a marca é o campo indicado.
+pub async fn read(source: &Watermark) -> Result<String, String> {
+
+    let output = match (&source.command, &source.url) {
+        (Some(command), _) => run_command(command).await?,
+        (None, Some(url)) => fetch_url(url).await?,
+        (None, None) => return Err("Informe `command` ou `url` na marca d'água.".to_string()),
+    };
+
+    let value = match &source.pointer {
+        Some(pointer) => {
+            let document: Value = serde_json::from_str(&output).map_err(|e| format!("A marca d'água não é um JSON.\nErro: {}", e))?;
+            match document.pointer(pointer) {
+                Some(Value::String(value)) => value.clone(),
+                Some(Value::Null) | None => return Err(format!("O campo {} não existe na marca d'água.", pointer)),
+                Some(value) => value.to_string(),
+            }
+        }
+        None => output,
+    };
+
+    let value = value.trim();
+    if value.is_empty() {
+        return Err("A marca d'água veio vazia.".to_string());
+    }
+
+    Ok(value.to_string())
+}